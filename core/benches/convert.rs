@@ -0,0 +1,87 @@
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use hm_hub_core::consts::{DISPLAY_HEIGHT, DISPLAY_WIDTH, FRAME_PIXEL_SIZE};
+use hm_hub_core::image::{load_image, resize_image, rgba_to_rgb565, LoadOptions};
+use hm_hub_core::{build_flash_buffer, Album};
+
+const FLASH_SIZE: u32 = 16 * 1024 * 1024;
+
+// A smooth gradient with some detail, so resizing does real work.
+fn photo(width: u32, height: u32) -> image::DynamicImage {
+	image::DynamicImage::from(image::RgbImage::from_fn(width, height, |x, y| {
+		image::Rgb([
+			(x * 255 / width) as u8,
+			(y * 255 / height) as u8,
+			((x ^ y) & 0xFF) as u8,
+		])
+	}))
+}
+
+fn conversion(c: &mut Criterion) {
+	let rgba = photo(DISPLAY_WIDTH, DISPLAY_HEIGHT).to_rgba8();
+	c.bench_function("rgba_to_rgb565 320x170", |b| {
+		b.iter(|| rgba_to_rgb565(black_box(rgba.as_raw()), DISPLAY_WIDTH, DISPLAY_HEIGHT))
+	});
+
+	let large = photo(1920, 1080);
+	c.bench_function("resize_image 1920x1080 fit", |b| {
+		b.iter(|| resize_image(black_box(&large), DISPLAY_WIDTH, DISPLAY_HEIGHT, false))
+	});
+	c.bench_function("resize_image 1920x1080 crop", |b| {
+		b.iter(|| resize_image(black_box(&large), DISPLAY_WIDTH, DISPLAY_HEIGHT, true))
+	});
+	let exact = photo(DISPLAY_WIDTH, DISPLAY_HEIGHT);
+	c.bench_function("resize_image 320x170 fit", |b| {
+		b.iter(|| resize_image(black_box(&exact), DISPLAY_WIDTH, DISPLAY_HEIGHT, false))
+	});
+}
+
+fn flash(c: &mut Criterion) {
+	let album = Album {
+		frames: (0..150)
+			.map(|f| (0..FRAME_PIXEL_SIZE).map(|i| (i + f) as u8).collect())
+			.collect(),
+		delay_ms: 40,
+	};
+	let albums = [album];
+	c.bench_function("build_flash_buffer 150 frames", |b| {
+		b.iter(|| build_flash_buffer(black_box(&albums), FLASH_SIZE).unwrap())
+	});
+}
+
+// The whole path for an animated GIF that isn't at display size, where every frame
+// is composited, resized and converted.
+fn gif(c: &mut Criterion) {
+	let (width, height) = (400u16, 240u16);
+	let path = std::env::temp_dir().join(format!("hm-hub-bench-{}.gif", std::process::id()));
+	{
+		let mut encoder =
+			gif::Encoder::new(std::fs::File::create(&path).unwrap(), width, height, &[]).unwrap();
+		for f in 0..200u32 {
+			let mut rgba = photo(width as u32, height as u32).to_rgba8();
+			for p in rgba.pixels_mut() {
+				p[0] = p[0].wrapping_add(f as u8);
+			}
+			let mut frame = gif::Frame::from_rgba_speed(width, height, &mut rgba, 30);
+			frame.delay = 4;
+			encoder.write_frame(&frame).unwrap();
+		}
+	}
+	let opts = LoadOptions {
+		crop: false,
+		temperature: None,
+		similarity_threshold: None,
+		raw_size: None,
+	};
+	let mut group = c.benchmark_group("load_image");
+	group.sample_size(10);
+	group.bench_function("gif 400x240 200 frames", |b| {
+		b.iter(|| load_image(black_box(&path), &opts).unwrap())
+	});
+	group.finish();
+	let _ = std::fs::remove_file(&path);
+}
+
+criterion_group!(benches, conversion, flash, gif);
+criterion_main!(benches);
@@ -0,0 +1,62 @@
+use anyhow::{bail, Result};
+
+use crate::types::DeviceConfig;
+
+const BACKUP_MAGIC: &[u8; 5] = b"HMHUB";
+const BACKUP_VERSION: u8 = 1;
+
+pub struct Backup {
+	pub config: DeviceConfig,
+	pub flash: Vec<u8>,
+}
+
+impl Backup {
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let config_bytes = self.config.to_bytes();
+		let mut backup = Vec::with_capacity(self.flash.len() + config_bytes.len() + 18);
+		backup.extend_from_slice(BACKUP_MAGIC);
+		backup.push(BACKUP_VERSION);
+		backup.extend_from_slice(&(config_bytes.len() as u32).to_le_bytes());
+		backup.extend_from_slice(&config_bytes);
+		backup.extend_from_slice(&(self.flash.len() as u32).to_le_bytes());
+		backup.extend_from_slice(&self.flash);
+		let checksum = crc32fast::hash(&backup);
+		backup.extend_from_slice(&checksum.to_le_bytes());
+		backup
+	}
+
+	pub fn from_bytes(data: &[u8]) -> Result<Self> {
+		if data.len() < 14 || &data[..5] != BACKUP_MAGIC {
+			bail!("not a valid hm-hub backup file");
+		}
+		if data[5] != BACKUP_VERSION {
+			bail!("unsupported backup version: {}", data[5]);
+		}
+
+		let stored_crc = u32::from_le_bytes([
+			data[data.len() - 4],
+			data[data.len() - 3],
+			data[data.len() - 2],
+			data[data.len() - 1],
+		]);
+		let computed_crc = crc32fast::hash(&data[..data.len() - 4]);
+		if stored_crc != computed_crc {
+			bail!("backup file is corrupted (CRC mismatch)");
+		}
+
+		let mut pos = 6;
+
+		let config_len =
+			u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]) as usize;
+		pos += 4;
+		let config = DeviceConfig::from_bytes(&data[pos..pos + config_len])?;
+		pos += config_len;
+
+		let flash_len =
+			u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]) as usize;
+		pos += 4;
+		let flash = data[pos..pos + flash_len].to_vec();
+
+		Ok(Backup { config, flash })
+	}
+}
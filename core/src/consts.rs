@@ -14,6 +14,7 @@ pub const CMD_POWER: u8 = 9;
 pub const CMD_LOG: u8 = 10;
 
 pub const FLASH_HEADER_AREA: usize = 8192;
+pub const FLASH_ERASE_BLOCK: usize = 4096;
 pub const FRAME_HEADER_SIZE: usize = 28;
 pub const MAX_FRAME_HEADERS: usize = 292;
 pub const FRAME_MAGIC: u32 = 0xC019_0001;
@@ -0,0 +1,46 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum HubError {
+	#[error("no HM Lab device found (VID:{vid:#06x} PID:{pid:#06x}). Is it plugged in?")]
+	DeviceNotFound { vid: u16, pid: u16 },
+
+	#[error("{0}")]
+	Timeout(String),
+
+	#[error("connection closed by device")]
+	Disconnected,
+
+	#[error("{what} CRC mismatch: expected {expected:#x}, got {actual:#x}")]
+	CrcMismatch {
+		what: &'static str,
+		expected: u32,
+		actual: u32,
+	},
+
+	#[error("{0}")]
+	ProtocolViolation(String),
+
+	// The device answered, but reported that it gave up on a flash transfer.
+	#[error("{0}")]
+	FlashFailed(String),
+
+	#[error("{0}")]
+	CapacityExceeded(String),
+
+	#[error("{0}")]
+	InvalidConfig(String),
+
+	// A cancellation token passed to `DeviceBuilder::cancel` was set.
+	#[error("operation cancelled")]
+	Cancelled,
+
+	#[cfg(feature = "serial")]
+	#[error(transparent)]
+	Serial(#[from] serialport::Error),
+
+	#[error(transparent)]
+	Io(#[from] std::io::Error),
+}
+
+pub type Result<T, E = HubError> = std::result::Result<T, E>;
@@ -0,0 +1,426 @@
+use crate::consts::*;
+use crate::error::{HubError, Result};
+use crate::types::{
+	max_frames, Album, AlbumMeta, FlashMetadata, FrameHeader, METADATA_TRAILER_SIZE,
+};
+
+pub fn build_flash_buffer(albums: &[Album], flash_size: u32) -> Result<Vec<u8>> {
+	let max = max_frames(flash_size);
+
+	if albums.len() > MAX_FRAME_HEADERS {
+		return Err(HubError::CapacityExceeded(format!(
+			"too many albums: {} > {}",
+			albums.len(),
+			MAX_FRAME_HEADERS
+		)));
+	}
+
+	let total_frames: usize = albums.iter().map(|a| a.frames.len()).sum();
+	if total_frames > max {
+		return Err(HubError::CapacityExceeded(format!(
+			"total frames ({total_frames}) exceeds device capacity ({max})"
+		)));
+	}
+
+	let total_pixel_data: usize = albums
+		.iter()
+		.map(|a| a.frames.iter().map(|f| f.len()).sum::<usize>())
+		.sum();
+	let total_size = FLASH_HEADER_AREA + total_pixel_data;
+
+	let mut buffer = vec![0u8; total_size];
+	let mut data_offset = FLASH_HEADER_AREA;
+
+	for (i, album) in albums.iter().enumerate() {
+		let start = data_offset;
+		for frame in &album.frames {
+			buffer[data_offset..data_offset + frame.len()].copy_from_slice(frame);
+			data_offset += frame.len();
+		}
+		let data_length = data_offset - start;
+		let data_crc = crc32fast::hash(&buffer[start..data_offset]);
+
+		let header = FrameHeader {
+			width: DISPLAY_WIDTH as u16,
+			height: DISPLAY_HEIGHT as u16,
+			frame_count: album.frames.len() as u16,
+			delay_ms: album.delay_ms,
+			data_offset: start as u32,
+			data_length: data_length as u32,
+			data_crc32: data_crc,
+		};
+
+		let hdr_start = i * FRAME_HEADER_SIZE;
+		header.write_to(&mut buffer[hdr_start..hdr_start + FRAME_HEADER_SIZE]);
+	}
+
+	// The device erases and requests whole blocks, so pad with erased bytes up to the
+	// next boundary (but never past the end of flash).
+	let aligned = total_size
+		.next_multiple_of(FLASH_ERASE_BLOCK)
+		.min((flash_size as usize).max(total_size));
+	buffer.resize(aligned, 0xFF);
+
+	Ok(buffer)
+}
+
+// Host metadata (layout version, per-album flags, labels, source hashes and the sync
+// hash) lives at the end of the header area, in space frame headers don't use. See
+// types::FlashMetadata for the layout.
+const METADATA_END: usize = FLASH_HEADER_AREA;
+
+// The trailer (and with it the sync hash) sits past the last frame header slot, in
+// bytes no header covers; only the body reaches into unused slots.
+const _: () =
+	assert!(MAX_FRAME_HEADERS * FRAME_HEADER_SIZE + METADATA_TRAILER_SIZE == METADATA_END);
+
+// Before metadata was versioned, sync kept a bare "HMSY" + hash marker where the
+// metadata trailer now sits. Devices synced by older builds still carry it.
+const LEGACY_SYNC_MAGIC: &[u8; 4] = b"HMSY";
+
+pub fn header_slots_used(header: &[u8]) -> usize {
+	(0..MAX_FRAME_HEADERS)
+		.take_while(|i| {
+			header
+				.get(i * FRAME_HEADER_SIZE..(i + 1) * FRAME_HEADER_SIZE)
+				.is_some_and(FrameHeader::is_present)
+		})
+		.count()
+}
+
+// The frame headers in use, in slot order.
+pub fn read_headers(header: &[u8]) -> Result<Vec<FrameHeader>> {
+	(0..header_slots_used(header))
+		.filter_map(|i| FrameHeader::read_from(&header[i * FRAME_HEADER_SIZE..]).transpose())
+		.collect()
+}
+
+// How much of the flash a copy has to carry: the header area plus every album's
+// data, as recorded in the frame headers.
+pub fn used_length(header: &[u8]) -> Result<usize> {
+	let mut end = FLASH_HEADER_AREA;
+	for i in 0..header_slots_used(header) {
+		if let Some(h) = FrameHeader::read_from(&header[i * FRAME_HEADER_SIZE..])? {
+			end = end.max(h.data_offset as usize + h.data_length as usize);
+		}
+	}
+	Ok(end)
+}
+
+pub fn write_metadata(buffer: &mut [u8], meta: &FlashMetadata) -> Result<()> {
+	let body = meta.encode_body()?;
+	// Keep one empty slot after the last header so the firmware's scan stops there,
+	// whatever the metadata bytes happen to look like.
+	let slots = (header_slots_used(buffer) + 1).min(MAX_FRAME_HEADERS);
+	let trailer_start = METADATA_END - METADATA_TRAILER_SIZE;
+	let available = trailer_start.saturating_sub(slots * FRAME_HEADER_SIZE);
+	if body.len() > available {
+		return Err(HubError::CapacityExceeded(format!(
+			"flash metadata needs {} bytes, only {available} are free in the header area",
+			body.len()
+		)));
+	}
+	buffer[trailer_start - body.len()..trailer_start].copy_from_slice(&body);
+	buffer[trailer_start..METADATA_END].copy_from_slice(&meta.trailer(&body));
+	Ok(())
+}
+
+pub fn read_metadata(header: &[u8]) -> Result<Option<FlashMetadata>> {
+	match header.get(..METADATA_END) {
+		Some(area) => FlashMetadata::decode(area),
+		None => Ok(None),
+	}
+}
+
+// Hashes the buffer as built (metadata included) and records the hash in the
+// metadata, so sync can tell when the device is already current. With no labels or
+// source hashes only the trailer is written. Whether the firmware ignores those bytes
+// is unverified.
+pub fn stamp_sync_hash(buffer: &mut [u8]) -> Result<u32> {
+	let mut meta = read_metadata(buffer)?.unwrap_or_default();
+	meta.sync_hash = None;
+	write_metadata(buffer, &meta)?;
+	let hash = crc32fast::hash(buffer);
+	meta.sync_hash = Some(hash);
+	write_metadata(buffer, &meta)?;
+	Ok(hash)
+}
+
+// Unreadable metadata just means the device isn't known to be in sync.
+pub fn read_sync_hash(header: &[u8]) -> Option<u32> {
+	let trailer = header.get(METADATA_END - METADATA_TRAILER_SIZE..METADATA_END)?;
+	if &trailer[..4] == LEGACY_SYNC_MAGIC {
+		return Some(u32::from_le_bytes(trailer[4..8].try_into().unwrap()));
+	}
+	read_metadata(header).ok().flatten()?.sync_hash
+}
+
+pub fn split_into_pages(albums: Vec<Album>, flash_size: u32) -> Result<Vec<Vec<Album>>> {
+	let capacity = max_frames(flash_size);
+	if capacity == 0 {
+		return Err(HubError::CapacityExceeded(
+			"device has no room for frames".to_string(),
+		));
+	}
+
+	let mut pages: Vec<Vec<Album>> = vec![Vec::new()];
+	let mut used = 0;
+
+	for album in albums {
+		let fits_whole_page = album.frames.len() <= capacity;
+		if fits_whole_page && used + album.frames.len() > capacity {
+			pages.push(Vec::new());
+			used = 0;
+		}
+
+		let delay_ms = album.delay_ms;
+		let mut frames = album.frames.into_iter().peekable();
+		while frames.peek().is_some() {
+			let page = pages.last_mut().unwrap();
+			if used == capacity || page.len() == MAX_FRAME_HEADERS {
+				pages.push(Vec::new());
+				used = 0;
+				continue;
+			}
+			let chunk: Vec<_> = frames.by_ref().take(capacity - used).collect();
+			used += chunk.len();
+			page.push(Album {
+				frames: chunk,
+				delay_ms,
+			});
+		}
+	}
+
+	Ok(pages)
+}
+
+fn header_area(header: &[u8]) -> Result<&[u8]> {
+	header.get(..FLASH_HEADER_AREA).ok_or_else(|| {
+		HubError::ProtocolViolation(format!(
+			"header area is {} bytes, expected {FLASH_HEADER_AREA}",
+			header.len()
+		))
+	})
+}
+
+// Rewrites the header area so the albums play in `order`, which must list every album
+// once. Only the header slots change; each still points at the same data, so the
+// result can be uploaded on its own. Metadata follows its album and the sync hash is
+// dropped.
+pub fn reorder_headers(header: &[u8], order: &[usize]) -> Result<Vec<u8>> {
+	let area = header_area(header)?;
+	let count = header_slots_used(area);
+	let mut seen = vec![false; count];
+	let valid = order.len() == count
+		&& order
+			.iter()
+			.all(|&i| i < count && !std::mem::replace(&mut seen[i], true));
+	if !valid {
+		return Err(HubError::InvalidConfig(format!(
+			"the new order must list each of the {count} albums exactly once"
+		)));
+	}
+
+	let mut buffer = area.to_vec();
+	for (slot, &i) in order.iter().enumerate() {
+		buffer[slot * FRAME_HEADER_SIZE..(slot + 1) * FRAME_HEADER_SIZE]
+			.copy_from_slice(&area[i * FRAME_HEADER_SIZE..(i + 1) * FRAME_HEADER_SIZE]);
+	}
+	// Past the used slots there is only metadata (or a legacy sync marker), which is
+	// rewritten below.
+	buffer[count * FRAME_HEADER_SIZE..].fill(0);
+	if let Some(meta) = read_metadata(area)? {
+		let meta = FlashMetadata {
+			sync_hash: None,
+			albums: order
+				.iter()
+				.map(|&i| meta.albums.get(i).cloned().unwrap_or_default())
+				.collect(),
+		};
+		write_metadata(&mut buffer, &meta)?;
+	}
+	Ok(buffer)
+}
+
+// Rebuilds a flash image holding only the albums at `indices`, in that order, packed
+// from the start of the data area. `flash_data` must reach the end of the last album
+// (see used_length). Host metadata moves with its album; the sync hash is dropped,
+// since the content no longer matches what sync uploaded.
+pub fn select_albums(flash_data: &[u8], indices: &[usize], flash_size: u32) -> Result<Vec<u8>> {
+	let mut albums: Vec<Option<Album>> = parse_albums(flash_data)?.into_iter().map(Some).collect();
+	let count = albums.len();
+	let selected = indices
+		.iter()
+		.map(|&i| {
+			albums.get_mut(i).and_then(Option::take).ok_or_else(|| {
+				HubError::InvalidConfig(format!(
+					"album {i} doesn't exist or is listed twice (the device holds {count})"
+				))
+			})
+		})
+		.collect::<Result<Vec<_>>>()?;
+	let mut buffer = build_flash_buffer(&selected, flash_size)?;
+
+	if let Some(meta) = read_metadata(flash_data)? {
+		let meta = FlashMetadata {
+			sync_hash: None,
+			albums: indices
+				.iter()
+				.map(|&i| meta.albums.get(i).cloned().unwrap_or_default())
+				.collect(),
+		};
+		write_metadata(&mut buffer, &meta)?;
+	}
+	Ok(buffer)
+}
+
+// Adds `albums` after the ones already in `flash_data`, which must reach the end of
+// the last album (see used_length). The existing albums keep their metadata; the sync
+// hash is dropped.
+pub fn append_albums(flash_data: &[u8], albums: Vec<Album>, flash_size: u32) -> Result<Vec<u8>> {
+	let mut all = parse_albums(flash_data)?;
+	let existing = all.len();
+	all.extend(albums);
+	let mut buffer = build_flash_buffer(&all, flash_size)?;
+
+	if let Some(mut meta) = read_metadata(flash_data)? {
+		meta.sync_hash = None;
+		meta.albums.truncate(existing);
+		write_metadata(&mut buffer, &meta)?;
+	}
+	Ok(buffer)
+}
+
+// Returns the header area with album `index` showing each frame for `delay_ms`. Like
+// reorder_headers, the result can be uploaded on its own.
+pub fn set_album_delay(header: &[u8], index: usize, delay_ms: u16) -> Result<Vec<u8>> {
+	let h = album_header(header, index)?;
+	let mut buffer = header_area(header)?.to_vec();
+	FrameHeader { delay_ms, ..h }
+		.write_to(&mut buffer[index * FRAME_HEADER_SIZE..(index + 1) * FRAME_HEADER_SIZE]);
+	match read_metadata(&buffer)? {
+		Some(meta) => write_metadata(
+			&mut buffer,
+			&FlashMetadata {
+				sync_hash: None,
+				..meta
+			},
+		)?,
+		None => buffer[METADATA_END - METADATA_TRAILER_SIZE..METADATA_END].fill(0),
+	}
+	Ok(buffer)
+}
+
+fn album_header(header: &[u8], index: usize) -> Result<FrameHeader> {
+	let headers = read_headers(header)?;
+	let count = headers.len();
+	headers.into_iter().nth(index).ok_or_else(|| {
+		HubError::InvalidConfig(format!("no album {index}; the device holds {count}"))
+	})
+}
+
+// How much of the flash replace_album needs to have read to put an album of `frames`
+// frames in slot `index`: up to the end of that slot when writing in place and the new
+// data fits in it, otherwise everything up to the end of the last album.
+pub fn replace_extent(header: &[u8], index: usize, frames: usize, in_place: bool) -> Result<usize> {
+	let h = album_header(header, index)?;
+	let used = used_length(header)?;
+	let len = frames * FRAME_PIXEL_SIZE;
+	if !in_place || len > h.data_length as usize {
+		return Ok(used);
+	}
+	Ok((h.data_offset as usize + len)
+		.next_multiple_of(FLASH_ERASE_BLOCK)
+		.min(used))
+}
+
+// Puts `album` in slot `index`, packing every album again. With `in_place`, an album
+// that fits in the old one's space is written there instead and the image only runs
+// to the end of that space, rounded up to an erase block. That keeps the albums after
+// it only if the firmware erases no more than it is sent, which is unverified, so
+// callers must opt in. `flash_data` must cover replace_extent. The slot's metadata and
+// the sync hash are cleared.
+pub fn replace_album(
+	flash_data: &[u8],
+	index: usize,
+	album: Album,
+	flash_size: u32,
+	in_place: bool,
+) -> Result<Vec<u8>> {
+	let h = album_header(flash_data, index)?;
+	let len: usize = album.frames.iter().map(Vec::len).sum();
+	let mut buffer = if in_place && len <= h.data_length as usize {
+		let start = h.data_offset as usize;
+		let end = (start + len)
+			.next_multiple_of(FLASH_ERASE_BLOCK)
+			.min((flash_size as usize).max(start + len));
+		let mut buffer = flash_data[..end.min(flash_data.len())].to_vec();
+		buffer.resize(end, 0xFF);
+		let mut at = start;
+		for frame in &album.frames {
+			buffer[at..at + frame.len()].copy_from_slice(frame);
+			at += frame.len();
+		}
+		let header = FrameHeader {
+			frame_count: album.frames.len() as u16,
+			delay_ms: album.delay_ms,
+			data_length: len as u32,
+			data_crc32: crc32fast::hash(&buffer[start..at]),
+			..h
+		};
+		header.write_to(&mut buffer[index * FRAME_HEADER_SIZE..(index + 1) * FRAME_HEADER_SIZE]);
+		// Drops a legacy sync marker; real metadata is rewritten below.
+		buffer[METADATA_END - METADATA_TRAILER_SIZE..METADATA_END].fill(0);
+		buffer
+	} else {
+		let mut albums = parse_albums(flash_data)?;
+		albums[index] = album;
+		build_flash_buffer(&albums, flash_size)?
+	};
+
+	if let Some(mut meta) = read_metadata(flash_data)? {
+		meta.sync_hash = None;
+		if let Some(slot) = meta.albums.get_mut(index) {
+			*slot = AlbumMeta::default();
+		}
+		write_metadata(&mut buffer, &meta)?;
+	}
+	Ok(buffer)
+}
+
+// Reads back the albums written by build_flash_buffer, stopping at the first empty
+// header slot.
+pub fn parse_albums(flash_data: &[u8]) -> Result<Vec<Album>> {
+	let frame_size = (DISPLAY_WIDTH * DISPLAY_HEIGHT * 2) as usize;
+	let mut albums = Vec::new();
+	for i in 0..MAX_FRAME_HEADERS {
+		let offset = i * FRAME_HEADER_SIZE;
+		if offset + FRAME_HEADER_SIZE > flash_data.len().min(FLASH_HEADER_AREA) {
+			break;
+		}
+		let Some(header) = FrameHeader::read_from(&flash_data[offset..])? else {
+			break;
+		};
+		if header.width as u32 != DISPLAY_WIDTH || header.height as u32 != DISPLAY_HEIGHT {
+			return Err(HubError::ProtocolViolation(format!(
+				"album {i} is {}x{}, expected {DISPLAY_WIDTH}x{DISPLAY_HEIGHT}",
+				header.width, header.height
+			)));
+		}
+		let start = header.data_offset as usize;
+		let end = start + header.frame_count as usize * frame_size;
+		if end > flash_data.len() {
+			return Err(HubError::ProtocolViolation(format!(
+				"album {i} extends past the end of flash"
+			)));
+		}
+		albums.push(Album {
+			frames: flash_data[start..end]
+				.chunks_exact(frame_size)
+				.map(<[u8]>::to_vec)
+				.collect(),
+			delay_ms: header.delay_ms,
+		});
+	}
+	Ok(albums)
+}
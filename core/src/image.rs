@@ -0,0 +1,894 @@
+use anyhow::{bail, Result};
+use fast_image_resize::{FilterType, ResizeAlg, ResizeOptions, Resizer};
+use image::GenericImageView;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::consts::{DISPLAY_HEIGHT, DISPLAY_WIDTH, FRAME_PIXEL_SIZE};
+use crate::types::Album;
+
+pub fn rgb565_to_image(data: &[u8], width: u16, height: u16) -> image::RgbaImage {
+	let w = width as u32;
+	let h = height as u32;
+	let mut img = image::RgbaImage::new(w, h);
+
+	for y in 0..h {
+		for x in 0..w {
+			let idx = ((y * w + x) * 2) as usize;
+			if idx + 1 >= data.len() {
+				break;
+			}
+			let hi = data[idx] as u16;
+			let lo = data[idx + 1] as u16;
+			let pixel = (hi << 8) | lo;
+
+			let r = ((pixel >> 11) & 0x1F) as u8;
+			let g = ((pixel >> 5) & 0x3F) as u8;
+			let b = (pixel & 0x1F) as u8;
+
+			let r8 = (r << 3) | (r >> 2);
+			let g8 = (g << 2) | (g >> 4);
+			let b8 = (b << 3) | (b >> 2);
+
+			img.put_pixel(x, y, image::Rgba([r8, g8, b8, 255]));
+		}
+	}
+	img
+}
+
+pub fn rgba_to_rgb565(rgba: &[u8], width: u32, height: u32) -> Vec<u8> {
+	let pixel_count = (width * height) as usize;
+	let mut buf = vec![0u8; pixel_count * 2];
+	for i in 0..pixel_count {
+		buf[2 * i..2 * i + 2].copy_from_slice(&rgb565(
+			rgba[4 * i],
+			rgba[4 * i + 1],
+			rgba[4 * i + 2],
+		));
+	}
+	buf
+}
+
+fn rgb565(r: u8, g: u8, b: u8) -> [u8; 2] {
+	let (r, g, b) = (r as u16, g as u16, b as u16);
+	let pixel = ((r & 0xF8) << 8) | ((g & 0xFC) << 3) | ((b & 0xF8) >> 3);
+	pixel.to_be_bytes()
+}
+
+// Fills a rectangle of an RGB565 display frame, clipped to the display.
+pub fn fill_rect(frame: &mut [u8], x: u32, y: u32, w: u32, h: u32, rgb: [u8; 3]) {
+	let pixel = rgb565(rgb[0], rgb[1], rgb[2]);
+	for row in y..(y + h).min(DISPLAY_HEIGHT) {
+		for col in x..(x + w).min(DISPLAY_WIDTH) {
+			let idx = ((row * DISPLAY_WIDTH + col) * 2) as usize;
+			frame[idx..idx + 2].copy_from_slice(&pixel);
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LoadOptions {
+	pub crop: bool,
+	pub temperature: Option<u32>,
+	pub similarity_threshold: Option<f64>,
+	// Frame size of headerless .rgb565 files.
+	pub raw_size: Option<(u32, u32)>,
+}
+
+pub fn load_image(path: &Path, opts: &LoadOptions) -> Result<Album> {
+	let ext = path
+		.extension()
+		.and_then(|e| e.to_str())
+		.unwrap_or("")
+		.to_lowercase();
+
+	match ext.as_str() {
+		"gif" => {
+			let mut album = load_gif(path, opts)?;
+			if let Some(threshold) = opts.similarity_threshold {
+				merge_similar_frames(&mut album, threshold);
+			}
+			Ok(album)
+		}
+		"png" | "jpg" | "jpeg" | "bmp" | "webp" => load_static(path, opts),
+		"rgb565" => {
+			let mut album = load_raw(path, opts.raw_size)?;
+			if let Some(threshold) = opts.similarity_threshold {
+				merge_similar_frames(&mut album, threshold);
+			}
+			Ok(album)
+		}
+		_ => bail!("unsupported image format: {ext}"),
+	}
+}
+
+fn is_raw(path: &Path) -> bool {
+	path.extension()
+		.and_then(|e| e.to_str())
+		.is_some_and(|e| e.eq_ignore_ascii_case("rgb565"))
+}
+
+// .rgb565 files hold frames already in the device's format (big-endian RGB565 at the
+// display size), so they skip decoding, resizing and quantization. They may start with
+// a 12-byte header: "R565", then width, height, frame count and delay in ms as u16 LE.
+// Without one, the frame size must be given and the file is a run of whole frames.
+const RAW_MAGIC: &[u8; 4] = b"R565";
+const RAW_HEADER_SIZE: usize = 12;
+const RAW_DEFAULT_DELAY_MS: u16 = 100;
+
+fn load_raw(path: &Path, size: Option<(u32, u32)>) -> Result<Album> {
+	let data = std::fs::read(path)?;
+	let field = |i: usize| u16::from_le_bytes([data[i], data[i + 1]]);
+	// A headerless file is a whole number of frames, so the lengths can't be confused.
+	let headered = data.starts_with(RAW_MAGIC) && data.len() % FRAME_PIXEL_SIZE == RAW_HEADER_SIZE;
+	let (width, height, count, delay_ms, pixels) = if headered {
+		(
+			field(4) as u32,
+			field(6) as u32,
+			Some(field(8) as usize),
+			field(10),
+			&data[RAW_HEADER_SIZE..],
+		)
+	} else {
+		let Some((width, height)) = size else {
+			bail!("raw file has no R565 header; pass --size {DISPLAY_WIDTH}x{DISPLAY_HEIGHT}");
+		};
+		(width, height, None, RAW_DEFAULT_DELAY_MS, &data[..])
+	};
+
+	if (width, height) != (DISPLAY_WIDTH, DISPLAY_HEIGHT) {
+		bail!(
+			"raw frames are {width}x{height}, but must be {DISPLAY_WIDTH}x{DISPLAY_HEIGHT} (they are not resized)"
+		);
+	}
+	if pixels.is_empty() || pixels.len() % FRAME_PIXEL_SIZE != 0 {
+		bail!(
+			"{} bytes of pixel data is not a whole number of {FRAME_PIXEL_SIZE}-byte frames",
+			pixels.len()
+		);
+	}
+	let frames: Vec<Vec<u8>> = pixels
+		.chunks(FRAME_PIXEL_SIZE)
+		.map(|f| f.to_vec())
+		.collect();
+	if let Some(count) = count.filter(|&c| c != frames.len()) {
+		bail!("header says {count} frame(s), file holds {}", frames.len());
+	}
+
+	let delay_ms = if frames.len() > 1 { delay_ms } else { 0 };
+	Ok(Album { frames, delay_ms })
+}
+
+// Splits `logo@480x222.png` into ("logo", Some((480, 222))). Anything else is an
+// unsized asset named after its whole stem.
+fn variant_of(path: &Path) -> (String, Option<(u32, u32)>) {
+	let stem = path
+		.file_stem()
+		.map(|s| s.to_string_lossy().into_owned())
+		.unwrap_or_default();
+	let size = stem.rsplit_once('@').and_then(|(base, size)| {
+		let (w, h) = size.split_once(['x', 'X'])?;
+		Some((base.to_string(), (w.parse().ok()?, h.parse().ok()?)))
+	});
+	match size {
+		Some((base, size)) => (base, Some(size)),
+		None => (stem, None),
+	}
+}
+
+// Several renditions of one asset can sit side by side as `name@WxH.ext` (plus an
+// optional plain `name.ext`); only the one that suits a `width`x`height` display is
+// kept. Preference: an exact match, then the smallest variant that covers the display
+// (least downscaling), then the plain file, then the largest remaining variant.
+// Order follows each asset's first appearance in `paths`.
+pub fn select_variants(paths: Vec<PathBuf>, width: u32, height: u32) -> Vec<PathBuf> {
+	let rank = |size: Option<(u32, u32)>| -> (u8, i64) {
+		match size {
+			Some(s) if s == (width, height) => (0, 0),
+			Some((w, h)) if w >= width && h >= height => (1, w as i64 * h as i64),
+			None => (2, 0),
+			Some((w, h)) => (3, -(w as i64 * h as i64)),
+		}
+	};
+
+	let mut chosen: Vec<(PathBuf, (u8, i64))> = Vec::new();
+	let mut index: HashMap<(Option<PathBuf>, String), usize> = HashMap::new();
+	for path in paths {
+		let (base, size) = variant_of(&path);
+		let key = (path.parent().map(Path::to_path_buf), base);
+		let candidate = (path, rank(size));
+		match index.get(&key) {
+			Some(&i) => {
+				if candidate.1 < chosen[i].1 {
+					chosen[i] = candidate;
+				}
+			}
+			None => {
+				index.insert(key, chosen.len());
+				chosen.push(candidate);
+			}
+		}
+	}
+	chosen.into_iter().map(|(path, _)| path).collect()
+}
+
+// Decodes any supported input; raw files are shown as their first frame.
+fn open_image(path: &Path, raw_size: Option<(u32, u32)>) -> Result<image::DynamicImage> {
+	if is_raw(path) {
+		let album = load_raw(path, raw_size)?;
+		let frame = rgb565_to_image(
+			&album.frames[0],
+			DISPLAY_WIDTH as u16,
+			DISPLAY_HEIGHT as u16,
+		);
+		return Ok(frame.into());
+	}
+	Ok(image::open(path)?)
+}
+
+fn load_static(path: &Path, opts: &LoadOptions) -> Result<Album> {
+	let img = image::open(path)?;
+	let data = convert_frame(&img, opts);
+
+	Ok(Album {
+		frames: vec![data],
+		delay_ms: 0,
+	})
+}
+
+fn load_gif(path: &Path, opts: &LoadOptions) -> Result<Album> {
+	use gif::DecodeOptions;
+	use std::fs::File;
+
+	let probe = DecodeOptions::new().read_info(File::open(path)?)?;
+	if probe.width() as u32 == DISPLAY_WIDTH && probe.height() as u32 == DISPLAY_HEIGHT {
+		return load_gif_indexed(path, opts);
+	}
+
+	let file = File::open(path)?;
+	let mut decode_opts = DecodeOptions::new();
+	decode_opts.set_color_output(gif::ColorOutput::RGBA);
+	let mut decoder = decode_opts.read_info(file)?;
+
+	let gif_width = decoder.width() as u32;
+	let gif_height = decoder.height() as u32;
+
+	let mut frames = Vec::new();
+	let mut delay_ms = 0u16;
+	let mut canvas = vec![0u8; (gif_width * gif_height * 4) as usize];
+
+	while let Some(frame) = decoder.read_next_frame()? {
+		if delay_ms == 0 && frame.delay > 0 {
+			delay_ms = frame.delay * 10;
+		}
+
+		let fx = frame.left as u32;
+		let fy = frame.top as u32;
+		let fw = frame.width as u32;
+		let fh = frame.height as u32;
+
+		for y in 0..fh {
+			for x in 0..fw {
+				let src_idx = ((y * fw + x) * 4) as usize;
+				let dst_x = fx + x;
+				let dst_y = fy + y;
+				if dst_x < gif_width && dst_y < gif_height {
+					let dst_idx = ((dst_y * gif_width + dst_x) * 4) as usize;
+					if frame.buffer[src_idx + 3] > 0 {
+						canvas[dst_idx..dst_idx + 4]
+							.copy_from_slice(&frame.buffer[src_idx..src_idx + 4]);
+					}
+				}
+			}
+		}
+
+		let img = image::RgbaImage::from_raw(gif_width, gif_height, canvas.clone())
+			.ok_or_else(|| anyhow::anyhow!("failed to create image from GIF frame"))?;
+		let dyn_img = image::DynamicImage::from(img);
+		frames.push(convert_frame(&dyn_img, opts));
+
+		if frame.dispose == gif::DisposalMethod::Background {
+			for y in 0..fh {
+				for x in 0..fw {
+					let dst_x = fx + x;
+					let dst_y = fy + y;
+					if dst_x < gif_width && dst_y < gif_height {
+						let idx = ((dst_y * gif_width + dst_x) * 4) as usize;
+						canvas[idx..idx + 4].copy_from_slice(&[0, 0, 0, 0]);
+					}
+				}
+			}
+		}
+	}
+
+	if frames.is_empty() {
+		bail!("GIF has no frames");
+	}
+
+	Ok(Album { frames, delay_ms })
+}
+
+// GIFs already at display size need no resampling, so each frame's palette is
+// converted to RGB565 once and pixels are looked up instead of going through RGBA.
+fn load_gif_indexed(path: &Path, opts: &LoadOptions) -> Result<Album> {
+	use gif::DecodeOptions;
+	use std::fs::File;
+
+	let mut decode_opts = DecodeOptions::new();
+	decode_opts.set_color_output(gif::ColorOutput::Indexed);
+	let mut decoder = decode_opts.read_info(File::open(path)?)?;
+	let global_palette = decoder.global_palette().map(|p| p.to_vec());
+	let gains = opts.temperature.map(temperature_gains);
+
+	let width = DISPLAY_WIDTH as usize;
+	let height = DISPLAY_HEIGHT as usize;
+	let mut frames = Vec::new();
+	let mut delay_ms = 0u16;
+	let mut canvas = vec![0u8; width * height * 2];
+
+	while let Some(frame) = decoder.read_next_frame()? {
+		if delay_ms == 0 && frame.delay > 0 {
+			delay_ms = frame.delay * 10;
+		}
+
+		let palette = frame
+			.palette
+			.as_deref()
+			.or(global_palette.as_deref())
+			.ok_or_else(|| anyhow::anyhow!("GIF frame has no palette"))?;
+		let mut lut = [[0u8; 2]; 256];
+		for (entry, rgb) in lut.iter_mut().zip(palette.chunks_exact(3)) {
+			let mut rgb = [rgb[0], rgb[1], rgb[2]];
+			if let Some(gains) = gains {
+				for (channel, gain) in rgb.iter_mut().zip(gains) {
+					*channel = (*channel as f64 * gain).round() as u8;
+				}
+			}
+			*entry = rgb565(rgb[0], rgb[1], rgb[2]);
+		}
+
+		let fx = frame.left as usize;
+		let fy = frame.top as usize;
+		let fw = frame.width as usize;
+		let fh = frame.height as usize;
+		let visible_w = fw.min(width.saturating_sub(fx));
+		let visible_h = fh.min(height.saturating_sub(fy));
+
+		for y in 0..visible_h {
+			for x in 0..visible_w {
+				let index = frame.buffer[y * fw + x];
+				if frame.transparent == Some(index) {
+					continue;
+				}
+				let dst = ((fy + y) * width + fx + x) * 2;
+				canvas[dst..dst + 2].copy_from_slice(&lut[index as usize]);
+			}
+		}
+
+		frames.push(canvas.clone());
+
+		if frame.dispose == gif::DisposalMethod::Background {
+			for y in 0..visible_h {
+				let dst = ((fy + y) * width + fx) * 2;
+				canvas[dst..dst + visible_w * 2].fill(0);
+			}
+		}
+	}
+
+	if frames.is_empty() {
+		bail!("GIF has no frames");
+	}
+
+	Ok(Album { frames, delay_ms })
+}
+
+fn convert_frame(img: &image::DynamicImage, opts: &LoadOptions) -> Vec<u8> {
+	let resized = resize_image(img, DISPLAY_WIDTH, DISPLAY_HEIGHT, opts.crop);
+	let mut rgba = resized.into_rgba8();
+	if let Some(kelvin) = opts.temperature {
+		apply_temperature(&mut rgba, kelvin);
+	}
+	rgba_to_rgb565(rgba.as_raw(), DISPLAY_WIDTH, DISPLAY_HEIGHT)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Quality {
+	pub psnr: f64,
+	pub ssim: f64,
+	pub clipped_shadows: f64,
+	pub clipped_highlights: f64,
+}
+
+// Compares the resized source (with any temperature shift, which is intentional) to
+// what the panel shows after RGB565 quantization. GIFs are measured on their first
+// frame. Clipping is the percentage of source pixels with a channel at 0 or 255.
+pub fn conversion_quality(path: &Path, opts: &LoadOptions) -> Result<Quality> {
+	let img = open_image(path, opts.raw_size)?;
+	let mut reference = resize_image(&img, DISPLAY_WIDTH, DISPLAY_HEIGHT, opts.crop).to_rgba8();
+	if let Some(kelvin) = opts.temperature {
+		apply_temperature(&mut reference, kelvin);
+	}
+	let converted = rgb565_to_image(
+		&rgba_to_rgb565(reference.as_raw(), DISPLAY_WIDTH, DISPLAY_HEIGHT),
+		DISPLAY_WIDTH as u16,
+		DISPLAY_HEIGHT as u16,
+	);
+
+	let mut squared_error = 0.0;
+	let mut shadows = 0;
+	let mut highlights = 0;
+	for (a, b) in reference.pixels().zip(converted.pixels()) {
+		for c in 0..3 {
+			let diff = a[c] as f64 - b[c] as f64;
+			squared_error += diff * diff;
+		}
+		if a.0[..3].contains(&0) {
+			shadows += 1;
+		}
+		if a.0[..3].contains(&255) {
+			highlights += 1;
+		}
+	}
+	let pixels = (DISPLAY_WIDTH * DISPLAY_HEIGHT) as f64;
+	let mse = squared_error / (pixels * 3.0);
+	let psnr = if mse == 0.0 {
+		f64::INFINITY
+	} else {
+		10.0 * (255.0 * 255.0 / mse).log10()
+	};
+
+	Ok(Quality {
+		psnr,
+		ssim: ssim(&luma(&reference), &luma(&converted)),
+		clipped_shadows: shadows as f64 * 100.0 / pixels,
+		clipped_highlights: highlights as f64 * 100.0 / pixels,
+	})
+}
+
+fn luma(img: &image::RgbaImage) -> Vec<f64> {
+	img.pixels()
+		.map(|p| 0.299 * p[0] as f64 + 0.587 * p[1] as f64 + 0.114 * p[2] as f64)
+		.collect()
+}
+
+// Mean SSIM over non-overlapping 8x8 luma windows.
+fn ssim(a: &[f64], b: &[f64]) -> f64 {
+	const WINDOW: usize = 8;
+	const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+	const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+	let width = DISPLAY_WIDTH as usize;
+	let height = DISPLAY_HEIGHT as usize;
+
+	let mut total = 0.0;
+	let mut windows = 0;
+	for wy in (0..height - WINDOW + 1).step_by(WINDOW) {
+		for wx in (0..width - WINDOW + 1).step_by(WINDOW) {
+			let samples = || {
+				(wy..wy + WINDOW).flat_map(move |y| (wx..wx + WINDOW).map(move |x| y * width + x))
+			};
+			let n = (WINDOW * WINDOW) as f64;
+			let mean_a = samples().map(|i| a[i]).sum::<f64>() / n;
+			let mean_b = samples().map(|i| b[i]).sum::<f64>() / n;
+			let (mut var_a, mut var_b, mut cov) = (0.0, 0.0, 0.0);
+			for i in samples() {
+				let (da, db) = (a[i] - mean_a, b[i] - mean_b);
+				var_a += da * da;
+				var_b += db * db;
+				cov += da * db;
+			}
+			let (var_a, var_b, cov) = (var_a / n, var_b / n, cov / n);
+			total += ((2.0 * mean_a * mean_b + C1) * (2.0 * cov + C2))
+				/ ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2));
+			windows += 1;
+		}
+	}
+	total / windows as f64
+}
+
+// Approximation of the black-body colour for a temperature (Tanner Helland).
+fn kelvin_to_rgb(kelvin: u32) -> [f64; 3] {
+	let t = kelvin as f64 / 100.0;
+	let r = if t <= 66.0 {
+		255.0
+	} else {
+		329.698727446 * (t - 60.0).powf(-0.1332047592)
+	};
+	let g = if t <= 66.0 {
+		99.4708025861 * t.ln() - 161.1195681661
+	} else {
+		288.1221695283 * (t - 60.0).powf(-0.0755148492)
+	};
+	let b = if t >= 66.0 {
+		255.0
+	} else if t <= 19.0 {
+		0.0
+	} else {
+		138.5177312231 * (t - 10.0).ln() - 305.0447927307
+	};
+	[
+		r.clamp(0.0, 255.0),
+		g.clamp(0.0, 255.0),
+		b.clamp(0.0, 255.0),
+	]
+}
+
+fn temperature_gains(kelvin: u32) -> [f64; 3] {
+	let target = kelvin_to_rgb(kelvin);
+	let neutral = kelvin_to_rgb(6500);
+	let mut gains: [f64; 3] = std::array::from_fn(|c| target[c] / neutral[c]);
+	let max = gains.iter().cloned().fold(f64::MIN, f64::max);
+	for g in &mut gains {
+		*g /= max;
+	}
+	gains
+}
+
+pub fn apply_temperature(img: &mut image::RgbaImage, kelvin: u32) {
+	let gains = temperature_gains(kelvin);
+	for pixel in img.pixels_mut() {
+		for (channel, gain) in pixel.0.iter_mut().zip(gains) {
+			*channel = (*channel as f64 * gain).round() as u8;
+		}
+	}
+}
+
+// Drops frames that differ from the last kept frame by less than `threshold` percent
+// (mean luma difference). Albums share one delay, so it is stretched to keep the
+// loop's total duration.
+pub fn merge_similar_frames(album: &mut Album, threshold: f64) {
+	let original = album.frames.len();
+	if original < 2 {
+		return;
+	}
+	let mut kept: Vec<Vec<u8>> = Vec::with_capacity(original);
+	for frame in album.frames.drain(..) {
+		match kept.last() {
+			Some(last) if frame_difference(last, &frame) < threshold => {}
+			_ => kept.push(frame),
+		}
+	}
+	let total_ms = album.delay_ms as usize * original;
+	album.delay_ms = (total_ms / kept.len()).min(u16::MAX as usize) as u16;
+	album.frames = kept;
+}
+
+fn frame_difference(a: &[u8], b: &[u8]) -> f64 {
+	let luma = |px: &[u8]| {
+		let v = u16::from_be_bytes([px[0], px[1]]);
+		let r = ((v >> 11) & 0x1F) as f64 * 255.0 / 31.0;
+		let g = ((v >> 5) & 0x3F) as f64 * 255.0 / 63.0;
+		let b = (v & 0x1F) as f64 * 255.0 / 31.0;
+		0.299 * r + 0.587 * g + 0.114 * b
+	};
+	let pixels = a.len() / 2;
+	let total: f64 = a
+		.chunks_exact(2)
+		.zip(b.chunks_exact(2))
+		.map(|(pa, pb)| (luma(pa) - luma(pb)).abs())
+		.sum();
+	total / pixels as f64 / 255.0 * 100.0
+}
+
+pub fn interpolate(album: &mut Album, steps: usize) {
+	if album.frames.len() < 2 || steps == 0 {
+		return;
+	}
+	let count = album.frames.len();
+	let mut frames = Vec::with_capacity(count * (steps + 1));
+	for i in 0..count {
+		let current = &album.frames[i];
+		let next = &album.frames[(i + 1) % count];
+		frames.push(current.clone());
+		for k in 1..=steps {
+			let t = k as f32 / (steps + 1) as f32;
+			frames.push(blend_rgb565(current, next, t));
+		}
+	}
+	album.frames = frames;
+	album.delay_ms = (album.delay_ms / (steps as u16 + 1)).max(10);
+}
+
+fn blend_rgb565(a: &[u8], b: &[u8], t: f32) -> Vec<u8> {
+	let mix = |x: u16, y: u16| -> u16 { (x as f32 + (y as f32 - x as f32) * t).round() as u16 };
+	let mut out = vec![0u8; a.len()];
+	for ((dst, pa), pb) in out
+		.chunks_exact_mut(2)
+		.zip(a.chunks_exact(2))
+		.zip(b.chunks_exact(2))
+	{
+		let pa = u16::from_be_bytes([pa[0], pa[1]]);
+		let pb = u16::from_be_bytes([pb[0], pb[1]]);
+		let r = mix(pa >> 11, pb >> 11);
+		let g = mix((pa >> 5) & 0x3F, (pb >> 5) & 0x3F);
+		let bl = mix(pa & 0x1F, pb & 0x1F);
+		dst.copy_from_slice(&((r << 11) | (g << 5) | bl).to_be_bytes());
+	}
+	out
+}
+
+// Difference hash: 64 bits comparing neighbouring pixels of a 9x8 greyscale thumbnail,
+// so the same picture at another size or format hashes (nearly) the same.
+pub fn perceptual_hash(path: &Path, raw_size: Option<(u32, u32)>) -> Result<u64> {
+	let img = open_image(path, raw_size)?;
+	let small = img
+		.grayscale()
+		.resize_exact(9, 8, image::imageops::FilterType::Triangle)
+		.to_luma8();
+	let mut hash = 0u64;
+	for y in 0..8 {
+		for x in 0..8 {
+			let bit = small.get_pixel(x, y)[0] > small.get_pixel(x + 1, y)[0];
+			hash = (hash << 1) | bit as u64;
+		}
+	}
+	Ok(hash)
+}
+
+pub fn test_pattern_album() -> Album {
+	let frames = (0..2u32)
+		.map(|f| {
+			let img = image::RgbaImage::from_fn(DISPLAY_WIDTH, DISPLAY_HEIGHT, |x, y| {
+				let r = (x * 255 / (DISPLAY_WIDTH - 1)) as u8;
+				let g = (y * 255 / (DISPLAY_HEIGHT - 1)) as u8;
+				let b = if (x / 16 + y / 16 + f) % 2 == 0 {
+					255
+				} else {
+					0
+				};
+				image::Rgba([r, g, b, 255])
+			});
+			rgba_to_rgb565(img.as_raw(), DISPLAY_WIDTH, DISPLAY_HEIGHT)
+		})
+		.collect();
+	Album {
+		frames,
+		delay_ms: 500,
+	}
+}
+
+pub fn resize_image(
+	img: &image::DynamicImage,
+	target_w: u32,
+	target_h: u32,
+	crop: bool,
+) -> image::DynamicImage {
+	let src_w = img.width() as f64;
+	let src_h = img.height() as f64;
+	let target_aspect = target_w as f64 / target_h as f64;
+	let src_aspect = src_w / src_h;
+
+	if crop {
+		let (crop_w, crop_h, crop_x, crop_y) = if src_aspect > target_aspect {
+			let cw = src_h * target_aspect;
+			let cx = (src_w - cw) / 2.0;
+			(cw, src_h, cx, 0.0)
+		} else {
+			let ch = src_w / target_aspect;
+			let cy = (src_h - ch) / 2.0;
+			(src_w, ch, 0.0, cy)
+		};
+
+		// Whole-pixel crop box, as image's crop_imm took it.
+		lanczos3(
+			img,
+			target_w,
+			target_h,
+			Some((
+				crop_x.trunc(),
+				crop_y.trunc(),
+				crop_w.trunc(),
+				crop_h.trunc(),
+			)),
+		)
+	} else {
+		let (fit_w, fit_h) = fit_dimensions(img.width(), img.height(), target_w, target_h);
+		let fitted = lanczos3(img, fit_w, fit_h, None);
+		if (fit_w, fit_h) == (target_w, target_h) {
+			return fitted;
+		}
+		let mut canvas = image::RgbaImage::new(target_w, target_h);
+		let offset_x = (target_w - fit_w) / 2;
+		let offset_y = (target_h - fit_h) / 2;
+		image::imageops::overlay(
+			&mut canvas,
+			&fitted.to_rgba8(),
+			offset_x as i64,
+			offset_y as i64,
+		);
+		image::DynamicImage::from(canvas)
+	}
+}
+
+// The largest size with the source's aspect ratio that fits the target, rounded the
+// way image's DynamicImage::resize does.
+fn fit_dimensions(src_w: u32, src_h: u32, target_w: u32, target_h: u32) -> (u32, u32) {
+	let ratio = f64::min(
+		target_w as f64 / src_w as f64,
+		target_h as f64 / src_h as f64,
+	);
+	(
+		((src_w as f64 * ratio).round() as u32).max(1),
+		((src_h as f64 * ratio).round() as u32).max(1),
+	)
+}
+
+// Lanczos3 through fast_image_resize, which is several times quicker than image's
+// resampler. Pixel formats it doesn't take fall back to image.
+fn lanczos3(
+	img: &image::DynamicImage,
+	w: u32,
+	h: u32,
+	crop: Option<(f64, f64, f64, f64)>,
+) -> image::DynamicImage {
+	if crop.is_none() && (w, h) == img.dimensions() {
+		return img.clone();
+	}
+	let mut options = ResizeOptions::new()
+		.resize_alg(ResizeAlg::Convolution(FilterType::Lanczos3))
+		.use_alpha(false);
+	if let Some((x, y, cw, ch)) = crop {
+		options = options.crop(x, y, cw, ch);
+	}
+	let mut dst = image::DynamicImage::new(w, h, img.color());
+	if Resizer::new().resize(img, &mut dst, &options).is_ok() {
+		return dst;
+	}
+	let src = match crop {
+		Some((x, y, cw, ch)) => img.crop_imm(x as u32, y as u32, cw as u32, ch as u32),
+		None => img.clone(),
+	};
+	src.resize_exact(w, h, image::imageops::FilterType::Lanczos3)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn pick(paths: &[&str]) -> Vec<String> {
+		let paths = paths.iter().map(PathBuf::from).collect();
+		select_variants(paths, 480, 222)
+			.into_iter()
+			.map(|p| p.to_string_lossy().into_owned())
+			.collect()
+	}
+
+	#[test]
+	fn variants_prefer_exact_then_covering_then_plain_then_largest() {
+		assert_eq!(
+			pick(&["a.png", "a@960x444.png", "a@480x222.png", "a@240x111.png"]),
+			["a@480x222.png"]
+		);
+		assert_eq!(
+			pick(&["a@960x444.png", "a.png", "a@500x300.png"]),
+			["a@500x300.png"]
+		);
+		assert_eq!(pick(&["a@240x111.png", "a.png"]), ["a.png"]);
+		assert_eq!(
+			pick(&["a@240x111.png", "a@320x148.png", "a@100x50.png"]),
+			["a@320x148.png"]
+		);
+	}
+
+	#[test]
+	fn variant_ties_keep_the_first_seen() {
+		assert_eq!(pick(&["a@480x222.jpg", "a@480x222.png"]), ["a@480x222.jpg"]);
+		assert_eq!(pick(&["a@500x300.png", "a@600x250.png"]), ["a@500x300.png"]);
+		assert_eq!(pick(&["a@600x250.png", "a@500x300.png"]), ["a@600x250.png"]);
+		assert_eq!(pick(&["a.png", "a.gif"]), ["a.png"]);
+	}
+
+	#[test]
+	fn variants_are_grouped_by_directory_and_keep_order() {
+		assert_eq!(
+			pick(&["x/b@480x222.png", "x/a.png", "y/b.png", "x/b.png"]),
+			["x/b@480x222.png", "x/a.png", "y/b.png"]
+		);
+	}
+
+	fn album(frames: &[u8], delay_ms: u16) -> Album {
+		Album {
+			frames: frames.iter().map(|&v| vec![v; 4]).collect(),
+			delay_ms,
+		}
+	}
+
+	#[test]
+	fn merged_frames_stretch_the_delay() {
+		let mut a = album(&[0, 0, 0xFF, 0xFF], 100);
+		merge_similar_frames(&mut a, 1.0);
+		assert_eq!(a.frames.len(), 2);
+		assert_eq!(a.delay_ms, 200);
+	}
+
+	#[test]
+	fn stretched_delay_rounds_down() {
+		let mut a = album(&[0, 0, 0xFF], 101);
+		merge_similar_frames(&mut a, 1.0);
+		assert_eq!(a.frames.len(), 2);
+		assert_eq!(a.delay_ms, 151);
+	}
+
+	#[test]
+	fn stretched_delay_saturates() {
+		let mut a = album(&[0, 0, 0], 40_000);
+		merge_similar_frames(&mut a, 1.0);
+		assert_eq!(a.frames.len(), 1);
+		assert_eq!(a.delay_ms, u16::MAX);
+	}
+
+	#[test]
+	fn distinct_or_single_frames_are_left_alone() {
+		let mut a = album(&[0, 0xFF, 0], 100);
+		merge_similar_frames(&mut a, 1.0);
+		assert_eq!((a.frames.len(), a.delay_ms), (3, 100));
+
+		let mut a = album(&[0], 0);
+		merge_similar_frames(&mut a, 100.0);
+		assert_eq!((a.frames.len(), a.delay_ms), (1, 0));
+	}
+
+	fn raw_file(name: &str, data: &[u8]) -> PathBuf {
+		let path =
+			std::env::temp_dir().join(format!("hm-hub-{}-{name}.rgb565", std::process::id()));
+		std::fs::write(&path, data).unwrap();
+		path
+	}
+
+	fn raw_header(width: u32, height: u32, count: u16, delay_ms: u16) -> Vec<u8> {
+		let mut data = RAW_MAGIC.to_vec();
+		for v in [width as u16, height as u16, count, delay_ms] {
+			data.extend(v.to_le_bytes());
+		}
+		data
+	}
+
+	fn load(name: &str, data: &[u8], size: Option<(u32, u32)>) -> Result<Album> {
+		let path = raw_file(name, data);
+		let album = load_raw(&path, size);
+		std::fs::remove_file(path).unwrap();
+		album
+	}
+
+	#[test]
+	fn raw_header_is_read() {
+		let mut data = raw_header(DISPLAY_WIDTH, DISPLAY_HEIGHT, 2, 40);
+		data.resize(RAW_HEADER_SIZE + 2 * FRAME_PIXEL_SIZE, 0);
+		let album = load("headered", &data, None).unwrap();
+		assert_eq!((album.frames.len(), album.delay_ms), (2, 40));
+	}
+
+	#[test]
+	fn raw_without_header_needs_a_size() {
+		let data = vec![0; 2 * FRAME_PIXEL_SIZE];
+		assert!(load("bare-unsized", &data, None).is_err());
+		let album = load("bare", &data, Some((DISPLAY_WIDTH, DISPLAY_HEIGHT))).unwrap();
+		assert_eq!(
+			(album.frames.len(), album.delay_ms),
+			(2, RAW_DEFAULT_DELAY_MS)
+		);
+	}
+
+	#[test]
+	fn raw_pixels_starting_with_the_magic_are_not_a_header() {
+		let mut data = vec![0; FRAME_PIXEL_SIZE];
+		data[..4].copy_from_slice(RAW_MAGIC);
+		assert!(load("magic-unsized", &data, None).is_err());
+		let album = load("magic", &data, Some((DISPLAY_WIDTH, DISPLAY_HEIGHT))).unwrap();
+		assert_eq!((album.frames.len(), album.delay_ms), (1, 0));
+		assert_eq!(&album.frames[0][..4], RAW_MAGIC);
+	}
+
+	#[test]
+	fn raw_header_mismatches_are_errors() {
+		let mut data = raw_header(DISPLAY_WIDTH, DISPLAY_HEIGHT, 3, 40);
+		data.resize(RAW_HEADER_SIZE + 2 * FRAME_PIXEL_SIZE, 0);
+		assert!(load("count", &data, None).is_err());
+
+		let mut data = raw_header(DISPLAY_HEIGHT, DISPLAY_WIDTH, 1, 0);
+		data.resize(RAW_HEADER_SIZE + FRAME_PIXEL_SIZE, 0);
+		assert!(load("size", &data, None).is_err());
+
+		let data = raw_header(DISPLAY_WIDTH, DISPLAY_HEIGHT, 0, 0);
+		assert!(load("empty", &data, None).is_err());
+	}
+}
@@ -0,0 +1,12 @@
+// Device-independent data formats: image conversion, flash layout and backup files.
+// Nothing here talks to a serial port, so it also builds for WASM and other hosts.
+pub mod backup;
+pub mod consts;
+pub mod error;
+pub mod flash;
+pub mod image;
+pub mod types;
+
+pub use error::HubError;
+pub use flash::build_flash_buffer;
+pub use types::{Album, DeviceConfig, DeviceInfo};
@@ -0,0 +1,5 @@
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Album {
+	pub frames: Vec<Vec<u8>>,
+	pub delay_ms: u16,
+}
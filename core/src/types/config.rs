@@ -0,0 +1,458 @@
+use std::str::FromStr;
+
+use crate::error::{HubError, Result};
+use std::fmt;
+
+pub const CONFIG_FIELDS: &[(&str, &str)] = &[
+	("brightness <0-30>", "Screen brightness"),
+	("rotation <0|90|180|270>", "Screen rotation"),
+	("interval <seconds>", "Image switch interval"),
+	("random <0|1>", "Random image order"),
+	("crop <0|1>", "Crop to fill (1) or letterbox (0)"),
+	("cut_frame <0|1>", "Album cut frame"),
+	("shake_sens <0-255>", "Shake sensitivity"),
+	("screen_onoff_by_usb <0|1>", "Screen on/off with USB"),
+	("power_style <0-255>", "Power display style"),
+	("srgb_style <0-255>", "sRGB style"),
+	("switch_mode <0-65535>", "Image switch mode"),
+	("page <0-255>", "Memory page"),
+	("single_click <0-255>", "Single-click action code"),
+	("double_click <0-255>", "Double-click action code"),
+	("tilt <0-255>", "Tilt action code"),
+	("shake <0-255>", "Shake action code"),
+	("language <0-255>", "Device language code (undocumented)"),
+	("web_help <0|1>", "Web help"),
+];
+
+pub const MAX_BRIGHTNESS: u8 = 30;
+
+// The names set_field and get_field take, in CONFIG_FIELDS order.
+pub fn config_field_names() -> impl Iterator<Item = &'static str> {
+	CONFIG_FIELDS
+		.iter()
+		.filter_map(|(usage, _)| usage.split_whitespace().next())
+}
+
+pub fn config_fields_help() -> String {
+	let mut help = String::from("Available config fields:\n");
+	for (field, description) in CONFIG_FIELDS {
+		help.push_str(&format!("  {field:<26}{description}\n"));
+	}
+	help
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceConfig {
+	pub cur_lang: u8,
+	pub web_help_onoff: u8,
+	pub memory_page: u8,
+	pub screen_dir: u8,
+	pub screen_brightness: u8,
+	pub album_cut_black: u8,
+	pub album_cut_frame: u8,
+	pub fun_single_click: u8,
+	pub fun_double_click: u8,
+	pub fun_tilt: u8,
+	pub fun_shake: u8,
+	pub fun_shake_sens: u8,
+	pub screen_onoff_by_usb: u8,
+	pub reserve1: u8,
+	pub reserve2: u8,
+	pub reserve3: u8,
+	pub reserve4: u8,
+	pub reserve5: u8,
+	pub power_style: u8,
+	pub image_switch_random: u8,
+	pub image_switch_mode: u16,
+	pub image_switch_interval: u8,
+	pub srgb_style: u8,
+}
+
+impl DeviceConfig {
+	pub fn from_bytes(data: &[u8]) -> Result<Self> {
+		if data.len() < 24 {
+			return Err(HubError::ProtocolViolation(format!(
+				"config data too short: {} < 24",
+				data.len()
+			)));
+		}
+		Ok(Self {
+			cur_lang: data[0],
+			web_help_onoff: data[1],
+			memory_page: data[2],
+			screen_dir: data[3],
+			screen_brightness: data[4],
+			album_cut_black: data[5],
+			album_cut_frame: data[6],
+			fun_single_click: data[7],
+			fun_double_click: data[8],
+			fun_tilt: data[9],
+			fun_shake: data[10],
+			fun_shake_sens: data[11],
+			screen_onoff_by_usb: data[12],
+			reserve1: data[13],
+			reserve2: data[14],
+			reserve3: data[15],
+			reserve4: data[16],
+			reserve5: data[17],
+			power_style: data[18],
+			image_switch_random: data[19],
+			image_switch_mode: u16::from_le_bytes([data[20], data[21]]),
+			image_switch_interval: data[22],
+			srgb_style: data[23],
+		})
+	}
+
+	pub fn screen_rotation(&self) -> Option<Rotation> {
+		Rotation::from_code(self.screen_dir)
+	}
+
+	pub fn button_action(&self, button: Button) -> u8 {
+		match button {
+			Button::SingleClick => self.fun_single_click,
+			Button::DoubleClick => self.fun_double_click,
+			Button::Tilt => self.fun_tilt,
+			Button::Shake => self.fun_shake,
+		}
+	}
+
+	pub fn to_bytes(&self) -> [u8; 24] {
+		let mut b = [0u8; 24];
+		b[0] = self.cur_lang;
+		b[1] = self.web_help_onoff;
+		b[2] = self.memory_page;
+		b[3] = self.screen_dir;
+		b[4] = self.screen_brightness;
+		b[5] = self.album_cut_black;
+		b[6] = self.album_cut_frame;
+		b[7] = self.fun_single_click;
+		b[8] = self.fun_double_click;
+		b[9] = self.fun_tilt;
+		b[10] = self.fun_shake;
+		b[11] = self.fun_shake_sens;
+		b[12] = self.screen_onoff_by_usb;
+		b[13] = self.reserve1;
+		b[14] = self.reserve2;
+		b[15] = self.reserve3;
+		b[16] = self.reserve4;
+		b[17] = self.reserve5;
+		b[18] = self.power_style;
+		b[19] = self.image_switch_random;
+		let mode = self.image_switch_mode.to_le_bytes();
+		b[20] = mode[0];
+		b[21] = mode[1];
+		b[22] = self.image_switch_interval;
+		b[23] = self.srgb_style;
+		b
+	}
+
+	// Typed setters, chainable: `config.brightness(20)?.rotation(Rotation::Deg90)`.
+	// Only setters that can reject their value return a Result.
+	pub fn brightness(&mut self, level: u8) -> Result<&mut Self> {
+		if level > MAX_BRIGHTNESS {
+			return Err(HubError::InvalidConfig(format!(
+				"brightness must be 0-{MAX_BRIGHTNESS}"
+			)));
+		}
+		self.screen_brightness = level;
+		Ok(self)
+	}
+
+	pub fn rotation(&mut self, rotation: Rotation) -> &mut Self {
+		self.screen_dir = rotation.code();
+		self
+	}
+
+	pub fn page(&mut self, page: u8) -> &mut Self {
+		self.memory_page = page;
+		self
+	}
+
+	pub fn interval(&mut self, seconds: u8) -> &mut Self {
+		self.image_switch_interval = seconds;
+		self
+	}
+
+	pub fn random(&mut self, on: bool) -> &mut Self {
+		self.image_switch_random = on as u8;
+		self
+	}
+
+	pub fn crop(&mut self, on: bool) -> &mut Self {
+		self.album_cut_black = on as u8;
+		self
+	}
+
+	pub fn screen_follows_usb(&mut self, on: bool) -> &mut Self {
+		self.screen_onoff_by_usb = on as u8;
+		self
+	}
+
+	pub fn shake_sensitivity(&mut self, level: u8) -> &mut Self {
+		self.fun_shake_sens = level;
+		self
+	}
+
+	pub fn power_style(&mut self, style: u8) -> &mut Self {
+		self.power_style = style;
+		self
+	}
+
+	pub fn srgb_style(&mut self, style: u8) -> &mut Self {
+		self.srgb_style = style;
+		self
+	}
+
+	// The firmware's switch-mode and button-action codes aren't documented, so they
+	// stay raw numbers rather than guessing at enum variants.
+	pub fn switch_mode(&mut self, mode: u16) -> &mut Self {
+		self.image_switch_mode = mode;
+		self
+	}
+
+	pub fn button(&mut self, button: Button, action: u8) -> &mut Self {
+		match button {
+			Button::SingleClick => self.fun_single_click = action,
+			Button::DoubleClick => self.fun_double_click = action,
+			Button::Tilt => self.fun_tilt = action,
+			Button::Shake => self.fun_shake = action,
+		}
+		self
+	}
+
+	// String form of the setters above, for the CLI and config files.
+	pub fn set_field(&mut self, name: &str, value: &str) -> Result<()> {
+		if let Ok(button) = name.parse::<Button>() {
+			self.button(button, parse_value(name, value)?);
+			return Ok(());
+		}
+		match name {
+			"brightness" | "screen_brightness" => {
+				self.brightness(parse_value(name, value)?)?;
+			}
+			"rotation" | "screen_dir" => {
+				self.rotation(value.parse()?);
+			}
+			"page" | "memory_page" => {
+				self.page(parse_value(name, value)?);
+			}
+			"interval" | "image_switch_interval" => {
+				self.interval(parse_value(name, value)?);
+			}
+			"random" | "image_switch_random" => {
+				self.random(parse_flag(name, value)?);
+			}
+			"crop" | "album_cut_black" => {
+				self.crop(parse_flag(name, value)?);
+			}
+			"cut_frame" | "album_cut_frame" => {
+				self.album_cut_frame = parse_flag(name, value)? as u8;
+			}
+			"language" | "cur_lang" => {
+				self.cur_lang = parse_value(name, value)?;
+			}
+			"web_help" | "web_help_onoff" => {
+				self.web_help_onoff = parse_flag(name, value)? as u8;
+			}
+			"screen_onoff_by_usb" => {
+				self.screen_follows_usb(parse_flag(name, value)?);
+			}
+			"shake_sens" | "fun_shake_sens" => {
+				self.shake_sensitivity(parse_value(name, value)?);
+			}
+			"power_style" => {
+				self.power_style(parse_value(name, value)?);
+			}
+			"srgb_style" => {
+				self.srgb_style(parse_value(name, value)?);
+			}
+			"switch_mode" | "image_switch_mode" => {
+				self.switch_mode(parse_value(name, value)?);
+			}
+			_ => {
+				return Err(HubError::InvalidConfig(format!(
+					"unknown config field: {name}"
+				)))
+			}
+		}
+		Ok(())
+	}
+
+	// Reads a field by the names set_field takes, in the form set_field accepts back.
+	pub fn get_field(&self, name: &str) -> Result<u32> {
+		if let Ok(button) = name.parse::<Button>() {
+			return Ok(self.button_action(button) as u32);
+		}
+		let value = match name {
+			"brightness" | "screen_brightness" => self.screen_brightness as u32,
+			"rotation" | "screen_dir" => match self.screen_rotation() {
+				Some(rotation) => rotation.degrees() as u32,
+				None => {
+					return Err(HubError::InvalidConfig(format!(
+						"device reports an unknown rotation code ({})",
+						self.screen_dir
+					)))
+				}
+			},
+			"page" | "memory_page" => self.memory_page as u32,
+			"interval" | "image_switch_interval" => self.image_switch_interval as u32,
+			"random" | "image_switch_random" => self.image_switch_random as u32,
+			"crop" | "album_cut_black" => self.album_cut_black as u32,
+			"cut_frame" | "album_cut_frame" => self.album_cut_frame as u32,
+			"screen_onoff_by_usb" => self.screen_onoff_by_usb as u32,
+			"shake_sens" | "fun_shake_sens" => self.fun_shake_sens as u32,
+			"power_style" => self.power_style as u32,
+			"srgb_style" => self.srgb_style as u32,
+			"switch_mode" | "image_switch_mode" => self.image_switch_mode as u32,
+			"language" | "cur_lang" => self.cur_lang as u32,
+			"web_help" | "web_help_onoff" => self.web_help_onoff as u32,
+			_ => {
+				return Err(HubError::InvalidConfig(format!(
+					"unknown config field: {name}"
+				)))
+			}
+		};
+		Ok(value)
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+	Deg0,
+	Deg90,
+	Deg180,
+	Deg270,
+}
+
+impl Rotation {
+	pub fn degrees(self) -> u16 {
+		match self {
+			Rotation::Deg0 => 0,
+			Rotation::Deg90 => 90,
+			Rotation::Deg180 => 180,
+			Rotation::Deg270 => 270,
+		}
+	}
+
+	pub fn from_degrees(degrees: u16) -> Option<Self> {
+		match degrees {
+			0 => Some(Rotation::Deg0),
+			90 => Some(Rotation::Deg90),
+			180 => Some(Rotation::Deg180),
+			270 => Some(Rotation::Deg270),
+			_ => None,
+		}
+	}
+
+	// The firmware numbers orientations 0, 180, 90, 270.
+	fn code(self) -> u8 {
+		match self {
+			Rotation::Deg0 => 0,
+			Rotation::Deg180 => 1,
+			Rotation::Deg90 => 2,
+			Rotation::Deg270 => 3,
+		}
+	}
+
+	fn from_code(code: u8) -> Option<Self> {
+		match code {
+			0 => Some(Rotation::Deg0),
+			1 => Some(Rotation::Deg180),
+			2 => Some(Rotation::Deg90),
+			3 => Some(Rotation::Deg270),
+			_ => None,
+		}
+	}
+}
+
+impl FromStr for Rotation {
+	type Err = HubError;
+
+	fn from_str(s: &str) -> Result<Self> {
+		s.parse()
+			.ok()
+			.and_then(Rotation::from_degrees)
+			.ok_or_else(|| {
+				HubError::InvalidConfig("rotation must be 0, 90, 180, or 270".to_string())
+			})
+	}
+}
+
+impl fmt::Display for Rotation {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}\u{00b0}", self.degrees())
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+	SingleClick,
+	DoubleClick,
+	Tilt,
+	Shake,
+}
+
+impl FromStr for Button {
+	type Err = HubError;
+
+	fn from_str(s: &str) -> Result<Self> {
+		match s {
+			"single_click" | "fun_single_click" => Ok(Button::SingleClick),
+			"double_click" | "fun_double_click" => Ok(Button::DoubleClick),
+			"tilt" | "fun_tilt" => Ok(Button::Tilt),
+			"shake" | "fun_shake" => Ok(Button::Shake),
+			_ => Err(HubError::InvalidConfig(format!("unknown button: {s}"))),
+		}
+	}
+}
+
+fn parse_flag(name: &str, value: &str) -> Result<bool> {
+	match value {
+		"0" => Ok(false),
+		"1" => Ok(true),
+		_ => Err(HubError::InvalidConfig(format!(
+			"invalid value '{value}' for {name}: expected 0 or 1"
+		))),
+	}
+}
+
+fn parse_value<T: FromStr>(name: &str, value: &str) -> Result<T>
+where
+	T::Err: fmt::Display,
+{
+	value
+		.parse()
+		.map_err(|e| HubError::InvalidConfig(format!("invalid value '{value}' for {name}: {e}")))
+}
+
+impl fmt::Display for DeviceConfig {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let rotation = match self.screen_rotation() {
+			Some(rotation) => rotation.to_string(),
+			None => "unknown".to_string(),
+		};
+		writeln!(f, "Screen brightness:    {}/30", self.screen_brightness)?;
+		writeln!(f, "Screen rotation:      {rotation}")?;
+		writeln!(f, "Screen on/off by USB: {}", self.screen_onoff_by_usb != 0)?;
+		writeln!(f, "Memory page:          {}", self.memory_page)?;
+		writeln!(f, "Album crop to fill:   {}", self.album_cut_black != 0)?;
+		writeln!(f, "Album cut frame:      {}", self.album_cut_frame != 0)?;
+		writeln!(f, "Image switch random:  {}", self.image_switch_random != 0)?;
+		writeln!(f, "Image switch mode:    {}", self.image_switch_mode)?;
+		writeln!(
+			f,
+			"Image switch interval:{} sec",
+			self.image_switch_interval
+		)?;
+		writeln!(f, "Single click:         {}", self.fun_single_click)?;
+		writeln!(f, "Double click:         {}", self.fun_double_click)?;
+		writeln!(f, "Tilt:                 {}", self.fun_tilt)?;
+		writeln!(f, "Shake:                {}", self.fun_shake)?;
+		writeln!(f, "Shake sensitivity:    {}", self.fun_shake_sens)?;
+		writeln!(f, "Power style:          {}", self.power_style)?;
+		writeln!(f, "sRGB style:           {}", self.srgb_style)?;
+		writeln!(f, "Language:             {}", self.cur_lang)?;
+		write!(f, "Web help:             {}", self.web_help_onoff != 0)
+	}
+}
@@ -1,6 +1,7 @@
-use crate::consts::{FLASH_HEADER_AREA, FRAME_PIXEL_SIZE};
+use crate::consts::{FLASH_ERASE_BLOCK, FLASH_HEADER_AREA, FRAME_PIXEL_SIZE};
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DeviceInfo {
 	pub hw_id: u32,
 	pub fw_ver: u32,
@@ -12,6 +13,10 @@ impl DeviceInfo {
 		max_frames(self.flash_size)
 	}
 
+	pub fn erase_block_size(&self) -> usize {
+		FLASH_ERASE_BLOCK
+	}
+
 	pub fn fw_version_string(&self) -> String {
 		let major = (self.fw_ver >> 16) & 0xFF;
 		let minor = (self.fw_ver >> 8) & 0xFF;
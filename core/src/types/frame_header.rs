@@ -1,8 +1,8 @@
-use anyhow::{bail, Result};
-
 use crate::consts::{FRAME_HEADER_SIZE, FRAME_MAGIC};
+use crate::error::{HubError, Result};
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FrameHeader {
 	pub width: u16,
 	pub height: u16,
@@ -36,9 +36,17 @@ impl FrameHeader {
 		buf[pos..pos + 4].copy_from_slice(&hdr_crc.to_le_bytes());
 	}
 
+	// Whether a header slot is occupied; anything after the first empty slot is free
+	// for host metadata.
+	pub fn is_present(buf: &[u8]) -> bool {
+		buf.get(..4) == Some(&FRAME_MAGIC.to_le_bytes()[..])
+	}
+
 	pub fn read_from(buf: &[u8]) -> Result<Option<Self>> {
 		if buf.len() < FRAME_HEADER_SIZE {
-			bail!("frame header too short");
+			return Err(HubError::ProtocolViolation(
+				"frame header too short".to_string(),
+			));
 		}
 		let magic = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
 		if magic != FRAME_MAGIC {
@@ -55,7 +63,11 @@ impl FrameHeader {
 
 		let expected_hdr_crc = crc32fast::hash(&buf[..24]);
 		if expected_hdr_crc != header_crc32 {
-			bail!("frame header CRC mismatch");
+			return Err(HubError::CrcMismatch {
+				what: "frame header",
+				expected: expected_hdr_crc,
+				actual: header_crc32,
+			});
 		}
 
 		Ok(Some(FrameHeader {
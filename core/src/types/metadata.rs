@@ -0,0 +1,158 @@
+use crate::error::{HubError, Result};
+
+// Host-side metadata kept in the part of the flash header area the firmware never
+// reads: a 16-byte trailer in the last bytes of the area, preceded by a body that
+// grows backwards into header slots no album uses.
+//
+// Trailer (little-endian):
+//   0..4   magic "HMMD"
+//   4      layout version
+//   5      reserved, 0
+//   6..8   body length
+//   8..12  CRC32 of the body
+//   12..16 sync hash (0 = none)
+//
+// The body is a list of records: tag (u8), album index (u16), length (u8), data.
+// Readers skip tags they don't know, so new record types don't need a new layout
+// version; anything that changes the trailer or record framing does.
+pub const METADATA_MAGIC: &[u8; 4] = b"HMMD";
+pub const METADATA_VERSION: u8 = 1;
+pub const METADATA_TRAILER_SIZE: usize = 16;
+
+const TAG_FLAGS: u8 = 1;
+const TAG_LABEL: u8 = 2;
+const TAG_SOURCE_HASH: u8 = 3;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AlbumMeta {
+	// No bits are assigned yet; writers must preserve bits they don't understand.
+	pub flags: u8,
+	pub label: Option<String>,
+	pub source_hash: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FlashMetadata {
+	pub sync_hash: Option<u32>,
+	pub albums: Vec<AlbumMeta>,
+}
+
+impl FlashMetadata {
+	pub fn encode_body(&self) -> Result<Vec<u8>> {
+		let mut body = Vec::new();
+		for (i, album) in self.albums.iter().enumerate() {
+			let index = u16::try_from(i).map_err(|_| {
+				HubError::CapacityExceeded(format!("metadata for album {i} can't be indexed"))
+			})?;
+			if album.flags != 0 {
+				push_record(&mut body, TAG_FLAGS, index, &[album.flags])?;
+			}
+			if let Some(label) = &album.label {
+				push_record(&mut body, TAG_LABEL, index, label.as_bytes())?;
+			}
+			if let Some(hash) = album.source_hash {
+				push_record(&mut body, TAG_SOURCE_HASH, index, &hash.to_le_bytes())?;
+			}
+		}
+		Ok(body)
+	}
+
+	pub fn trailer(&self, body: &[u8]) -> [u8; METADATA_TRAILER_SIZE] {
+		let mut t = [0u8; METADATA_TRAILER_SIZE];
+		t[0..4].copy_from_slice(METADATA_MAGIC);
+		t[4] = METADATA_VERSION;
+		t[6..8].copy_from_slice(&(body.len() as u16).to_le_bytes());
+		t[8..12].copy_from_slice(&crc32fast::hash(body).to_le_bytes());
+		t[12..16].copy_from_slice(&self.sync_hash.unwrap_or(0).to_le_bytes());
+		t
+	}
+
+	// `area` is the whole header area. Returns None when no metadata was written.
+	pub fn decode(area: &[u8]) -> Result<Option<Self>> {
+		let Some(trailer_start) = area.len().checked_sub(METADATA_TRAILER_SIZE) else {
+			return Ok(None);
+		};
+		let t = &area[trailer_start..];
+		if &t[0..4] != METADATA_MAGIC {
+			return Ok(None);
+		}
+		if t[4] > METADATA_VERSION {
+			return Err(HubError::ProtocolViolation(format!(
+				"flash metadata layout v{} is newer than this tool understands (v{METADATA_VERSION})",
+				t[4]
+			)));
+		}
+		let len = u16::from_le_bytes([t[6], t[7]]) as usize;
+		let body = trailer_start
+			.checked_sub(len)
+			.map(|start| &area[start..trailer_start])
+			.ok_or_else(|| {
+				HubError::ProtocolViolation(format!("flash metadata claims {len} bytes"))
+			})?;
+		let expected = u32::from_le_bytes([t[8], t[9], t[10], t[11]]);
+		let actual = crc32fast::hash(body);
+		if expected != actual {
+			return Err(HubError::CrcMismatch {
+				what: "flash metadata",
+				expected,
+				actual,
+			});
+		}
+		let sync_hash = u32::from_le_bytes([t[12], t[13], t[14], t[15]]);
+
+		let mut meta = FlashMetadata {
+			sync_hash: (sync_hash != 0).then_some(sync_hash),
+			albums: Vec::new(),
+		};
+		let mut pos = 0;
+		while pos < body.len() {
+			if pos + 4 > body.len() {
+				return Err(HubError::ProtocolViolation(
+					"truncated flash metadata record".to_string(),
+				));
+			}
+			let tag = body[pos];
+			let index = u16::from_le_bytes([body[pos + 1], body[pos + 2]]) as usize;
+			let len = body[pos + 3] as usize;
+			let data = body.get(pos + 4..pos + 4 + len).ok_or_else(|| {
+				HubError::ProtocolViolation("truncated flash metadata record".to_string())
+			})?;
+			pos += 4 + len;
+
+			if meta.albums.len() <= index {
+				meta.albums.resize(index + 1, AlbumMeta::default());
+			}
+			let album = &mut meta.albums[index];
+			match (tag, data) {
+				(TAG_FLAGS, [flags]) => album.flags = *flags,
+				(TAG_LABEL, _) => album.label = Some(String::from_utf8_lossy(data).into_owned()),
+				(TAG_SOURCE_HASH, &[a, b, c, d]) => {
+					album.source_hash = Some(u32::from_le_bytes([a, b, c, d]))
+				}
+				(TAG_FLAGS | TAG_SOURCE_HASH, _) => {
+					return Err(HubError::ProtocolViolation(format!(
+						"flash metadata record {tag} has {len} bytes"
+					)))
+				}
+				_ => {}
+			}
+		}
+		Ok(Some(meta))
+	}
+}
+
+fn push_record(body: &mut Vec<u8>, tag: u8, index: u16, data: &[u8]) -> Result<()> {
+	let len = u8::try_from(data.len()).map_err(|_| {
+		HubError::CapacityExceeded(format!(
+			"metadata for album {index} is {} bytes, at most 255 fit in a record",
+			data.len()
+		))
+	})?;
+	body.push(tag);
+	body.extend_from_slice(&index.to_le_bytes());
+	body.push(len);
+	body.extend_from_slice(data);
+	Ok(())
+}
@@ -0,0 +1,13 @@
+mod album;
+mod config;
+mod device_info;
+mod frame_header;
+mod metadata;
+
+pub use album::Album;
+pub use config::{
+	config_field_names, config_fields_help, Button, DeviceConfig, Rotation, MAX_BRIGHTNESS,
+};
+pub use device_info::{max_frames, DeviceInfo};
+pub use frame_header::FrameHeader;
+pub use metadata::{AlbumMeta, FlashMetadata, METADATA_TRAILER_SIZE, METADATA_VERSION};
@@ -0,0 +1,10 @@
+// Regenerates include/hm_hub.h from the extern functions in src/lib.rs, so the
+// header in the repo always matches the library it ships with.
+fn main() {
+	let dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+	println!("cargo:rerun-if-changed=src/lib.rs");
+	println!("cargo:rerun-if-changed=cbindgen.toml");
+	cbindgen::generate(&dir)
+		.expect("generating hm_hub.h")
+		.write_to_file(format!("{dir}/include/hm_hub.h"));
+}
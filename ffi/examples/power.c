@@ -0,0 +1,37 @@
+/*
+ * Prints the hub's bus voltage and port currents once a second.
+ *
+ *   cargo build --release -p hm-hub-ffi
+ *   cc ffi/examples/power.c -Iffi/include target/release/libhm_hub_ffi.a \
+ *       -ludev -lpthread -ldl -lm -o hm-power
+ *   ./hm-power [port]
+ */
+#include <stdio.h>
+
+#include "hm_hub.h"
+
+int main(int argc, char **argv) {
+	HmHub *hub;
+	if (hm_hub_open(argc > 1 ? argv[1] : NULL, &hub) != HM_HUB_STATUS_OK) {
+		fprintf(stderr, "open: %s\n", hm_hub_last_error());
+		return 1;
+	}
+
+	HmHubConfig config;
+	if (hm_hub_read_config(hub, &config) == HM_HUB_STATUS_OK)
+		printf("brightness %u, page %u\n", config.brightness, config.memory_page);
+
+	for (;;) {
+		HmHubPower power;
+		if (hm_hub_read_power(hub, &power) != HM_HUB_STATUS_OK) {
+			fprintf(stderr, "power: %s\n", hm_hub_last_error());
+			break;
+		}
+		printf("%.2f V  %u / %u / %u mA\n", power.bus_voltage / 1000.0,
+		       power.current_port1, power.current_port2, power.current_port3);
+		fflush(stdout);
+	}
+
+	hm_hub_close(hub);
+	return 1;
+}
@@ -0,0 +1,168 @@
+#ifndef HM_HUB_H
+#define HM_HUB_H
+
+/* Generated by cbindgen from ffi/src/lib.rs; do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Result of every call. Anything but `HM_HUB_STATUS_OK` leaves a message for
+ * `hm_hub_last_error`.
+ */
+typedef enum HmHubStatus {
+  HM_HUB_STATUS_OK = 0,
+  /**
+   * A required pointer was null or a string wasn't UTF-8.
+   */
+  HM_HUB_STATUS_INVALID_ARGUMENT,
+  /**
+   * No hub is plugged in.
+   */
+  HM_HUB_STATUS_NOT_FOUND,
+  /**
+   * The hub didn't answer in time.
+   */
+  HM_HUB_STATUS_TIMEOUT,
+  /**
+   * The port went away.
+   */
+  HM_HUB_STATUS_DISCONNECTED,
+  /**
+   * A reply was corrupt or unexpected, or the hub gave up on a transfer.
+   */
+  HM_HUB_STATUS_PROTOCOL,
+  /**
+   * The images don't fit in flash.
+   */
+  HM_HUB_STATUS_CAPACITY,
+  /**
+   * Unknown config field or out-of-range value.
+   */
+  HM_HUB_STATUS_INVALID_CONFIG,
+  /**
+   * Serial port or file error.
+   */
+  HM_HUB_STATUS_IO,
+  /**
+   * Anything else, including images that can't be decoded.
+   */
+  HM_HUB_STATUS_OTHER,
+} HmHubStatus;
+
+/**
+ * An open connection to a hub. Create with `hm_hub_open`, free with `hm_hub_close`.
+ */
+typedef struct HmHub HmHub;
+
+/**
+ * The commonly used config fields, as `hm-hub config` shows them.
+ */
+typedef struct HmHubConfig {
+  /**
+   * 0-30.
+   */
+  uint8_t brightness;
+  /**
+   * 0, 90, 180 or 270; 0xFFFF if the device reports something else.
+   */
+  uint16_t rotation;
+  uint8_t memory_page;
+  bool crop;
+  bool random;
+  uint8_t interval_secs;
+  uint16_t switch_mode;
+  bool screen_follows_usb;
+} HmHubConfig;
+
+/**
+ * One reading from the power monitor.
+ */
+typedef struct HmHubPower {
+  /**
+   * Millivolts.
+   */
+  uint16_t bus_voltage;
+  /**
+   * Milliamps per downstream port.
+   */
+  uint16_t current_port1;
+  uint16_t current_port2;
+  uint16_t current_port3;
+} HmHubPower;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Message for the last failed call on this thread, or NULL if none has failed.
+ * Valid until the next failing call on the same thread.
+ */
+const char *hm_hub_last_error(void);
+
+/**
+ * Connects to a hub and handshakes. `port` is a serial port path, `tcp://host:port`
+ * or `mock:`; NULL auto-detects the hub.
+ *
+ * # Safety
+ *
+ * `port` must be NULL or a NUL-terminated string, and `out` must be writable.
+ */
+enum HmHubStatus hm_hub_open(const char *port, struct HmHub **out);
+
+/**
+ * Closes the connection. NULL is ignored.
+ *
+ * # Safety
+ *
+ * `hub` must be NULL or a handle from `hm_hub_open` that hasn't been closed.
+ */
+void hm_hub_close(struct HmHub *hub);
+
+/**
+ * Reads the device config into `out`.
+ *
+ * # Safety
+ *
+ * `hub` must be an open handle and `out` must be writable.
+ */
+enum HmHubStatus hm_hub_read_config(struct HmHub *hub, struct HmHubConfig *out);
+
+/**
+ * Sets one config field by name, as `hm-hub config set <field> <value>` does, and
+ * writes the config back.
+ *
+ * # Safety
+ *
+ * `hub` must be an open handle; `field` and `value` must be NUL-terminated strings.
+ */
+enum HmHubStatus hm_hub_set_field(struct HmHub *hub, const char *field, const char *value);
+
+/**
+ * Converts an image or GIF and replaces the device's flash with it. `crop` fills
+ * the screen instead of letterboxing.
+ *
+ * # Safety
+ *
+ * `hub` must be an open handle and `path` a NUL-terminated string.
+ */
+enum HmHubStatus hm_hub_upload_file(struct HmHub *hub, const char *path, bool crop);
+
+/**
+ * Waits for the next power report, which the device sends about once a second.
+ *
+ * # Safety
+ *
+ * `hub` must be an open handle and `out` must be writable.
+ */
+enum HmHubStatus hm_hub_read_power(struct HmHub *hub, struct HmHubPower *out);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* HM_HUB_H */
@@ -0,0 +1,262 @@
+//! C bindings for the core device operations: open a hub, read and change its
+//! config, upload an image and read the power monitor. `include/hm_hub.h` is
+//! generated from this file by the build script.
+//!
+//! Every function returns an `HmHubStatus`; on failure `hm_hub_last_error` gives
+//! the message. Handles may be used from any thread, but not from two at once.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::Path;
+use std::ptr;
+
+use hm_hub::image::{load_image, LoadOptions};
+use hm_hub::{build_flash_buffer, detect_port, Device, HubError};
+
+/// An open connection to a hub. Create with `hm_hub_open`, free with `hm_hub_close`.
+pub struct HmHub(Device);
+
+/// Result of every call. Anything but `HM_HUB_STATUS_OK` leaves a message for
+/// `hm_hub_last_error`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HmHubStatus {
+	Ok = 0,
+	/// A required pointer was null or a string wasn't UTF-8.
+	InvalidArgument,
+	/// No hub is plugged in.
+	NotFound,
+	/// The hub didn't answer in time.
+	Timeout,
+	/// The port went away.
+	Disconnected,
+	/// A reply was corrupt or unexpected, or the hub gave up on a transfer.
+	Protocol,
+	/// The images don't fit in flash.
+	Capacity,
+	/// Unknown config field or out-of-range value.
+	InvalidConfig,
+	/// Serial port or file error.
+	Io,
+	/// Anything else, including images that can't be decoded.
+	Other,
+}
+
+/// The commonly used config fields, as `hm-hub config` shows them.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HmHubConfig {
+	/// 0-30.
+	pub brightness: u8,
+	/// 0, 90, 180 or 270; 0xFFFF if the device reports something else.
+	pub rotation: u16,
+	pub memory_page: u8,
+	pub crop: bool,
+	pub random: bool,
+	pub interval_secs: u8,
+	pub switch_mode: u16,
+	pub screen_follows_usb: bool,
+}
+
+/// One reading from the power monitor.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HmHubPower {
+	/// Millivolts.
+	pub bus_voltage: u16,
+	/// Milliamps per downstream port.
+	pub current_port1: u16,
+	pub current_port2: u16,
+	pub current_port3: u16,
+}
+
+struct Failure(HmHubStatus, String);
+
+impl From<HubError> for Failure {
+	fn from(e: HubError) -> Self {
+		let status = match e {
+			HubError::DeviceNotFound { .. } => HmHubStatus::NotFound,
+			HubError::Timeout(_) => HmHubStatus::Timeout,
+			HubError::Disconnected => HmHubStatus::Disconnected,
+			HubError::CrcMismatch { .. }
+			| HubError::ProtocolViolation(_)
+			| HubError::FlashFailed(_) => HmHubStatus::Protocol,
+			HubError::CapacityExceeded(_) => HmHubStatus::Capacity,
+			HubError::InvalidConfig(_) => HmHubStatus::InvalidConfig,
+			HubError::Serial(_) | HubError::Io(_) => HmHubStatus::Io,
+			HubError::Cancelled => HmHubStatus::Other,
+		};
+		Failure(status, e.to_string())
+	}
+}
+
+thread_local! {
+	static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+// Runs one call, turning errors and panics into a status and a stored message.
+fn call(f: impl FnOnce() -> Result<(), Failure>) -> HmHubStatus {
+	let Failure(status, message) = match catch_unwind(AssertUnwindSafe(f)) {
+		Ok(Ok(())) => return HmHubStatus::Ok,
+		Ok(Err(failure)) => failure,
+		Err(_) => Failure(HmHubStatus::Other, "internal error (panic)".to_string()),
+	};
+	let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+	LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+	status
+}
+
+fn invalid(what: &str) -> Failure {
+	Failure(HmHubStatus::InvalidArgument, format!("{what} is invalid"))
+}
+
+unsafe fn hub<'a>(hub: *mut HmHub) -> Result<&'a mut Device, Failure> {
+	hub.as_mut().map(|h| &mut h.0).ok_or_else(|| invalid("hub"))
+}
+
+unsafe fn string<'a>(s: *const c_char, what: &str) -> Result<&'a str, Failure> {
+	if s.is_null() {
+		return Err(invalid(what));
+	}
+	CStr::from_ptr(s).to_str().map_err(|_| invalid(what))
+}
+
+/// Message for the last failed call on this thread, or NULL if none has failed.
+/// Valid until the next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn hm_hub_last_error() -> *const c_char {
+	LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |m| m.as_ptr()))
+}
+
+/// Connects to a hub and handshakes. `port` is a serial port path, `tcp://host:port`
+/// or `mock:`; NULL auto-detects the hub.
+///
+/// # Safety
+///
+/// `port` must be NULL or a NUL-terminated string, and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn hm_hub_open(port: *const c_char, out: *mut *mut HmHub) -> HmHubStatus {
+	call(|| {
+		if out.is_null() {
+			return Err(invalid("out"));
+		}
+		let port = if port.is_null() {
+			detect_port()?
+		} else {
+			string(port, "port")?.to_string()
+		};
+		let dev = Device::builder(&port).open()?;
+		*out = Box::into_raw(Box::new(HmHub(dev)));
+		Ok(())
+	})
+}
+
+/// Closes the connection. NULL is ignored.
+///
+/// # Safety
+///
+/// `hub` must be NULL or a handle from `hm_hub_open` that hasn't been closed.
+#[no_mangle]
+pub unsafe extern "C" fn hm_hub_close(hub: *mut HmHub) {
+	if !hub.is_null() {
+		drop(Box::from_raw(hub));
+	}
+}
+
+/// Reads the device config into `out`.
+///
+/// # Safety
+///
+/// `hub` must be an open handle and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn hm_hub_read_config(hub: *mut HmHub, out: *mut HmHubConfig) -> HmHubStatus {
+	call(|| {
+		let dev = self::hub(hub)?;
+		let out = out.as_mut().ok_or_else(|| invalid("out"))?;
+		let config = dev.read_config()?;
+		*out = HmHubConfig {
+			brightness: config.screen_brightness,
+			rotation: config.screen_rotation().map_or(u16::MAX, |r| r.degrees()),
+			memory_page: config.memory_page,
+			crop: config.album_cut_black != 0,
+			random: config.image_switch_random != 0,
+			interval_secs: config.image_switch_interval,
+			switch_mode: config.image_switch_mode,
+			screen_follows_usb: config.screen_onoff_by_usb != 0,
+		};
+		Ok(())
+	})
+}
+
+/// Sets one config field by name, as `hm-hub config set <field> <value>` does, and
+/// writes the config back.
+///
+/// # Safety
+///
+/// `hub` must be an open handle; `field` and `value` must be NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn hm_hub_set_field(
+	hub: *mut HmHub,
+	field: *const c_char,
+	value: *const c_char,
+) -> HmHubStatus {
+	call(|| {
+		let dev = self::hub(hub)?;
+		let (field, value) = (string(field, "field")?, string(value, "value")?);
+		let mut config = dev.read_config()?;
+		config.set_field(field, value)?;
+		dev.write_config(&config)?;
+		Ok(())
+	})
+}
+
+/// Converts an image or GIF and replaces the device's flash with it. `crop` fills
+/// the screen instead of letterboxing.
+///
+/// # Safety
+///
+/// `hub` must be an open handle and `path` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn hm_hub_upload_file(
+	hub: *mut HmHub,
+	path: *const c_char,
+	crop: bool,
+) -> HmHubStatus {
+	call(|| {
+		let dev = self::hub(hub)?;
+		let path = string(path, "path")?;
+		let opts = LoadOptions {
+			crop,
+			temperature: None,
+			similarity_threshold: None,
+			raw_size: None,
+		};
+		let album = load_image(Path::new(path), &opts)
+			.map_err(|e| Failure(HmHubStatus::Other, format!("{e:#}")))?;
+		let flash = build_flash_buffer(&[album], dev.info.flash_size)?;
+		dev.upload_flash_with(&flash, &mut ())?;
+		Ok(())
+	})
+}
+
+/// Waits for the next power report, which the device sends about once a second.
+///
+/// # Safety
+///
+/// `hub` must be an open handle and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn hm_hub_read_power(hub: *mut HmHub, out: *mut HmHubPower) -> HmHubStatus {
+	call(|| {
+		let dev = self::hub(hub)?;
+		let out = out.as_mut().ok_or_else(|| invalid("out"))?;
+		let stats = dev.read_power()?;
+		*out = HmHubPower {
+			bus_voltage: stats.bus_voltage,
+			current_port1: stats.current_port1,
+			current_port2: stats.current_port2,
+			current_port3: stats.current_port3,
+		};
+		Ok(())
+	})
+}
@@ -0,0 +1,88 @@
+use std::ffi::{CStr, CString};
+use std::ptr;
+
+use hm_hub::consts::{DISPLAY_HEIGHT, DISPLAY_WIDTH, FRAME_PIXEL_SIZE};
+use hm_hub_ffi::*;
+
+fn open() -> *mut HmHub {
+	let port = CString::new("mock:").unwrap();
+	let mut hub = ptr::null_mut();
+	assert_eq!(
+		unsafe { hm_hub_open(port.as_ptr(), &mut hub) },
+		HmHubStatus::Ok
+	);
+	assert!(!hub.is_null());
+	hub
+}
+
+fn last_error() -> String {
+	unsafe { CStr::from_ptr(hm_hub_last_error()) }
+		.to_string_lossy()
+		.into_owned()
+}
+
+#[test]
+fn config_power_and_upload_round_trip() {
+	let hub = open();
+	unsafe {
+		let (field, value) = (
+			CString::new("brightness").unwrap(),
+			CString::new("12").unwrap(),
+		);
+		assert_eq!(
+			hm_hub_set_field(hub, field.as_ptr(), value.as_ptr()),
+			HmHubStatus::Ok
+		);
+		let mut config = HmHubConfig::default();
+		assert_eq!(hm_hub_read_config(hub, &mut config), HmHubStatus::Ok);
+		assert_eq!(config.brightness, 12);
+
+		let mut power = HmHubPower::default();
+		assert_eq!(hm_hub_read_power(hub, &mut power), HmHubStatus::Ok);
+		assert!(power.bus_voltage > 0);
+
+		// A headered .rgb565 file, which needs no decoding.
+		let path = std::env::temp_dir().join(format!("hm-hub-ffi-{}.rgb565", std::process::id()));
+		let mut data = b"R565".to_vec();
+		for field in [DISPLAY_WIDTH as u16, DISPLAY_HEIGHT as u16, 1, 100] {
+			data.extend_from_slice(&field.to_le_bytes());
+		}
+		data.resize(data.len() + FRAME_PIXEL_SIZE, 0x5A);
+		std::fs::write(&path, &data).unwrap();
+		let c_path = CString::new(path.to_str().unwrap()).unwrap();
+		let status = hm_hub_upload_file(hub, c_path.as_ptr(), true);
+		std::fs::remove_file(&path).unwrap();
+		assert_eq!(status, HmHubStatus::Ok, "{}", last_error());
+
+		hm_hub_close(hub);
+	}
+}
+
+#[test]
+fn failures_report_status_and_message() {
+	let hub = open();
+	unsafe {
+		let (field, value) = (
+			CString::new("nonsense").unwrap(),
+			CString::new("1").unwrap(),
+		);
+		assert_eq!(
+			hm_hub_set_field(hub, field.as_ptr(), value.as_ptr()),
+			HmHubStatus::InvalidConfig
+		);
+		assert!(last_error().contains("nonsense"));
+
+		let mut config = HmHubConfig::default();
+		assert_eq!(
+			hm_hub_read_config(ptr::null_mut(), &mut config),
+			HmHubStatus::InvalidArgument
+		);
+		assert_eq!(
+			hm_hub_read_config(hub, ptr::null_mut()),
+			HmHubStatus::InvalidArgument
+		);
+
+		hm_hub_close(hub);
+		hm_hub_close(ptr::null_mut());
+	}
+}
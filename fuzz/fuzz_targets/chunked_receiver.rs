@@ -0,0 +1,17 @@
+#![no_main]
+
+use hm_hub::types::ChunkedReceiver;
+use libfuzzer_sys::fuzz_target;
+
+// Each payload is prefixed by its length, so the fuzzer controls how the input is
+// split, including payloads shorter than the chunk header.
+fuzz_target!(|data: &[u8]| {
+	let mut receiver = ChunkedReceiver::new();
+	let mut rest = data;
+	while let Some((&len, tail)) = rest.split_first() {
+		let (payload, tail) = tail.split_at((len as usize).min(tail.len()));
+		let _ = receiver.feed(payload);
+		let _ = receiver.missing();
+		rest = tail;
+	}
+});
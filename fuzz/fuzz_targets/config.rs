@@ -0,0 +1,14 @@
+#![no_main]
+
+use hm_hub::types::DeviceConfig;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+	let Ok(config) = DeviceConfig::from_bytes(data) else {
+		return;
+	};
+	let bytes = config.to_bytes();
+	assert_eq!(&bytes[..], &data[..bytes.len()]);
+	let _ = config.to_string();
+	let _ = config.screen_rotation();
+});
@@ -0,0 +1,12 @@
+#![no_main]
+
+use hm_hub::flash::{parse_albums, read_metadata};
+use hm_hub::types::FrameHeader;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+	let _ = FrameHeader::read_from(data);
+	let _ = FrameHeader::is_present(data);
+	let _ = parse_albums(data);
+	let _ = read_metadata(data);
+});
@@ -0,0 +1,27 @@
+#![no_main]
+
+use hm_hub::consts::PACKET_SIZE;
+use hm_hub::protocol::{
+	parse_flash_error, parse_handshake, parse_log, parse_power_stats, parse_readback_data, Packet,
+};
+use hm_hub::DeviceEvent;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+	let mut buf = [0u8; PACKET_SIZE];
+	let len = data.len().min(PACKET_SIZE);
+	buf[..len].copy_from_slice(&data[..len]);
+	let _ = Packet::from_bytes(buf);
+
+	// Random bytes almost never carry a valid CRC, so also parse a sealed copy to
+	// reach the payload decoders.
+	let mut packet = Packet { buf };
+	packet.seal();
+	let packet = Packet::from_bytes(packet.buf).expect("sealed packet must pass its CRC");
+	let _ = parse_handshake(&packet);
+	let _ = parse_power_stats(&packet);
+	let _ = parse_log(&packet);
+	let _ = parse_flash_error(&packet);
+	let _ = parse_readback_data(&packet);
+	let _ = DeviceEvent::from_packet(packet);
+});
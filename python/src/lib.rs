@@ -0,0 +1,202 @@
+// Python bindings, built with maturin (see pyproject.toml). The module is named
+// `hm_hub`; the blocking calls release the GIL while they wait on the device.
+
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use hm_hub::image::{load_image, LoadOptions};
+use hm_hub::{build_flash_buffer, detect_port, HubError};
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+
+create_exception!(
+	hm_hub,
+	DeviceError,
+	PyException,
+	"A device or protocol error."
+);
+
+fn err(e: HubError) -> PyErr {
+	DeviceError::new_err(e.to_string())
+}
+
+/// A connection to one hub.
+#[pyclass(module = "hm_hub")]
+struct Device(Mutex<hm_hub::Device>);
+
+impl Device {
+	fn with<T: Send>(
+		&self,
+		py: Python<'_>,
+		f: impl FnOnce(&mut hm_hub::Device) -> Result<T, HubError> + Send,
+	) -> PyResult<T> {
+		py.detach(|| f(&mut self.0.lock().unwrap())).map_err(err)
+	}
+}
+
+#[pymethods]
+impl Device {
+	/// With no port the hub is auto-detected. `mock:` opens an emulated hub.
+	#[new]
+	#[pyo3(signature = (port = None, timeout = 2.0))]
+	fn new(py: Python<'_>, port: Option<String>, timeout: f64) -> PyResult<Self> {
+		let dev = py
+			.detach(|| {
+				let port = match port {
+					Some(p) => p,
+					None => detect_port()?,
+				};
+				hm_hub::Device::builder(&port)
+					.timeout(Duration::from_secs_f64(timeout))
+					.open()
+			})
+			.map_err(err)?;
+		Ok(Device(Mutex::new(dev)))
+	}
+
+	#[getter]
+	fn hw_id(&self) -> u32 {
+		self.0.lock().unwrap().info.hw_id
+	}
+
+	#[getter]
+	fn firmware(&self) -> String {
+		self.0.lock().unwrap().info.fw_version_string()
+	}
+
+	#[getter]
+	fn flash_size(&self) -> u32 {
+		self.0.lock().unwrap().info.flash_size
+	}
+
+	fn read_config(&self, py: Python<'_>) -> PyResult<DeviceConfig> {
+		self.with(py, |dev| dev.read_config()).map(DeviceConfig)
+	}
+
+	fn write_config(&self, py: Python<'_>, config: &DeviceConfig) -> PyResult<()> {
+		let config = config.0.clone();
+		self.with(py, move |dev| dev.write_config(&config))
+	}
+
+	/// Converts the images and GIFs and replaces the device's flash with them.
+	#[pyo3(signature = (*paths, crop = true))]
+	fn upload(&self, py: Python<'_>, paths: Vec<PathBuf>, crop: bool) -> PyResult<()> {
+		let opts = LoadOptions {
+			crop,
+			temperature: None,
+			similarity_threshold: None,
+			raw_size: None,
+		};
+		let albums = py
+			.detach(|| {
+				paths
+					.iter()
+					.map(|p| load_image(p, &opts).map_err(|e| format!("{}: {e:#}", p.display())))
+					.collect::<Result<Vec<_>, _>>()
+			})
+			.map_err(DeviceError::new_err)?;
+		self.with(py, move |dev| {
+			let flash = build_flash_buffer(&albums, dev.info.flash_size)?;
+			dev.upload_flash_with(&flash, &mut ())
+		})
+	}
+
+	/// Waits for the next power report (the device sends one about every second).
+	fn read_power(&self, py: Python<'_>) -> PyResult<PowerStats> {
+		let stats = self.with(py, |dev| dev.read_power())?;
+		Ok(PowerStats {
+			bus_voltage: stats.bus_voltage,
+			current_port1: stats.current_port1,
+			current_port2: stats.current_port2,
+			current_port3: stats.current_port3,
+		})
+	}
+}
+
+/// The device config, as read by `Device.read_config()`.
+#[pyclass(module = "hm_hub")]
+struct DeviceConfig(hm_hub::DeviceConfig);
+
+#[pymethods]
+impl DeviceConfig {
+	#[getter]
+	fn brightness(&self) -> u8 {
+		self.0.screen_brightness
+	}
+
+	/// None if the device reports a value that isn't 0/90/180/270.
+	#[getter]
+	fn rotation(&self) -> Option<u16> {
+		self.0.screen_rotation().map(|r| r.degrees())
+	}
+
+	#[getter]
+	fn page(&self) -> u8 {
+		self.0.memory_page
+	}
+
+	#[getter]
+	fn crop(&self) -> bool {
+		self.0.album_cut_black != 0
+	}
+
+	#[getter]
+	fn random(&self) -> bool {
+		self.0.image_switch_random != 0
+	}
+
+	#[getter]
+	fn interval(&self) -> u8 {
+		self.0.image_switch_interval
+	}
+
+	/// Takes the same field names as `hm-hub config set`; the value may be any
+	/// object whose str() the CLI would accept.
+	fn set_field(&mut self, name: &str, value: &Bound<'_, PyAny>) -> PyResult<()> {
+		let value = value.str()?.to_string();
+		self.0.set_field(name, &value).map_err(err)
+	}
+
+	fn __repr__(&self) -> String {
+		format!(
+			"DeviceConfig(brightness={}, rotation={}, page={}, crop={}, random={}, interval={})",
+			self.brightness(),
+			self.rotation()
+				.map_or("None".to_string(), |r| r.to_string()),
+			self.page(),
+			if self.crop() { "True" } else { "False" },
+			if self.random() { "True" } else { "False" },
+			self.interval()
+		)
+	}
+}
+
+/// Bus voltage in millivolts and per-port current in milliamps.
+#[pyclass(module = "hm_hub", get_all, frozen)]
+struct PowerStats {
+	bus_voltage: u16,
+	current_port1: u16,
+	current_port2: u16,
+	current_port3: u16,
+}
+
+#[pymethods]
+impl PowerStats {
+	fn __repr__(&self) -> String {
+		format!(
+			"PowerStats(bus_voltage={}, current_port1={}, current_port2={}, current_port3={})",
+			self.bus_voltage, self.current_port1, self.current_port2, self.current_port3
+		)
+	}
+}
+
+#[pymodule(name = "hm_hub")]
+fn hm_hub_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+	m.add_class::<Device>()?;
+	m.add_class::<DeviceConfig>()?;
+	m.add_class::<PowerStats>()?;
+	m.add("DeviceError", m.py().get_type::<DeviceError>())?;
+	Ok(())
+}
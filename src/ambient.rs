@@ -0,0 +1,182 @@
+use anyhow::{bail, Context, Result};
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::types::MAX_BRIGHTNESS;
+
+pub enum LightSource {
+	Command(String),
+	File(PathBuf),
+	Iio(PathBuf),
+}
+
+impl LightSource {
+	pub fn iio(device: &str) -> Result<Self> {
+		let dir = if device.contains('/') {
+			PathBuf::from(device)
+		} else {
+			PathBuf::from("/sys/bus/iio/devices").join(device)
+		};
+		for name in [
+			"in_illuminance_input",
+			"in_illuminance_raw",
+			"in_intensity_both_raw",
+		] {
+			let path = dir.join(name);
+			if path.exists() {
+				return Ok(LightSource::Iio(path));
+			}
+		}
+		bail!("no illuminance channel found in {}", dir.display())
+	}
+
+	pub fn read(&self) -> Result<f64> {
+		let text = match self {
+			LightSource::Command(cmd) => {
+				let output = Command::new("sh")
+					.arg("-c")
+					.arg(cmd)
+					.output()
+					.with_context(|| format!("failed to run '{cmd}'"))?;
+				if !output.status.success() {
+					bail!("'{cmd}' exited with {}", output.status);
+				}
+				String::from_utf8_lossy(&output.stdout).into_owned()
+			}
+			LightSource::File(path) | LightSource::Iio(path) => std::fs::read_to_string(path)
+				.with_context(|| format!("failed to read {}", path.display()))?,
+		};
+		let value = text.split_whitespace().next().unwrap_or("");
+		value
+			.parse()
+			.with_context(|| format!("light source produced '{}', expected a number", text.trim()))
+	}
+}
+
+pub struct BrightnessMapper {
+	min_input: f64,
+	max_input: f64,
+	min_brightness: u8,
+	max_brightness: u8,
+	steps: u8,
+	hysteresis: f64,
+	bucket: Option<u8>,
+}
+
+impl BrightnessMapper {
+	pub fn new(
+		min_input: f64,
+		max_input: f64,
+		min_brightness: u8,
+		max_brightness: u8,
+		steps: u8,
+		hysteresis: f64,
+	) -> Result<Self> {
+		if max_input <= min_input {
+			bail!("input max must be greater than input min");
+		}
+		if max_brightness > MAX_BRIGHTNESS || min_brightness > max_brightness {
+			bail!("brightness range must be within 0-{MAX_BRIGHTNESS} with min <= max");
+		}
+		if steps < 2 {
+			bail!("at least 2 steps are required");
+		}
+		Ok(Self {
+			min_input,
+			max_input,
+			min_brightness,
+			max_brightness,
+			steps,
+			hysteresis,
+			bucket: None,
+		})
+	}
+
+	pub fn update(&mut self, value: f64) -> Option<u8> {
+		let pos = ((value - self.min_input) / (self.max_input - self.min_input)).clamp(0.0, 1.0);
+		let steps = self.steps as f64;
+		let width = 1.0 / steps;
+		let candidate = ((pos * steps) as u8).min(self.steps - 1);
+
+		let next = match self.bucket {
+			None => candidate,
+			Some(current) if candidate > current => {
+				let edge = (current as f64 + 1.0) * width;
+				if pos >= edge + self.hysteresis * width {
+					candidate
+				} else {
+					return None;
+				}
+			}
+			Some(current) if candidate < current => {
+				let edge = current as f64 * width;
+				if pos <= edge - self.hysteresis * width {
+					candidate
+				} else {
+					return None;
+				}
+			}
+			Some(_) => return None,
+		};
+
+		self.bucket = Some(next);
+		Some(self.brightness_for(next))
+	}
+
+	fn brightness_for(&self, bucket: u8) -> u8 {
+		let span = (self.max_brightness - self.min_brightness) as f64;
+		let frac = bucket as f64 / (self.steps - 1) as f64;
+		self.min_brightness + (span * frac).round() as u8
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// 0-100 lux in 4 buckets of 25, brightness 0-9 (0, 3, 6, 9), band of 5 lux.
+	fn mapper() -> BrightnessMapper {
+		BrightnessMapper::new(0.0, 100.0, 0, 9, 4, 0.2).unwrap()
+	}
+
+	#[test]
+	fn first_reading_always_sets_brightness() {
+		assert_eq!(mapper().update(60.0), Some(6));
+	}
+
+	#[test]
+	fn rising_needs_to_clear_the_band() {
+		let mut m = mapper();
+		assert_eq!(m.update(10.0), Some(0));
+		assert_eq!(m.update(27.0), None);
+		assert_eq!(m.update(29.0), None);
+		assert_eq!(m.update(31.0), Some(3));
+		assert_eq!(m.update(40.0), None);
+	}
+
+	#[test]
+	fn falling_needs_to_clear_the_band() {
+		let mut m = mapper();
+		assert_eq!(m.update(40.0), Some(3));
+		assert_eq!(m.update(23.0), None);
+		assert_eq!(m.update(21.0), None);
+		assert_eq!(m.update(19.0), Some(0));
+	}
+
+	#[test]
+	fn big_jumps_skip_buckets_and_clamp() {
+		let mut m = mapper();
+		assert_eq!(m.update(-50.0), Some(0));
+		assert_eq!(m.update(500.0), Some(9));
+		assert_eq!(m.update(100.0), None);
+		assert_eq!(m.update(0.0), Some(0));
+	}
+
+	#[test]
+	fn zero_hysteresis_switches_at_the_edge() {
+		let mut m = BrightnessMapper::new(0.0, 100.0, 0, 9, 4, 0.0).unwrap();
+		assert_eq!(m.update(24.0), Some(0));
+		assert_eq!(m.update(25.0), Some(3));
+		assert_eq!(m.update(24.0), Some(0));
+	}
+}
@@ -0,0 +1,118 @@
+use anyhow::{Context, Result};
+use serialport::SerialPort;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+// How often blocked reads wake up to check whether the client or the bridge is gone.
+const POLL: Duration = Duration::from_millis(200);
+
+// Relays raw bytes between the serial port and one TCP client at a time, so a remote
+// `--port tcp://host:port` sees exactly what a local port would. Further clients wait
+// in the listen backlog until the current one disconnects.
+pub fn serve(listener: TcpListener, port: Box<dyn SerialPort>, stop: &AtomicBool) -> Result<()> {
+	listener.set_nonblocking(true)?;
+	let mut port = port;
+	port.set_timeout(POLL)?;
+
+	while !stop.load(Ordering::SeqCst) {
+		match listener.accept() {
+			Ok((stream, peer)) => {
+				eprintln!("Client connected from {peer}");
+				match relay(stream, &mut port, stop) {
+					Ok((up, down)) => {
+						eprintln!(
+							"Client {peer} disconnected ({up} bytes to hub, {down} bytes from hub)"
+						)
+					}
+					Err(e) => eprintln!("Client {peer} dropped: {e:#}"),
+				}
+			}
+			Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(POLL),
+			Err(e) => return Err(e.into()),
+		}
+	}
+	Ok(())
+}
+
+fn relay(
+	stream: TcpStream,
+	port: &mut Box<dyn SerialPort>,
+	stop: &AtomicBool,
+) -> Result<(u64, u64)> {
+	stream.set_nonblocking(false)?;
+	stream.set_nodelay(true)?;
+	stream.set_read_timeout(Some(POLL))?;
+	// Power samples pile up while nobody is connected; the client only wants fresh ones.
+	port.clear(serialport::ClearBuffer::Input)?;
+
+	let done = Arc::new(AtomicBool::new(false));
+	let downstream = {
+		let mut serial = port.try_clone().context("failed to clone serial port")?;
+		let mut client = stream.try_clone()?;
+		let done = done.clone();
+		thread::spawn(move || {
+			let mut buf = [0u8; 4096];
+			let mut total = 0;
+			while !done.load(Ordering::SeqCst) {
+				match serial.read(&mut buf) {
+					Ok(0) => break,
+					Ok(n) => {
+						if let Err(e) = client.write_all(&buf[..n]) {
+							return (total, Err(e));
+						}
+						total += n as u64;
+					}
+					Err(e) if e.kind() == io::ErrorKind::TimedOut => {}
+					Err(e) => return (total, Err(e)),
+				}
+			}
+			(total, Ok(()))
+		})
+	};
+
+	let mut client = stream;
+	let mut buf = [0u8; 4096];
+	let mut up = 0;
+	let upstream = loop {
+		if stop.load(Ordering::SeqCst) || downstream.is_finished() {
+			break Ok(());
+		}
+		match client.read(&mut buf) {
+			Ok(0) => break Ok(()),
+			Ok(n) => {
+				if let Err(e) = port.write_all(&buf[..n]) {
+					break Err(e);
+				}
+				up += n as u64;
+			}
+			Err(e)
+				if matches!(
+					e.kind(),
+					io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+				) => {}
+			Err(e) => break Err(e),
+		}
+	};
+
+	done.store(true, Ordering::SeqCst);
+	let (down, result) = downstream
+		.join()
+		.map_err(|_| anyhow::anyhow!("serial reader panicked"))?;
+	upstream.context("serial write failed")?;
+	match result {
+		// A client hanging up mid-write is a normal disconnect, not a bridge failure.
+		Err(e)
+			if !matches!(
+				e.kind(),
+				io::ErrorKind::BrokenPipe | io::ErrorKind::ConnectionReset
+			) =>
+		{
+			Err(anyhow::Error::new(e).context("serial read failed"))
+		}
+		_ => Ok((up, down)),
+	}
+}
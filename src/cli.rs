@@ -1,5 +1,16 @@
-use clap::{Parser, Subcommand};
+use chrono::NaiveTime;
+use clap::{ArgAction, ArgGroup, Args, Parser, Subcommand};
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::device::FlowControl;
+use crate::errors::ErrorFormat;
+use crate::export::SpriteLayout;
+use crate::i18n::Lang;
+use crate::image::LoadOptions;
+use crate::output::OutputFormat;
+use crate::power::{Edge, PortLimit};
 
 #[derive(Parser)]
 #[command(name = "hm-hub", about = "CLI for HM Lab Z-NEO 8K USB Hub")]
@@ -7,45 +18,374 @@ pub struct Cli {
 	#[arg(short, long, help = "Serial port path (auto-detects if not specified)")]
 	pub port: Option<String>,
 
+	#[arg(
+		long,
+		value_name = "SERIAL|HWID",
+		conflicts_with = "port",
+		help = "Pick the hub with this USB serial number or hardware ID (e.g. 0x4d4f434b)"
+	)]
+	pub device: Option<String>,
+
+	#[arg(
+		long,
+		global = true,
+		default_value_t = 115200,
+		help = "Serial baud rate"
+	)]
+	pub baud: u32,
+
+	#[arg(
+		long,
+		global = true,
+		value_parser = parse_duration,
+		default_value = "2s",
+		help = "How long to wait for each device response"
+	)]
+	pub timeout: Duration,
+
+	#[arg(
+		long,
+		global = true,
+		value_parser = parse_duration,
+		default_value = "60s",
+		help = "How long an upload waits for the device to finish erasing"
+	)]
+	pub erase_timeout: Duration,
+
+	#[arg(
+		long,
+		global = true,
+		value_parser = parse_flow_control,
+		default_value = "none",
+		help = "Serial flow control: none, software or hardware"
+	)]
+	pub flow_control: FlowControl,
+
+	#[arg(
+		long,
+		global = true,
+		default_value_t = 3,
+		help = "How many times to re-send handshake and config requests"
+	)]
+	pub retries: u32,
+
+	#[arg(
+		long,
+		global = true,
+		default_value_t = 0,
+		help = "Repeat a failed upload, readback or config write up to N times, reconnecting in between"
+	)]
+	pub op_retries: u32,
+
+	#[arg(
+		long,
+		global = true,
+		value_parser = parse_duration,
+		default_value = "2s",
+		help = "Wait before the first --op-retries attempt; doubles each time, up to 60s"
+	)]
+	pub backoff: Duration,
+
+	#[arg(
+		long,
+		global = true,
+		value_parser = parse_duration,
+		default_value = "5s",
+		help = "How often long-running commands ping the device to check the link (0 to disable)"
+	)]
+	pub keepalive: Duration,
+
+	#[arg(
+		long,
+		global = true,
+		help = "Don't handshake on connect (device info will be unavailable)"
+	)]
+	pub skip_handshake: bool,
+
+	#[arg(
+		long,
+		global = true,
+		value_name = "FILE",
+		help = "Append every packet sent and received to a trace file (see `replay`)"
+	)]
+	pub record: Option<PathBuf>,
+
+	#[arg(
+		short,
+		long,
+		global = true,
+		help = "Skip confirmation prompts for destructive commands"
+	)]
+	pub yes: bool,
+
+	#[arg(
+		long,
+		global = true,
+		help = "Disable progress bars and percentage output"
+	)]
+	pub no_progress: bool,
+
+	#[arg(
+		long,
+		global = true,
+		help = "Lean profile for slow hosts such as a Pi Zero: no progress output or per-packet bookkeeping during transfers"
+	)]
+	pub minimal: bool,
+
+	#[arg(
+		short,
+		long,
+		global = true,
+		action = ArgAction::Count,
+		help = "Show protocol diagnostics (-v debug, -vv trace)"
+	)]
+	pub verbose: u8,
+
+	#[arg(
+		long,
+		global = true,
+		help = "Log every packet sent and received as hex"
+	)]
+	pub log_packets: bool,
+
+	#[arg(
+		long,
+		global = true,
+		value_enum,
+		default_value_t = OutputFormat::Human,
+		help = "Output format for command results"
+	)]
+	pub format: OutputFormat,
+
+	#[arg(
+		long,
+		global = true,
+		help = "Print command results as JSON (same as --format json)"
+	)]
+	pub json: bool,
+
+	#[arg(
+		long,
+		global = true,
+		value_enum,
+		help = "Language for messages (defaults to the system locale)"
+	)]
+	pub lang: Option<Lang>,
+
+	#[arg(
+		long,
+		global = true,
+		value_enum,
+		default_value_t = ErrorFormat::Human,
+		help = "How to report failures on stderr"
+	)]
+	pub error_format: ErrorFormat,
+
 	#[command(subcommand)]
 	pub command: Commands,
 }
 
+impl Cli {
+	pub fn output_format(&self) -> OutputFormat {
+		if self.json {
+			OutputFormat::Json
+		} else {
+			self.format
+		}
+	}
+}
+
 #[derive(Subcommand)]
 pub enum Commands {
 	#[command(about = "Show device info (HW ID, firmware, flash size)")]
 	Info,
+	#[command(about = "List every attached hub with its hardware ID, firmware and flash size")]
+	Devices,
+	#[command(about = "List the albums stored on the device, reading only the frame headers")]
+	List,
+	#[command(about = "Remove one album and pack the rest together (see list for indices)")]
+	Delete {
+		#[arg(help = "Index of the album to remove")]
+		index: usize,
+	},
+	#[command(
+		about = "Change the slideshow order by rewriting only the frame headers",
+		after_help = "Assumes the hub erases only the 8 KB header area it is sent, which hasn't \
+			been verified on hardware. If it erases the whole flash, every album is lost, so \
+			this asks for confirmation; take a backup first.",
+		group = clap::ArgGroup::new("how").required(true).args(["order", "from"])
+	)]
+	Reorder {
+		#[arg(
+			help = "Album indices in their new order; unlisted albums follow in their current order"
+		)]
+		order: Vec<usize>,
+
+		#[arg(
+			long = "move",
+			value_name = "INDEX",
+			requires = "to",
+			conflicts_with = "order",
+			help = "Move one album instead"
+		)]
+		from: Option<usize>,
+
+		#[arg(
+			long,
+			value_name = "INDEX",
+			requires = "from",
+			help = "Where --move puts it"
+		)]
+		to: Option<usize>,
+	},
+	#[command(
+		about = "Change an animation's frame delay by rewriting only its frame header",
+		after_help = "Like reorder, this assumes the hub erases only the 8 KB header area it is \
+			sent, which hasn't been verified on hardware. If it erases the whole flash, every \
+			album is lost, so this asks for confirmation; take a backup first."
+	)]
+	SetDelay {
+		#[arg(help = "Index of the album (see list)")]
+		index: usize,
+
+		#[arg(help = "Milliseconds per frame")]
+		ms: u16,
+	},
+	#[command(about = "Show flash usage and roughly how much more will fit")]
+	Du {
+		#[arg(
+			long,
+			default_value_t = 10,
+			help = "Frame rate to assume for the GIF estimate"
+		)]
+		fps: u32,
+	},
 	#[command(about = "Read or set device config")]
 	Config {
 		#[command(subcommand)]
 		action: Option<ConfigAction>,
 	},
+	#[command(about = "Set the screen brightness (0-30); short for config set brightness")]
+	Brightness { level: u8 },
+	#[command(
+		about = "Set the screen rotation (0, 90, 180 or 270); short for config set rotation"
+	)]
+	Rotation { degrees: u16 },
 	#[command(about = "Upload images/GIFs to the device LCD")]
-	Upload {
-		#[arg(required = true)]
-		images: Vec<PathBuf>,
+	Upload(UploadArgs),
+	#[command(about = "Upload all images from a directory")]
+	Slideshow {
+		#[arg(help = "Directory containing images")]
+		dir: PathBuf,
+
+		#[command(flatten)]
+		image: ImageArgs,
 
-		#[arg(long, help = "Letterbox instead of cropping to fill")]
-		no_crop: bool,
+		#[arg(long, help = "Turn the backlight off while flash is being written")]
+		blank: bool,
 
-		#[arg(long, help = "Save a preview PNG instead of uploading")]
-		preview: Option<PathBuf>,
+		#[arg(long, help = "Skip images that look like one already included")]
+		dedupe: bool,
 	},
-	#[command(about = "Upload all images from a directory")]
-	Slideshow {
+	#[command(about = "Upload a directory only if the device doesn't already hold it")]
+	Sync {
 		#[arg(help = "Directory containing images")]
 		dir: PathBuf,
 
-		#[arg(long, help = "Letterbox instead of cropping to fill")]
-		no_crop: bool,
+		#[command(flatten)]
+		image: ImageArgs,
+
+		#[arg(long, help = "Turn the backlight off while flash is being written")]
+		blank: bool,
+	},
+	#[command(about = "Bring config and images in line with a state file")]
+	Apply {
+		#[arg(help = "TOML file declaring the desired config and image directory")]
+		file: PathBuf,
+
+		#[arg(long, help = "Show what would change without touching the device")]
+		plan: bool,
+
+		#[arg(long, help = "Turn the backlight off while flash is being written")]
+		blank: bool,
+	},
+	#[command(about = "Roll changes out to several hubs")]
+	Fleet {
+		#[command(subcommand)]
+		action: FleetAction,
+	},
+	#[command(about = "Convert images without a device, saving PNGs or reporting quality")]
+	Preview {
+		#[arg(
+			required = true,
+			help = "Images or directories to convert; append :crop or :fit to override --no-crop"
+		)]
+		images: Vec<ImageSpec>,
+
+		#[command(flatten)]
+		image: ImageArgs,
+
+		#[arg(
+			short,
+			long,
+			default_value = ".",
+			help = "Directory to write the converted PNGs to"
+		)]
+		output: PathBuf,
+
+		#[arg(
+			long,
+			help = "Print PSNR/SSIM and clipping per image instead of writing PNGs"
+		)]
+		report: bool,
 	},
+	#[command(about = "Show the local journal of uploads and config changes")]
+	History {
+		#[arg(
+			long,
+			value_name = "HWID",
+			help = "Only show entries for this hardware ID (e.g. 0x00c01901)"
+		)]
+		hw_id: Option<String>,
+
+		#[arg(long, help = "Only show entries for this action (e.g. upload, config)")]
+		action: Option<String>,
+
+		#[arg(short = 'n', long, help = "Show only the most recent N entries")]
+		limit: Option<usize>,
+	},
+	#[command(about = "Decode a packet trace recorded with --record and flag protocol anomalies")]
+	Replay {
+		file: PathBuf,
+
+		#[arg(short, long, help = "Only print anomalies and the summary")]
+		quiet: bool,
+	},
+	#[command(about = "Generate man pages for hm-hub and its subcommands")]
+	Man {
+		#[arg(
+			long,
+			default_value = ".",
+			help = "Directory to write the man pages to"
+		)]
+		out: PathBuf,
+	},
+	#[command(about = "Print the protocol constants and cargo features this build uses, as JSON")]
+	Internals,
 	#[command(about = "Show USB power/current stats")]
 	Power {
-		#[arg(short, long, help = "Continuously monitor power stats")]
+		#[arg(
+			short,
+			long,
+			help = "Continuously monitor power stats (one JSON object per line with --json)"
+		)]
 		watch: bool,
+
+		#[command(flatten)]
+		session: WatchArgs,
 	},
 	#[command(about = "Live device log and power monitor")]
-	Monitor,
+	Monitor(MonitorArgs),
 	#[command(about = "Read back stored images from device flash")]
 	Read {
 		#[arg(
@@ -55,9 +395,75 @@ pub enum Commands {
 			help = "Output directory for saved images"
 		)]
 		output: PathBuf,
+
+		#[arg(
+			long,
+			value_enum,
+			num_args = 0..=1,
+			default_missing_value = "horizontal",
+			help = "Write animations as one sprite sheet PNG plus timing JSON"
+		)]
+		sprite_sheet: Option<SpriteLayout>,
 	},
 	#[command(about = "Factory reset the device")]
 	Reset,
+	#[command(about = "Share the local hub over TCP for use with --port tcp://host:port")]
+	Bridge {
+		#[arg(
+			long,
+			default_value = "127.0.0.1:9000",
+			help = "Address and port to listen on"
+		)]
+		listen: String,
+	},
+	#[command(about = "Upload a test album, read it back and restore the previous content")]
+	Selftest {
+		#[arg(
+			long,
+			help = "Where to keep the safety backup (default: a new file under the state directory)"
+		)]
+		backup_file: Option<PathBuf>,
+	},
+	#[command(about = "Exercise the serial link and report errors, retries and round-trip times")]
+	Doctor {
+		#[arg(long, default_value_t = 20, help = "Number of handshake round trips")]
+		rounds: u32,
+	},
+	#[command(about = "Switch night mode (dim screen, optional dark page) on or off")]
+	Night {
+		#[command(subcommand)]
+		action: NightAction,
+	},
+	#[command(
+		about = "Switch the memory page the hub shows, or list, copy or clear pages",
+		args_conflicts_with_subcommands = true
+	)]
+	Page {
+		#[arg(help = "Page to switch to (prints the current page if omitted)")]
+		page: Option<u8>,
+
+		#[command(subcommand)]
+		action: Option<PageAction>,
+	},
+	#[command(about = "Export device images and settings as a portable .hmpkg archive")]
+	Export {
+		#[arg(help = "Package file to write (e.g. desk.hmpkg)")]
+		file: PathBuf,
+	},
+	#[command(about = "Import a .hmpkg archive, replacing all images on the device")]
+	Import {
+		#[arg(help = "Package file to import")]
+		file: PathBuf,
+
+		#[arg(
+			long,
+			help = "Keep the device's current settings instead of the package's"
+		)]
+		no_config: bool,
+
+		#[arg(long, help = "Turn the backlight off while flash is being written")]
+		blank: bool,
+	},
 	#[command(about = "Backup device config and flash to a file")]
 	Backup {
 		#[arg(help = "Output file path")]
@@ -80,18 +486,526 @@ pub enum Commands {
 		)]
 		interval: u64,
 
-		#[arg(long, help = "Letterbox instead of cropping to fill")]
-		no_crop: bool,
+		#[command(flatten)]
+		image: ImageArgs,
+
+		#[arg(long, help = "Turn the backlight off while flash is being written")]
+		blank: bool,
+
+		#[arg(long, help = "Skip images that look like one already included")]
+		dedupe: bool,
+	},
+	#[command(
+		about = "Show an image with a power/voltage bar along one edge, re-uploading as readings change"
+	)]
+	Dashboard {
+		#[arg(help = "Image to show (the first frame of a GIF is used)")]
+		file: PathBuf,
+
+		#[command(flatten)]
+		image: ImageArgs,
+
+		#[arg(long, value_enum, default_value_t = Edge::Bottom, help = "Edge to draw the bar along")]
+		edge: Edge,
+
+		#[arg(long, default_value_t = 8, help = "Bar height in pixels")]
+		bar_height: u32,
+
+		#[arg(
+			long,
+			default_value_t = 3000,
+			value_name = "MA",
+			help = "Port current shown as a full gauge"
+		)]
+		full_scale: u16,
+
+		#[arg(
+			long,
+			default_value_t = 250,
+			value_name = "MA",
+			help = "Re-render when a port current moves by this much (voltage rating changes always count)"
+		)]
+		min_change: u16,
+
+		#[arg(
+			long,
+			value_parser = parse_duration,
+			default_value = "5m",
+			help = "Minimum time between re-uploads, to limit flash wear"
+		)]
+		min_interval: Duration,
+
+		#[arg(long, default_value_t = 10, help = "Seconds between power readings")]
+		interval: u64,
+	},
+	#[command(about = "Turn the screen off and on at fixed times of day")]
+	Schedule {
+		#[arg(long, value_parser = parse_time_of_day, help = "Time to turn the screen off (HH:MM)")]
+		screen_off: NaiveTime,
+
+		#[arg(long, value_parser = parse_time_of_day, help = "Time to turn the screen back on (HH:MM)")]
+		screen_on: NaiveTime,
+
+		#[arg(
+			long,
+			help = "Brightness to restore at screen-on (defaults to the level before screen-off)"
+		)]
+		brightness: Option<u8>,
+
+		#[arg(
+			long,
+			default_value_t = 30,
+			help = "Seconds between schedule checks, aligned to the clock (60 checks on the minute)"
+		)]
+		interval: u64,
+	},
+	#[command(about = "Adjust brightness from an ambient light source")]
+	#[command(group(ArgGroup::new("source").required(true).args(["command", "file", "iio"])))]
+	Ambient {
+		#[arg(long, help = "Shell command whose stdout is the light level")]
+		command: Option<String>,
+
+		#[arg(long, help = "File containing the light level")]
+		file: Option<PathBuf>,
+
+		#[arg(long, help = "Linux IIO light sensor (e.g. iio:device0)")]
+		iio: Option<String>,
+
+		#[arg(
+			long,
+			default_value_t = 0.0,
+			help = "Input value mapped to the lowest brightness"
+		)]
+		min: f64,
+
+		#[arg(
+			long,
+			default_value_t = 1000.0,
+			help = "Input value mapped to the highest brightness"
+		)]
+		max: f64,
+
+		#[arg(long, default_value_t = 1, help = "Lowest brightness to apply (0-30)")]
+		min_brightness: u8,
+
+		#[arg(
+			long,
+			default_value_t = 30,
+			help = "Highest brightness to apply (0-30)"
+		)]
+		max_brightness: u8,
+
+		#[arg(long, default_value_t = 6, help = "Number of brightness buckets")]
+		steps: u8,
+
+		#[arg(
+			long,
+			default_value_t = 0.25,
+			help = "Fraction of a bucket the input must pass a boundary by before switching"
+		)]
+		hysteresis: f64,
+
+		#[arg(long, default_value_t = 10, help = "Seconds between light readings")]
+		interval: u64,
+	},
+}
+
+#[derive(Args)]
+pub struct ImageArgs {
+	#[arg(long, help = "Letterbox instead of cropping to fill")]
+	pub no_crop: bool,
+
+	#[arg(
+		long,
+		value_parser = parse_temperature,
+		help = "Colour temperature in kelvin, or warm/neutral/cool"
+	)]
+	pub temperature: Option<u32>,
+
+	#[arg(
+		long,
+		value_name = "N",
+		default_value_t = 0,
+		help = "Blend N extra frames between animation frames (reduced to fit the device)"
+	)]
+	pub interpolate: u8,
+
+	#[arg(
+		long,
+		value_name = "PERCENT",
+		value_parser = parse_percent,
+		help = "Merge animation frames that differ from the previous one by less than this"
+	)]
+	pub similarity_threshold: Option<f64>,
+
+	#[arg(
+		long,
+		value_name = "WxH",
+		value_parser = parse_size,
+		help = "Frame size of .rgb565 files that have no header"
+	)]
+	pub size: Option<(u32, u32)>,
+}
+
+impl ImageArgs {
+	pub fn load_options(&self) -> LoadOptions {
+		LoadOptions {
+			crop: !self.no_crop,
+			temperature: self.temperature,
+			similarity_threshold: self.similarity_threshold,
+			raw_size: self.size,
+		}
+	}
+}
+
+// An image path with an optional ":crop" or ":fit" suffix overriding --no-crop.
+#[derive(Clone)]
+pub struct ImageSpec {
+	pub path: PathBuf,
+	pub crop: Option<bool>,
+}
+
+impl FromStr for ImageSpec {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let plain = ImageSpec {
+			path: PathBuf::from(s),
+			crop: None,
+		};
+		if plain.path.exists() {
+			return Ok(plain);
+		}
+		let (path, crop) = match s.rsplit_once(':') {
+			Some((path, "crop")) => (path, true),
+			Some((path, "fit")) => (path, false),
+			_ => return Ok(plain),
+		};
+		Ok(ImageSpec {
+			path: PathBuf::from(path),
+			crop: Some(crop),
+		})
+	}
+}
+
+#[derive(Args)]
+pub struct UploadArgs {
+	#[arg(
+		required_unless_present = "from_export",
+		conflicts_with = "from_export",
+		help = "Images or directories to upload; append :crop or :fit to override --no-crop"
+	)]
+	pub images: Vec<ImageSpec>,
+
+	#[arg(
+		long,
+		help = "Upload the frames and manifest written by the read command"
+	)]
+	pub from_export: Option<PathBuf>,
+
+	#[command(flatten)]
+	pub image: ImageArgs,
+
+	#[arg(long, help = "Turn the backlight off while flash is being written")]
+	pub blank: bool,
+
+	#[arg(
+		long,
+		help = "Spread content that exceeds one memory page over consecutive pages"
+	)]
+	pub split_pages: bool,
+
+	#[arg(
+		long,
+		conflicts_with = "split_pages",
+		help = "Add the images after the albums already on the device instead of replacing them"
+	)]
+	pub append: bool,
+
+	#[arg(
+		long,
+		value_name = "INDEX",
+		conflicts_with_all = ["split_pages", "append"],
+		help = "Replace only this album (see list) with the one image or GIF given"
+	)]
+	pub slot: Option<usize>,
+
+	#[arg(
+		long,
+		requires = "slot",
+		help = "With --slot, write a same-size or smaller album into the old one's space and \
+			upload only up to its end (assumes the hub erases only what it is sent; unverified)"
+	)]
+	pub in_place: bool,
+
+	#[arg(long, help = "Save a preview PNG instead of uploading")]
+	pub preview: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct WatchArgs {
+	#[arg(
+		long = "limit",
+		value_name = "PORT=MA",
+		help = "Per-port current limit to flag (e.g. port2=900)"
+	)]
+	pub limits: Vec<PortLimit>,
+
+	#[arg(
+		long,
+		value_parser = parse_duration,
+		help = "Stop after this long (e.g. 30s, 5m, 1h)"
+	)]
+	pub duration: Option<Duration>,
+
+	#[arg(long, help = "Stop after this many power samples")]
+	pub count: Option<u64>,
+
+	#[arg(
+		long,
+		value_name = "MS",
+		help = "Minimum milliseconds between status updates (the device report rate is fixed)"
+	)]
+	pub interval: Option<u64>,
+}
+
+#[derive(Args)]
+pub struct MonitorArgs {
+	#[command(flatten)]
+	pub session: WatchArgs,
+
+	#[arg(
+		long,
+		help = "Memory page holding a warning image to show while bus voltage is Critical"
+	)]
+	pub alert_page: Option<u8>,
+
+	#[arg(long, help = "Don't show the live status line on stderr")]
+	pub quiet: bool,
+
+	#[arg(long, help = "Write samples and events to stdout as NDJSON")]
+	pub ndjson: bool,
+
+	#[arg(
+		long,
+		value_name = "PATH",
+		help = "Append samples and events to a file as NDJSON"
+	)]
+	pub log_file: Option<PathBuf>,
+
+	#[arg(long, help = "Send samples and events to syslog/journald")]
+	pub syslog: bool,
+}
+
+#[derive(Subcommand)]
+pub enum FleetAction {
+	#[command(about = "Apply a state file to each hub in turn, verifying every one")]
+	Push {
+		#[arg(help = "TOML file declaring the desired config and image directory")]
+		file: PathBuf,
+
+		#[arg(
+			long,
+			value_name = "N",
+			default_value_t = 0,
+			help = "Update N hubs first and read their flash back in full, then ask before updating the rest"
+		)]
+		canary: usize,
+
+		#[arg(
+			long = "port",
+			value_name = "PORT",
+			help = "Hub to include (repeatable; defaults to every attached hub)"
+		)]
+		ports: Vec<String>,
+
+		#[arg(long, help = "Turn the backlight off while flash is being written")]
+		blank: bool,
+	},
+}
+
+#[derive(Subcommand)]
+pub enum PageAction {
+	#[command(about = "Show which memory pages hold images")]
+	List {
+		#[arg(
+			long,
+			default_value_t = 4,
+			help = "Number of pages to check, from page 0"
+		)]
+		pages: u8,
+	},
+	#[command(about = "Replace the images on one memory page with those on another")]
+	Copy {
+		#[arg(help = "Page to copy from")]
+		src: u8,
+
+		#[arg(help = "Page to overwrite")]
+		dst: u8,
+	},
+	#[command(about = "Remove all images from a memory page")]
+	Clear {
+		#[arg(help = "Page to clear")]
+		page: u8,
 	},
 }
 
+#[derive(Subcommand)]
+pub enum NightAction {
+	#[command(about = "Dim the screen and optionally switch to a dark memory page")]
+	On {
+		#[arg(long, default_value_t = 2, help = "Night brightness (0-30)")]
+		brightness: u8,
+
+		#[arg(long, help = "Memory page to switch to while night mode is on")]
+		page: Option<u8>,
+	},
+	#[command(about = "Restore the brightness and page saved by night on")]
+	Off,
+}
+
 #[derive(Subcommand)]
 pub enum ConfigAction {
-	#[command(about = "Set a config field (e.g. brightness 20, rotation 90)")]
+	#[command(
+		about = "Set config fields (e.g. brightness 20, or brightness=20 rotation=90), writing once",
+		after_long_help = crate::types::config_fields_help()
+	)]
 	Set {
-		field: Option<String>,
-		value: Option<String>,
+		#[arg(value_name = "FIELD VALUE | FIELD=VALUE...")]
+		args: Vec<String>,
 	},
+	#[command(
+		about = "Print one config field's value (e.g. brightness), as config set takes it",
+		after_long_help = crate::types::config_fields_help()
+	)]
+	Get { field: String },
 	#[command(about = "Dump raw config bytes (hex)")]
 	Dump,
+	#[command(
+		about = "Factory reset to the firmware's default config, then upload the stored images again"
+	)]
+	Reset {
+		#[arg(
+			long,
+			default_value_t = 4,
+			help = "Number of memory pages to check for albums a reset would lose, from page 0"
+		)]
+		pages: u8,
+	},
+	#[command(about = "Save every config field to a TOML file (JSON if it ends in .json)")]
+	Export { file: PathBuf },
+	#[command(about = "Apply the config fields from a file written by config export")]
+	Import { file: PathBuf },
+	#[command(about = "Show which config fields differ from a file or another hub")]
+	Diff {
+		#[arg(
+			required_unless_present = "against_port",
+			conflicts_with = "against_port",
+			help = "Config export or apply state file to compare with"
+		)]
+		file: Option<PathBuf>,
+
+		#[arg(
+			long,
+			value_name = "PORT",
+			help = "Compare with the hub on PORT instead"
+		)]
+		against_port: Option<String>,
+	},
+}
+
+fn parse_time_of_day(s: &str) -> Result<NaiveTime, String> {
+	NaiveTime::parse_from_str(s, "%H:%M").map_err(|_| format!("invalid time '{s}', expected HH:MM"))
+}
+
+fn parse_percent(s: &str) -> Result<f64, String> {
+	let value: f64 = s
+		.trim_end_matches('%')
+		.parse()
+		.map_err(|_| format!("invalid percentage '{s}'"))?;
+	if !(0.0..=100.0).contains(&value) {
+		return Err(format!("percentage must be 0-100, got {value}"));
+	}
+	Ok(value)
+}
+
+fn parse_flow_control(s: &str) -> Result<FlowControl, String> {
+	match s.to_lowercase().as_str() {
+		"none" => Ok(FlowControl::None),
+		"software" | "xonxoff" => Ok(FlowControl::Software),
+		"hardware" | "rtscts" => Ok(FlowControl::Hardware),
+		_ => Err(format!(
+			"invalid flow control '{s}', expected none, software or hardware"
+		)),
+	}
+}
+
+pub fn parse_size(s: &str) -> Result<(u32, u32), String> {
+	let invalid = || format!("invalid size '{s}', expected WxH");
+	let (w, h) = s.split_once(['x', 'X']).ok_or_else(invalid)?;
+	Ok((
+		w.trim().parse().map_err(|_| invalid())?,
+		h.trim().parse().map_err(|_| invalid())?,
+	))
+}
+
+pub fn parse_temperature(s: &str) -> Result<u32, String> {
+	let kelvin = match s.to_lowercase().as_str() {
+		"warm" => 3500,
+		"neutral" => 6500,
+		"cool" => 9000,
+		other => other
+			.trim_end_matches('k')
+			.parse()
+			.map_err(|_| format!("invalid temperature '{s}'"))?,
+	};
+	if !(1000..=40000).contains(&kelvin) {
+		return Err(format!("temperature must be 1000-40000K, got {kelvin}"));
+	}
+	Ok(kelvin)
+}
+
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+	let s = s.trim();
+	let split = s
+		.find(|c: char| !c.is_ascii_digit() && c != '.')
+		.unwrap_or(s.len());
+	let (number, unit) = s.split_at(split);
+	let value: f64 = number
+		.parse()
+		.map_err(|_| format!("invalid duration '{s}'"))?;
+	let secs = match unit {
+		"ms" => value / 1000.0,
+		"" | "s" => value,
+		"m" => value * 60.0,
+		"h" => value * 3600.0,
+		_ => {
+			return Err(format!(
+				"invalid duration unit in '{s}' (use ms, s, m or h)"
+			))
+		}
+	};
+	Duration::try_from_secs_f64(secs).map_err(|e| format!("invalid duration '{s}': {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn durations_take_every_unit() {
+		assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+		assert_eq!(parse_duration("3"), Ok(Duration::from_secs(3)));
+		assert_eq!(parse_duration("1.5s"), Ok(Duration::from_millis(1500)));
+		assert_eq!(parse_duration("2m"), Ok(Duration::from_secs(120)));
+		assert_eq!(parse_duration(" 1h "), Ok(Duration::from_secs(3600)));
+	}
+
+	#[test]
+	fn bad_durations_are_errors_not_panics() {
+		assert!(parse_duration("99999999999999999999h").is_err());
+		assert!(parse_duration("5d").is_err());
+		assert!(parse_duration("soon").is_err());
+		assert!(parse_duration("").is_err());
+	}
 }
@@ -1,41 +1,196 @@
-use anyhow::{bail, Result};
-use indicatif::{ProgressBar, ProgressStyle};
+pub use serialport::FlowControl;
+use std::net::TcpStream;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use crate::consts::*;
-use crate::protocol::chunked::encode_chunked;
+use crate::error::{HubError, Result};
+use crate::mock::MockDevice;
+use crate::progress::{Phase, Progress, ProgressMode, ProgressObserver};
 use crate::protocol::commands::*;
-use crate::protocol::packet::recv_packet;
-use crate::types::{ChunkedReceiver, DeviceConfig, DeviceInfo};
+use crate::protocol::packet::{read_packet, recv_packet};
+use crate::trace::Recorder;
+use crate::transport::{MockTransport, Transport};
+use crate::types::{
+	ChunkedReceiver, DeviceConfig, DeviceEvent, DeviceInfo, LinkStats, Packet, PowerStats,
+};
+
+#[cfg(feature = "async")]
+mod asynchronous;
+#[cfg(feature = "async")]
+pub use asynchronous::AsyncDevice;
 
 const HM_VID: u16 = 0xC019;
 const HM_PID: u16 = 0x0401;
+const DEFAULT_RETRIES: u32 = 3;
+const DEFAULT_BACKOFF: Duration = Duration::from_secs(2);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+// How often blocked reads wake up to check the cancellation token.
+const CANCEL_POLL: Duration = Duration::from_millis(250);
+// After a cancel, replies still in flight are discarded until the line is quiet this
+// long (or DRAIN_LIMIT passes, for a readback that keeps streaming).
+const DRAIN_QUIET: Duration = Duration::from_millis(200);
+const DRAIN_LIMIT: Duration = Duration::from_secs(3);
+
+// Replaces a dead transport with a fresh one for the same path.
+type Reconnect<T> = Box<dyn Fn(&mut T) -> Result<()> + Send>;
 
-pub struct Device {
-	port: Box<dyn serialport::SerialPort>,
+pub struct Device<T: Transport = Box<dyn Transport>> {
+	port: T,
 	pub info: DeviceInfo,
+	pub progress: ProgressMode,
+	timeout: Duration,
+	erase_timeout: Duration,
+	retries: u32,
+	op_retries: u32,
+	backoff: Duration,
+	reconnect: Option<Reconnect<T>>,
+	cancel: Option<Arc<AtomicBool>>,
+	stats: LinkStats,
 }
 
-pub fn detect_port() -> Result<String> {
-	let ports = serialport::available_ports()?;
-	for p in &ports {
-		if let serialport::SerialPortType::UsbPort(usb) = &p.port_type {
-			if usb.vid == HM_VID && usb.pid == HM_PID {
-				return Ok(p.port_name.clone());
-			}
+// Link settings. The defaults match the stock firmware over USB CDC.
+#[derive(Debug, Clone)]
+pub struct DeviceOptions {
+	pub baud: u32,
+	// How long to wait for each reply.
+	pub timeout: Duration,
+	// How long the device may go quiet while erasing before an upload gives up.
+	pub erase_timeout: Duration,
+	// Handshake and config-read attempts.
+	pub retries: u32,
+	pub flow_control: FlowControl,
+}
+
+impl Default for DeviceOptions {
+	fn default() -> Self {
+		Self {
+			baud: SERIAL_BAUD_RATE,
+			timeout: NORMAL_TIMEOUT,
+			erase_timeout: ERASE_TIMEOUT,
+			retries: DEFAULT_RETRIES,
+			flow_control: FlowControl::None,
 		}
 	}
-	bail!("no HM Lab device found (VID:{HM_VID:#06x} PID:{HM_PID:#06x}). Is it plugged in?")
 }
 
-impl Device {
-	pub fn open(path: &str) -> Result<Self> {
-		let port = serialport::new(path, SERIAL_BAUD_RATE)
-			.data_bits(serialport::DataBits::Eight)
-			.stop_bits(serialport::StopBits::One)
-			.parity(serialport::Parity::None)
-			.timeout(NORMAL_TIMEOUT)
-			.open()?;
+#[derive(Clone)]
+pub struct DeviceBuilder {
+	path: String,
+	options: DeviceOptions,
+	op_retries: u32,
+	backoff: Duration,
+	skip_handshake: bool,
+	record: Option<PathBuf>,
+	cancel: Option<Arc<AtomicBool>>,
+}
+
+impl DeviceBuilder {
+	pub fn options(mut self, options: DeviceOptions) -> Self {
+		self.options = options;
+		self.options.retries = self.options.retries.max(1);
+		self
+	}
+
+	pub fn baud(mut self, baud: u32) -> Self {
+		self.options.baud = baud;
+		self
+	}
+
+	pub fn timeout(mut self, timeout: Duration) -> Self {
+		self.options.timeout = timeout;
+		self
+	}
+
+	pub fn erase_timeout(mut self, timeout: Duration) -> Self {
+		self.options.erase_timeout = timeout;
+		self
+	}
+
+	pub fn retries(mut self, retries: u32) -> Self {
+		self.options.retries = retries.max(1);
+		self
+	}
+
+	pub fn flow_control(mut self, flow_control: FlowControl) -> Self {
+		self.options.flow_control = flow_control;
+		self
+	}
+
+	// Whole uploads, readbacks and config writes are repeated up to `retries` more times
+	// after a transient failure, waiting `backoff` (doubling each time) and reconnecting
+	// in between.
+	pub fn op_retries(mut self, retries: u32, backoff: Duration) -> Self {
+		self.op_retries = retries;
+		self.backoff = backoff;
+		self
+	}
+
+	pub fn skip_handshake(mut self, skip: bool) -> Self {
+		self.skip_handshake = skip;
+		self
+	}
+
+	// Appends every packet exchanged by `open`ed devices to a trace file.
+	pub fn record(mut self, path: Option<PathBuf>) -> Self {
+		self.record = path;
+		self
+	}
+
+	// Once the flag is set, uploads and readbacks stop with `HubError::Cancelled` and
+	// `monitor` returns. The flag is never cleared by the device.
+	pub fn cancel(mut self, flag: Arc<AtomicBool>) -> Self {
+		self.cancel = Some(flag);
+		self
+	}
+
+	// `tcp://host:port` connects to a serial bridge instead of a local port, and
+	// `mock:` talks to an emulated hub held in memory (`mock:<file>` to persist it).
+	pub fn open(self) -> Result<Device> {
+		let port = self.connect()?;
+		let reconnect = self.clone();
+		let mut dev = self.open_with(port)?;
+		dev.reconnect = Some(Box::new(move |port| {
+			// Serial ports are opened exclusively, so the old handle must go first.
+			*port = Box::new(MockTransport::new());
+			*port = reconnect.connect()?;
+			Ok(())
+		}));
+		Ok(dev)
+	}
+
+	fn connect(&self) -> Result<Box<dyn Transport>> {
+		let port: Box<dyn Transport> = if let Some(addr) = self.path.strip_prefix("tcp://") {
+			let stream = TcpStream::connect(addr)?;
+			stream.set_nodelay(true)?;
+			Box::new(stream)
+		} else if let Some(state) = self.path.strip_prefix("mock:") {
+			match state {
+				"" => Box::new(MockDevice::default()),
+				path => Box::new(MockDevice::open(Path::new(path))?),
+			}
+		} else {
+			Box::new(
+				serialport::new(&self.path, self.options.baud)
+					.data_bits(serialport::DataBits::Eight)
+					.stop_bits(serialport::StopBits::One)
+					.parity(serialport::Parity::None)
+					.flow_control(self.options.flow_control)
+					.timeout(self.options.timeout)
+					.open()?,
+			)
+		};
+		Ok(match &self.record {
+			Some(path) => Box::new(Recorder::new(port, path)?),
+			None => port,
+		})
+	}
 
+	// The path, baud rate and flow control are ignored; the transport is used as given.
+	pub fn open_with<T: Transport>(self, port: T) -> Result<Device<T>> {
 		let mut dev = Device {
 			port,
 			info: DeviceInfo {
@@ -43,228 +198,644 @@ impl Device {
 				fw_ver: 0,
 				flash_size: 0,
 			},
+			progress: ProgressMode::detect(false),
+			timeout: self.options.timeout,
+			erase_timeout: self.options.erase_timeout,
+			retries: self.options.retries,
+			op_retries: self.op_retries,
+			backoff: self.backoff,
+			reconnect: None,
+			cancel: self.cancel,
+			stats: LinkStats::default(),
 		};
-		dev.handshake()?;
+		if !self.skip_handshake {
+			dev.handshake()?;
+		}
 		Ok(dev)
 	}
+}
 
+pub fn detect_port() -> Result<String> {
+	detect_ports()?
+		.into_iter()
+		.next()
+		.ok_or(HubError::DeviceNotFound {
+			vid: HM_VID,
+			pid: HM_PID,
+		})
+}
+
+// Every attached hub, in the order the OS lists them.
+pub fn detect_ports() -> Result<Vec<String>> {
+	Ok(detect_hubs()?.into_iter().map(|h| h.port).collect())
+}
+
+// A serial port with the hub's VID/PID, and its USB serial number if the OS reports one.
+#[derive(Debug, Clone)]
+pub struct HubPort {
+	pub port: String,
+	pub serial: Option<String>,
+}
+
+pub fn detect_hubs() -> Result<Vec<HubPort>> {
+	let ports = serialport::available_ports()?;
+	Ok(ports
+		.into_iter()
+		.filter_map(|p| match p.port_type {
+			serialport::SerialPortType::UsbPort(usb) if usb.vid == HM_VID && usb.pid == HM_PID => {
+				Some(HubPort {
+					port: p.port_name,
+					serial: usb.serial_number,
+				})
+			}
+			_ => None,
+		})
+		.collect())
+}
+
+// Copies the part of a readback packet that falls inside the window starting at
+// `start`, returning how far into the window it reached.
+fn copy_window(buffer: &mut [u8], start: usize, offset: usize, data: &[u8]) -> Option<usize> {
+	let end = start + buffer.len();
+	let lo = offset.max(start);
+	let hi = (offset + data.len()).min(end);
+	if lo >= hi {
+		return None;
+	}
+	buffer[lo - start..hi - start].copy_from_slice(&data[lo - offset..hi - offset]);
+	Some(hi - start)
+}
+
+// Device log lines go through `tracing` like the host's own diagnostics, under their
+// own target so they can be filtered separately.
+pub struct Events<'a, T: Transport> {
+	dev: &'a mut Device<T>,
+}
+
+impl<T: Transport> Iterator for Events<'_, T> {
+	type Item = Result<DeviceEvent>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		self.dev.next_event().transpose()
+	}
+}
+
+fn log_device_message(resp: &Packet) {
+	if let Ok(msg) = parse_log(resp) {
+		log_device_line(&msg);
+	}
+}
+
+fn log_device_line(msg: &str) {
+	tracing::info!(target: "hm_hub::device_log", "[device log] {msg}");
+}
+
+fn is_timeout(err: &HubError) -> bool {
+	matches!(err, HubError::Timeout(_))
+}
+
+// Bad input won't get better by trying again; anything else might be a USB hiccup.
+fn is_transient(err: &HubError) -> bool {
+	!matches!(
+		err,
+		HubError::CapacityExceeded(_) | HubError::InvalidConfig(_)
+	)
+}
+
+impl Device {
+	pub fn builder(path: &str) -> DeviceBuilder {
+		DeviceBuilder {
+			path: path.to_string(),
+			options: DeviceOptions::default(),
+			op_retries: 0,
+			backoff: DEFAULT_BACKOFF,
+			skip_handshake: false,
+			record: None,
+			cancel: None,
+		}
+	}
+
+	pub fn open_with_options(path: &str, options: DeviceOptions) -> Result<Device> {
+		Device::builder(path).options(options).open()
+	}
+}
+
+impl<T: Transport> Device<T> {
 	fn handshake(&mut self) -> Result<()> {
+		let _span = tracing::debug_span!("handshake").entered();
+		for attempt in 1..=self.retries {
+			if attempt > 1 {
+				self.stats.retransmissions += 1;
+			}
+			if self.try_handshake()? {
+				tracing::debug!(
+					hw_id = format_args!("{:#010x}", self.info.hw_id),
+					firmware = %self.info.fw_version_string(),
+					flash_size = self.info.flash_size,
+					"device identified"
+				);
+				return Ok(());
+			}
+			tracing::debug!(attempt, "no handshake reply");
+		}
+		Err(HubError::Timeout(format!(
+			"no handshake response after {} attempts",
+			self.retries
+		)))
+	}
+
+	// Sends one handshake and returns whether a reply arrived within the timeout.
+	fn try_handshake(&mut self) -> Result<bool> {
 		let pkt = build_handshake()?;
-		pkt.send(&mut *self.port)?;
+		self.send_packet(&pkt)?;
 		for _ in 0..10 {
-			let resp = recv_packet(&mut *self.port, NORMAL_TIMEOUT)?;
+			let resp = match self.recv_packet(self.timeout) {
+				Ok(resp) => resp,
+				Err(e) if is_timeout(&e) => return Ok(false),
+				Err(e) => return Err(e),
+			};
 			if resp.cmd_id() == CMD_HANDSHAKE {
 				self.info = parse_handshake(&resp)?;
-				return Ok(());
+				return Ok(true);
 			}
 		}
-		bail!("no handshake response after 10 attempts")
+		Ok(false)
+	}
+
+	// There is no dedicated ping command, so a single re-handshake serves as the
+	// keepalive. Returns the round-trip time.
+	pub fn ping(&mut self) -> Result<Duration> {
+		let started = Instant::now();
+		if !self.try_handshake()? {
+			return Err(HubError::Timeout(format!(
+				"no reply to keepalive within {:?}",
+				self.timeout
+			)));
+		}
+		Ok(started.elapsed())
 	}
 
 	pub fn read_config(&mut self) -> Result<DeviceConfig> {
+		let _span = tracing::debug_span!("config_read").entered();
+		for attempt in 1..=self.retries {
+			if attempt > 1 {
+				self.stats.retransmissions += 1;
+			}
+			if let Some(config) = self.request_config()? {
+				return Ok(config);
+			}
+			if attempt < self.retries {
+				tracing::warn!(
+					"[config] no response, re-requesting ({attempt}/{})",
+					self.retries
+				);
+			}
+		}
+		Err(HubError::Timeout(format!(
+			"device did not answer the config request after {} attempts \
+			 (it may be busy, or its firmware may not support config reads)",
+			self.retries
+		)))
+	}
+
+	// Sends one config request and waits up to the read timeout for the full reply,
+	// returning None if it never completes.
+	fn request_config(&mut self) -> Result<Option<DeviceConfig>> {
 		let pkt = build_config_read()?;
-		pkt.send(&mut *self.port)?;
+		self.send_packet(&pkt)?;
 
 		let mut receiver = ChunkedReceiver::new();
-		let mut retries = 0;
+		let deadline = Instant::now() + self.timeout;
 
 		loop {
-			let resp = recv_packet(&mut *self.port, NORMAL_TIMEOUT)?;
+			let remaining = deadline.saturating_duration_since(Instant::now());
+			let resp = if remaining.is_zero() {
+				None
+			} else {
+				match self.recv_packet(remaining) {
+					Ok(resp) => Some(resp),
+					Err(e) if is_timeout(&e) => None,
+					Err(e) => return Err(e),
+				}
+			};
+			let Some(resp) = resp else {
+				let missing = receiver.missing();
+				if !missing.is_empty() {
+					tracing::warn!("[config] reply incomplete, missing chunk(s) {missing:?}");
+				}
+				return Ok(None);
+			};
 			match resp.cmd_id() {
 				CMD_CONFIG => {
 					let payload = resp.payload();
 					match payload[0] {
 						1 => continue,
-						2 => match receiver.feed(&payload[1..])? {
-							Some(data) => return DeviceConfig::from_bytes(&data),
-							None => continue,
-						},
-						other => {
-							retries += 1;
-							if retries > 10 {
-								bail!("unexpected config sub-command: {other}");
+						2 => {
+							tracing::trace!(chunk = payload[1], total = payload[2], "config chunk");
+							match receiver.feed(&payload[1..]) {
+								Ok(Some(data)) => return DeviceConfig::from_bytes(&data).map(Some),
+								Ok(None) => {}
+								Err(e) => {
+									tracing::warn!("[config] discarding reply: {e}");
+									return Ok(None);
+								}
 							}
 						}
+						other => tracing::warn!("[config] ignoring unexpected sub-command {other}"),
 					}
 				}
-				CMD_LOG => {
-					if let Ok(msg) = parse_log(&resp) {
-						eprintln!("[device log] {msg}");
-					}
-				}
-				CMD_POWER => {}
-				_ => {
-					retries += 1;
-					if retries > 10 {
-						bail!("failed to read config after {retries} unexpected packets");
-					}
-				}
+				CMD_LOG => log_device_message(&resp),
+				_ => {}
 			}
 		}
 	}
 
 	pub fn write_config(&mut self, config: &DeviceConfig) -> Result<()> {
-		let data = config.to_bytes();
-		let packets = encode_chunked(CMD_CONFIG, 2, &data)?;
-		for pkt in &packets {
-			pkt.send(&mut *self.port)?;
-		}
-		Ok(())
+		let packets = build_config_write(config)?;
+		let _span = tracing::debug_span!("config_write", chunks = packets.len()).entered();
+		self.retrying("config write", |dev| {
+			for pkt in &packets {
+				dev.send_packet(pkt)?;
+			}
+			Ok(())
+		})
 	}
 
 	pub fn upload_flash(&mut self, flash_data: &[u8]) -> Result<()> {
-		let total_size = flash_data.len() as u32;
+		if self.progress == ProgressMode::Minimal {
+			return self.upload_flash_with(flash_data, &mut ());
+		}
+		let mut pb = Progress::new(self.progress, flash_data.len() as u64);
+		self.upload_flash_with(flash_data, &mut pb)
+	}
+
+	pub fn upload_flash_with(
+		&mut self,
+		flash_data: &[u8],
+		observer: &mut dyn ProgressObserver,
+	) -> Result<()> {
+		self.retrying("upload", |dev| dev.upload_once(flash_data, observer))
+	}
 
-		let pkt = build_flash_start(total_size)?;
-		pkt.send(&mut *self.port)?;
+	fn upload_once(
+		&mut self,
+		flash_data: &[u8],
+		observer: &mut dyn ProgressObserver,
+	) -> Result<()> {
+		let total = flash_data.len() as u64;
+		let _span = tracing::debug_span!("upload", bytes = total).entered();
 
-		let pb = ProgressBar::new(flash_data.len() as u64);
-		pb.set_style(
-			ProgressStyle::default_bar()
-				.template("{spinner:.cyan} [{bar:40.cyan/dim}] {bytes}/{total_bytes} ({eta})")?
-				.progress_chars("=> "),
-		);
-		pb.set_position(0);
+		let pkt = build_flash_start(flash_data.len() as u32)?;
+		self.send_packet(&pkt)?;
 
+		let mut sent = 0;
+		let mut writing = false;
+		let mut resp_pkt = Packet {
+			buf: [0; PACKET_SIZE],
+		};
+
+		self.port
+			.set_timeout(self.poll_timeout(self.erase_timeout))?;
 		loop {
-			let resp = recv_packet(&mut *self.port, ERASE_TIMEOUT)?;
+			let resp = match self.next_packet(self.erase_timeout) {
+				Err(HubError::Cancelled) => {
+					observer.on_finish(false);
+					return Err(HubError::Cancelled);
+				}
+				result => result?,
+			};
 			if resp.cmd_id() != CMD_FLASH {
 				if resp.cmd_id() == CMD_LOG {
-					if let Ok(msg) = parse_log(&resp) {
-						pb.println(format!("[device log] {msg}"));
-					}
+					log_device_message(&resp);
 				}
 				continue;
 			}
 
+			if let Some(err) = parse_flash_error(&resp) {
+				observer.on_finish(false);
+				return Err(HubError::FlashFailed(format!(
+					"upload aborted after {sent} of {} bytes: {err}",
+					flash_data.len()
+				)));
+			}
+
 			let payload = resp.payload();
 			match payload[0] {
-				1 => {
-					if payload[1] == 2 {
-						pb.set_message("Erasing flash...");
-					}
+				1 if payload[1] == 2 => {
+					tracing::debug!("erasing");
+					observer.on_phase(Phase::Erasing);
 				}
 				2 => {
+					if !writing {
+						writing = true;
+						tracing::debug!("writing");
+						observer.on_phase(Phase::Writing);
+					}
 					let offset =
 						u32::from_le_bytes([payload[1], payload[2], payload[3], payload[4]]);
 					let length = u16::from_le_bytes([payload[5], payload[6]]);
+					tracing::trace!(offset, length, "data request");
 
-					let start = offset as usize;
-					let end = (start + length as usize).min(flash_data.len());
-					let chunk = &flash_data[start..end];
-
-					let resp_pkt = build_flash_data_response(offset, length, chunk)?;
-					resp_pkt.send(&mut *self.port)?;
+					if fill_flash_data_response(&mut resp_pkt, offset, length, flash_data) {
+						let start = offset as usize;
+						let end = start + length as usize;
+						observer.on_message(&format!(
+							"[upload] device requested {start}..{end} past end of data ({} bytes), padding with 0xFF",
+							flash_data.len()
+						));
+					}
+					self.send_packet(&resp_pkt)?;
 
 					let pos = (offset as usize + length as usize).min(flash_data.len());
-					pb.set_position(pos as u64);
+					sent = pos;
+					observer.on_progress(pos as u64, total);
 
 					if pos >= flash_data.len() {
-						pb.finish_with_message("Upload complete!");
+						tracing::debug!("upload complete");
+						observer.on_finish(true);
 						return Ok(());
 					}
 				}
 				4 => {
-					pb.finish_with_message("Upload complete!");
+					tracing::debug!("device reported upload done");
+					observer.on_finish(true);
 					return Ok(());
 				}
-				_ => {}
+				1 if payload[1] < 2 => {}
+				1 => tracing::warn!("[upload] ignoring flash status {:#04x}", payload[1]),
+				other => tracing::warn!("[upload] ignoring unexpected flash sub-command {other}"),
 			}
 		}
 	}
 
 	pub fn read_flash(&mut self) -> Result<Vec<u8>> {
-		let flash_size = self.info.flash_size as usize;
+		if self.progress == ProgressMode::Minimal {
+			return self.read_flash_with(&mut ());
+		}
+		let mut pb = Progress::new(self.progress, self.info.flash_size as u64);
+		self.read_flash_with(&mut pb)
+	}
 
+	pub fn read_flash_with(&mut self, observer: &mut dyn ProgressObserver) -> Result<Vec<u8>> {
 		let pkt = build_flash_readback()?;
-		pkt.send(&mut *self.port)?;
+		self.readback(pkt, 0, self.info.flash_size as usize, observer)
+	}
 
-		let mut buffer = vec![0u8; flash_size];
+	pub fn read_flash_range(&mut self, start: usize, len: usize) -> Result<Vec<u8>> {
+		if self.progress == ProgressMode::Minimal {
+			return self.read_flash_range_with(start, len, &mut ());
+		}
+		let mut pb = Progress::new(self.progress, len as u64);
+		self.read_flash_range_with(start, len, &mut pb)
+	}
 
-		let pb = ProgressBar::new(flash_size as u64);
-		pb.set_style(
-			ProgressStyle::default_bar()
-				.template("{spinner:.cyan} [{bar:40.cyan/dim}] {bytes}/{total_bytes} ({eta})")?
-				.progress_chars("=> "),
-		);
+	// Firmware that ignores the range streams the whole flash; only the requested
+	// window is kept either way.
+	pub fn read_flash_range_with(
+		&mut self,
+		start: usize,
+		len: usize,
+		observer: &mut dyn ProgressObserver,
+	) -> Result<Vec<u8>> {
+		let pkt = build_flash_readback_range(start as u32, len as u32)?;
+		self.readback(pkt, start, len, observer)
+	}
+
+	fn readback(
+		&mut self,
+		pkt: Packet,
+		start: usize,
+		len: usize,
+		observer: &mut dyn ProgressObserver,
+	) -> Result<Vec<u8>> {
+		self.retrying("readback", |dev| {
+			dev.readback_once(&pkt, start, len, observer)
+		})
+	}
+
+	fn readback_once(
+		&mut self,
+		pkt: &Packet,
+		start: usize,
+		len: usize,
+		observer: &mut dyn ProgressObserver,
+	) -> Result<Vec<u8>> {
+		let _span = tracing::debug_span!("readback", start, len).entered();
+		self.send_packet(pkt)?;
+
+		let mut buffer = vec![0u8; len];
+		observer.on_phase(Phase::Reading);
+		let mut received = 0;
 
 		loop {
-			let resp = recv_packet(&mut *self.port, NORMAL_TIMEOUT)?;
+			// The callback may have used the port with another timeout.
+			self.port
+				.set_timeout(self.poll_timeout(self.timeout))
+				.map_err(HubError::from)?;
+			let resp = match self.next_packet(self.timeout) {
+				Err(HubError::Cancelled) => {
+					observer.on_finish(false);
+					return Err(HubError::Cancelled);
+				}
+				result => result?,
+			};
 			if resp.cmd_id() == CMD_FLASH {
+				if let Some(err) = parse_flash_error(&resp) {
+					observer.on_finish(false);
+					return Err(HubError::FlashFailed(format!(
+						"readback aborted after {received} of {len} bytes: {err}"
+					)));
+				}
 				let payload = resp.payload();
 				match payload[0] {
 					3 => {
-						let offset =
-							u32::from_le_bytes([payload[1], payload[2], payload[3], payload[4]])
-								as usize;
-						let length = u16::from_le_bytes([payload[5], payload[6]]) as usize;
-						let data = &payload[7..7 + length];
-
-						if offset + length <= buffer.len() {
-							buffer[offset..offset + length].copy_from_slice(data);
-						}
+						let (offset, data) = match parse_readback_data(&resp) {
+							Ok(parsed) => parsed,
+							Err(e) => {
+								observer.on_finish(false);
+								return Err(e);
+							}
+						};
+						tracing::trace!(offset, length = data.len(), "data");
 
-						pb.set_position((offset + length) as u64);
+						if let Some(filled) = copy_window(&mut buffer, start, offset, data) {
+							received = received.max(filled);
+							observer.on_progress(received as u64, len as u64);
+						}
 					}
 					4 => {
-						pb.finish_with_message("Read complete!");
+						tracing::debug!(received, "readback complete");
+						observer.on_finish(true);
 						return Ok(buffer);
 					}
-					_ => {}
+					1 if payload[1] <= 2 => {}
+					1 => tracing::warn!("[readback] ignoring flash status {:#04x}", payload[1]),
+					other => {
+						tracing::warn!("[readback] ignoring unexpected flash sub-command {other}")
+					}
 				}
 			} else if resp.cmd_id() == CMD_LOG {
-				if let Ok(msg) = parse_log(&resp) {
-					pb.println(format!("[device log] {msg}"));
-				}
+				log_device_message(&resp);
 			}
 		}
 	}
 
-	pub fn read_power(&mut self) -> Result<crate::types::PowerStats> {
+	// Raw access for experimenting with commands the library doesn't wrap; see the
+	// protocol module. Unsolicited power and log packets arrive here like any other.
+	pub fn send_packet(&mut self, pkt: &Packet) -> Result<()> {
+		pkt.send(&mut self.port)?;
+		self.stats.packets_sent += 1;
+		Ok(())
+	}
+
+	pub fn recv_packet(&mut self, timeout: Duration) -> Result<Packet> {
+		let result = recv_packet(&mut self.port, timeout);
+		self.stats.record_read(&result);
+		result
+	}
+
+	// Counters since the device was opened, including any reconnects.
+	pub fn link_stats(&self) -> LinkStats {
+		self.stats
+	}
+
+	pub fn read_power(&mut self) -> Result<PowerStats> {
 		loop {
-			let resp = recv_packet(&mut *self.port, NORMAL_TIMEOUT)?;
+			let resp = self.recv_packet(self.timeout)?;
 			if resp.cmd_id() == CMD_POWER {
 				return parse_power_stats(&resp);
 			} else if resp.cmd_id() == CMD_LOG {
-				if let Ok(msg) = parse_log(&resp) {
-					eprintln!("[device log] {msg}");
+				log_device_message(&resp);
+			}
+		}
+	}
+
+	// Waits for the next unsolicited packet from the device. Returns None once the
+	// cancellation flag is set, since that is the normal way to stop listening.
+	pub fn next_event(&mut self) -> Result<Option<DeviceEvent>> {
+		// Callers may have used the port with another timeout in between.
+		self.port.set_timeout(self.poll_timeout(self.timeout))?;
+		match self.next_packet(self.timeout) {
+			Err(HubError::Cancelled) => Ok(None),
+			Err(e) => Err(e),
+			Ok(resp) => Ok(Some(DeviceEvent::from_packet(resp))),
+		}
+	}
+
+	// Iterates over device events until cancelled. A timeout is yielded as an error
+	// without ending the iteration, so callers decide whether a quiet device is fatal.
+	pub fn events(&mut self) -> Events<'_, T> {
+		Events { dev: self }
+	}
+
+	// Errors from the callback are passed through, so it can use its own error type.
+	pub fn monitor<E: From<HubError>>(
+		&mut self,
+		mut on_power: impl FnMut(&mut Self, &PowerStats) -> Result<ControlFlow<()>, E>,
+	) -> Result<(), E> {
+		while let Some(event) = self.next_event()? {
+			match event {
+				DeviceEvent::Power(stats) => {
+					if on_power(self, &stats)?.is_break() {
+						return Ok(());
+					}
 				}
+				DeviceEvent::Log(msg) => log_device_line(&msg),
+				DeviceEvent::Unknown(_) => {}
 			}
 		}
+		Ok(())
 	}
 
-	pub fn monitor(&mut self) -> Result<()> {
+	fn retrying<R>(&mut self, what: &str, mut op: impl FnMut(&mut Self) -> Result<R>) -> Result<R> {
+		let mut delay = self.backoff;
+		let mut attempt = 0;
 		loop {
-			let resp = recv_packet(&mut *self.port, NORMAL_TIMEOUT)?;
-			match resp.cmd_id() {
-				CMD_POWER => {
-					let stats = parse_power_stats(&resp)?;
-					let voltage = stats.bus_voltage as f64 / 1000.0;
-					let rating = if stats.bus_voltage >= 4750 {
-						"Healthy"
-					} else if stats.bus_voltage >= 4250 {
-						"Warning"
-					} else {
-						"Critical"
-					};
-					eprint!(
-						"\rBus: {voltage:.2}V ({rating}) | Ports: {}mA {}mA {}mA   ",
-						stats.current_port1, stats.current_port2, stats.current_port3
+			self.check_cancelled()?;
+			match op(self) {
+				Err(e) if attempt < self.op_retries && is_transient(&e) => {
+					attempt += 1;
+					tracing::warn!(
+						"[retry] {what} failed: {e}; retrying in {delay:?} ({attempt}/{})",
+						self.op_retries
 					);
-				}
-				CMD_LOG => {
-					if let Ok(msg) = parse_log(&resp) {
-						eprintln!("\r[device log] {msg}                              ");
+					std::thread::sleep(delay);
+					delay = (delay * 2).min(MAX_BACKOFF);
+					self.stats.retransmissions += 1;
+					self.stats.resyncs += 1;
+					// A failed reconnect just costs this attempt; the next try reports it.
+					if let Err(e) = self.reconnect() {
+						tracing::warn!("[retry] reconnect failed: {e}");
 					}
 				}
-				_ => {}
+				result => return result,
+			}
+		}
+	}
+
+	fn poll_timeout(&self, timeout: Duration) -> Duration {
+		match self.cancel {
+			Some(_) => timeout.min(CANCEL_POLL),
+			None => timeout,
+		}
+	}
+
+	// Like `read_packet` (the transport timeout must already be set, via
+	// `poll_timeout`), but wakes up every CANCEL_POLL to check the cancellation token.
+	fn next_packet(&mut self, timeout: Duration) -> Result<Packet> {
+		let deadline = Instant::now() + timeout;
+		loop {
+			self.check_cancelled()?;
+			match read_packet(&mut self.port, timeout) {
+				Err(e) if is_timeout(&e) && self.cancel.is_some() && Instant::now() < deadline => {}
+				result => {
+					self.stats.record_read(&result);
+					return result;
+				}
 			}
 		}
 	}
 
+	// The firmware has no abort command: an abandoned transfer just times out on the
+	// device. Draining whatever it still sends keeps those replies away from the next
+	// command on this port.
+	fn check_cancelled(&mut self) -> Result<()> {
+		if !self
+			.cancel
+			.as_ref()
+			.is_some_and(|c| c.load(Ordering::SeqCst))
+		{
+			return Ok(());
+		}
+		let started = Instant::now();
+		let mut buf = [0u8; PACKET_SIZE];
+		let mut drained = false;
+		if self.port.set_timeout(DRAIN_QUIET).is_ok() {
+			while started.elapsed() < DRAIN_LIMIT {
+				match self.port.read(&mut buf) {
+					Ok(n) if n > 0 => drained = true,
+					_ => break,
+				}
+			}
+		}
+		if drained {
+			self.stats.resyncs += 1;
+		}
+		Err(HubError::Cancelled)
+	}
+
+	// Reopens the port when the device was opened by path (it may have re-enumerated
+	// after a USB reset), then handshakes again.
+	pub fn reconnect(&mut self) -> Result<()> {
+		if let Some(connect) = &self.reconnect {
+			connect(&mut self.port)?;
+		}
+		self.handshake()
+	}
+
 	pub fn factory_reset(&mut self) -> Result<()> {
 		let pkt = build_factory_reset()?;
-		pkt.send(&mut *self.port)?;
+		self.send_packet(&pkt)?;
 		Ok(())
 	}
 }
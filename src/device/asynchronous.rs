@@ -0,0 +1,409 @@
+use futures_util::stream::{self, Stream};
+use std::ops::ControlFlow;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
+
+use super::{copy_window, is_timeout, log_device_line, log_device_message, DeviceBuilder};
+use crate::consts::*;
+use crate::error::{HubError, Result};
+use crate::progress::{Phase, Progress, ProgressMode, ProgressObserver};
+use crate::protocol::commands::*;
+use crate::types::{
+	trace_packet, ChunkedReceiver, DeviceConfig, DeviceEvent, DeviceInfo, LinkStats, Packet,
+	PowerStats,
+};
+
+pub struct AsyncDevice {
+	port: SerialStream,
+	pub info: DeviceInfo,
+	pub progress: ProgressMode,
+	timeout: Duration,
+	erase_timeout: Duration,
+	retries: u32,
+	stats: LinkStats,
+}
+
+impl DeviceBuilder {
+	pub async fn open_async(self) -> Result<AsyncDevice> {
+		let port = tokio_serial::new(&self.path, self.options.baud)
+			.data_bits(tokio_serial::DataBits::Eight)
+			.stop_bits(tokio_serial::StopBits::One)
+			.parity(tokio_serial::Parity::None)
+			.flow_control(self.options.flow_control)
+			.timeout(self.options.timeout)
+			.open_native_async()?;
+
+		let mut dev = AsyncDevice {
+			port,
+			info: DeviceInfo {
+				hw_id: 0,
+				fw_ver: 0,
+				flash_size: 0,
+			},
+			progress: ProgressMode::detect(false),
+			timeout: self.options.timeout,
+			erase_timeout: self.options.erase_timeout,
+			retries: self.options.retries,
+			stats: LinkStats::default(),
+		};
+		if !self.skip_handshake {
+			dev.handshake().await?;
+		}
+		Ok(dev)
+	}
+}
+
+impl AsyncDevice {
+	async fn send(&mut self, pkt: &Packet) -> Result<()> {
+		trace_packet("tx", &pkt.buf);
+		self.port.write_all(&pkt.buf).await?;
+		self.port.flush().await?;
+		self.stats.packets_sent += 1;
+		Ok(())
+	}
+
+	async fn recv(&mut self, timeout: Duration) -> Result<Packet> {
+		let mut buf = [0u8; PACKET_SIZE];
+		let result = match tokio::time::timeout(timeout, self.port.read_exact(&mut buf)).await {
+			Ok(Ok(_)) => {
+				trace_packet("rx", &buf);
+				Packet::from_bytes(buf)
+			}
+			Ok(Err(e)) => Err(e.into()),
+			Err(_) => Err(HubError::Timeout("timeout waiting for packet".to_string())),
+		};
+		self.stats.record_read(&result);
+		result
+	}
+
+	async fn handshake(&mut self) -> Result<()> {
+		for attempt in 1..=self.retries {
+			if attempt > 1 {
+				self.stats.retransmissions += 1;
+			}
+			self.send(&build_handshake()?).await?;
+			for _ in 0..10 {
+				let resp = match self.recv(self.timeout).await {
+					Ok(resp) => resp,
+					Err(e) if is_timeout(&e) => break,
+					Err(e) => return Err(e),
+				};
+				if resp.cmd_id() == CMD_HANDSHAKE {
+					self.info = parse_handshake(&resp)?;
+					return Ok(());
+				}
+			}
+		}
+		Err(HubError::Timeout(format!(
+			"no handshake response after {} attempts",
+			self.retries
+		)))
+	}
+
+	pub async fn read_config(&mut self) -> Result<DeviceConfig> {
+		for attempt in 1..=self.retries {
+			if attempt > 1 {
+				self.stats.retransmissions += 1;
+			}
+			if let Some(config) = self.request_config().await? {
+				return Ok(config);
+			}
+			if attempt < self.retries {
+				tracing::warn!(
+					"[config] no response, re-requesting ({attempt}/{})",
+					self.retries
+				);
+			}
+		}
+		Err(HubError::Timeout(format!(
+			"device did not answer the config request after {} attempts \
+			 (it may be busy, or its firmware may not support config reads)",
+			self.retries
+		)))
+	}
+
+	async fn request_config(&mut self) -> Result<Option<DeviceConfig>> {
+		self.send(&build_config_read()?).await?;
+
+		let mut receiver = ChunkedReceiver::new();
+		let deadline = Instant::now() + self.timeout;
+
+		loop {
+			let remaining = deadline.saturating_duration_since(Instant::now());
+			let resp = if remaining.is_zero() {
+				None
+			} else {
+				match self.recv(remaining).await {
+					Ok(resp) => Some(resp),
+					Err(e) if is_timeout(&e) => None,
+					Err(e) => return Err(e),
+				}
+			};
+			let Some(resp) = resp else {
+				let missing = receiver.missing();
+				if !missing.is_empty() {
+					tracing::warn!("[config] reply incomplete, missing chunk(s) {missing:?}");
+				}
+				return Ok(None);
+			};
+			let payload = resp.payload();
+			match (resp.cmd_id(), payload[0]) {
+				(CMD_CONFIG, 1) => continue,
+				(CMD_CONFIG, 2) => match receiver.feed(&payload[1..]) {
+					Ok(Some(data)) => return DeviceConfig::from_bytes(&data).map(Some),
+					Ok(None) => {}
+					Err(e) => {
+						tracing::warn!("[config] discarding reply: {e}");
+						return Ok(None);
+					}
+				},
+				(CMD_CONFIG, other) => {
+					tracing::warn!("[config] ignoring unexpected sub-command {other}")
+				}
+				(CMD_LOG, _) => log_device_message(&resp),
+				_ => {}
+			}
+		}
+	}
+
+	pub async fn write_config(&mut self, config: &DeviceConfig) -> Result<()> {
+		for pkt in &build_config_write(config)? {
+			self.send(pkt).await?;
+		}
+		Ok(())
+	}
+
+	pub async fn upload_flash(&mut self, flash_data: &[u8]) -> Result<()> {
+		if self.progress == ProgressMode::Minimal {
+			return self.upload_flash_with(flash_data, &mut ()).await;
+		}
+		let mut pb = Progress::new(self.progress, flash_data.len() as u64);
+		self.upload_flash_with(flash_data, &mut pb).await
+	}
+
+	pub async fn upload_flash_with(
+		&mut self,
+		flash_data: &[u8],
+		observer: &mut (dyn ProgressObserver + Send),
+	) -> Result<()> {
+		self.send(&build_flash_start(flash_data.len() as u32)?)
+			.await?;
+
+		let total = flash_data.len() as u64;
+		let mut sent = 0;
+		let mut writing = false;
+		let mut resp_pkt = Packet {
+			buf: [0; PACKET_SIZE],
+		};
+
+		loop {
+			let resp = self.recv(self.erase_timeout).await?;
+			if resp.cmd_id() != CMD_FLASH {
+				if resp.cmd_id() == CMD_LOG {
+					log_device_message(&resp);
+				}
+				continue;
+			}
+
+			if let Some(err) = parse_flash_error(&resp) {
+				observer.on_finish(false);
+				return Err(HubError::FlashFailed(format!(
+					"upload aborted after {sent} of {} bytes: {err}",
+					flash_data.len()
+				)));
+			}
+
+			let payload = resp.payload();
+			match payload[0] {
+				1 if payload[1] == 2 => observer.on_phase(Phase::Erasing),
+				2 => {
+					if !writing {
+						writing = true;
+						observer.on_phase(Phase::Writing);
+					}
+					let offset =
+						u32::from_le_bytes([payload[1], payload[2], payload[3], payload[4]]);
+					let length = u16::from_le_bytes([payload[5], payload[6]]);
+					let end = offset as usize + length as usize;
+					if fill_flash_data_response(&mut resp_pkt, offset, length, flash_data) {
+						observer.on_message(&format!(
+							"[upload] device requested {offset}..{end} past end of data ({} bytes), padding with 0xFF",
+							flash_data.len()
+						));
+					}
+					self.send(&resp_pkt).await?;
+
+					sent = end.min(flash_data.len());
+					observer.on_progress(sent as u64, total);
+					if sent >= flash_data.len() {
+						observer.on_finish(true);
+						return Ok(());
+					}
+				}
+				4 => {
+					observer.on_finish(true);
+					return Ok(());
+				}
+				1 if payload[1] < 2 => {}
+				1 => tracing::warn!("[upload] ignoring flash status {:#04x}", payload[1]),
+				other => tracing::warn!("[upload] ignoring unexpected flash sub-command {other}"),
+			}
+		}
+	}
+
+	pub async fn read_flash(&mut self) -> Result<Vec<u8>> {
+		if self.progress == ProgressMode::Minimal {
+			return self.read_flash_with(&mut ()).await;
+		}
+		let mut pb = Progress::new(self.progress, self.info.flash_size as u64);
+		self.read_flash_with(&mut pb).await
+	}
+
+	pub async fn read_flash_with(
+		&mut self,
+		observer: &mut (dyn ProgressObserver + Send),
+	) -> Result<Vec<u8>> {
+		let len = self.info.flash_size as usize;
+		self.readback(build_flash_readback()?, 0, len, observer)
+			.await
+	}
+
+	pub async fn read_flash_range(&mut self, start: usize, len: usize) -> Result<Vec<u8>> {
+		if self.progress == ProgressMode::Minimal {
+			return self.read_flash_range_with(start, len, &mut ()).await;
+		}
+		let mut pb = Progress::new(self.progress, len as u64);
+		self.read_flash_range_with(start, len, &mut pb).await
+	}
+
+	// Firmware that ignores the range streams the whole flash; only the requested
+	// window is kept either way.
+	pub async fn read_flash_range_with(
+		&mut self,
+		start: usize,
+		len: usize,
+		observer: &mut (dyn ProgressObserver + Send),
+	) -> Result<Vec<u8>> {
+		let pkt = build_flash_readback_range(start as u32, len as u32)?;
+		self.readback(pkt, start, len, observer).await
+	}
+
+	async fn readback(
+		&mut self,
+		pkt: Packet,
+		start: usize,
+		len: usize,
+		observer: &mut (dyn ProgressObserver + Send),
+	) -> Result<Vec<u8>> {
+		self.send(&pkt).await?;
+
+		let mut buffer = vec![0u8; len];
+		observer.on_phase(Phase::Reading);
+		let mut received = 0;
+
+		loop {
+			let resp = self.recv(self.timeout).await?;
+			if resp.cmd_id() != CMD_FLASH {
+				if resp.cmd_id() == CMD_LOG {
+					log_device_message(&resp);
+				}
+				continue;
+			}
+			if let Some(err) = parse_flash_error(&resp) {
+				observer.on_finish(false);
+				return Err(HubError::FlashFailed(format!(
+					"readback aborted after {received} of {len} bytes: {err}"
+				)));
+			}
+			let payload = resp.payload();
+			match payload[0] {
+				3 => {
+					let (offset, data) = match parse_readback_data(&resp) {
+						Ok(parsed) => parsed,
+						Err(e) => {
+							observer.on_finish(false);
+							return Err(e);
+						}
+					};
+					if let Some(filled) = copy_window(&mut buffer, start, offset, data) {
+						received = received.max(filled);
+						observer.on_progress(received as u64, len as u64);
+					}
+				}
+				4 => {
+					observer.on_finish(true);
+					return Ok(buffer);
+				}
+				1 if payload[1] <= 2 => {}
+				1 => tracing::warn!("[readback] ignoring flash status {:#04x}", payload[1]),
+				other => tracing::warn!("[readback] ignoring unexpected flash sub-command {other}"),
+			}
+		}
+	}
+
+	pub async fn send_packet(&mut self, pkt: &Packet) -> Result<()> {
+		self.send(pkt).await
+	}
+
+	pub async fn recv_packet(&mut self, timeout: Duration) -> Result<Packet> {
+		self.recv(timeout).await
+	}
+
+	pub fn link_stats(&self) -> LinkStats {
+		self.stats
+	}
+
+	pub async fn read_power(&mut self) -> Result<PowerStats> {
+		loop {
+			let resp = self.recv(self.timeout).await?;
+			if resp.cmd_id() == CMD_POWER {
+				return parse_power_stats(&resp);
+			} else if resp.cmd_id() == CMD_LOG {
+				log_device_message(&resp);
+			}
+		}
+	}
+
+	// The device reports power on its own schedule; each item is the next report.
+	pub fn power_stats(&mut self) -> impl Stream<Item = Result<PowerStats>> + '_ {
+		stream::unfold(self, |dev| async move {
+			let stats = dev.read_power().await;
+			Some((stats, dev))
+		})
+	}
+
+	pub async fn next_event(&mut self) -> Result<DeviceEvent> {
+		let resp = self.recv(self.timeout).await?;
+		Ok(DeviceEvent::from_packet(resp))
+	}
+
+	// Like power_stats(), but with log messages and unrecognised packets too.
+	pub fn events(&mut self) -> impl Stream<Item = Result<DeviceEvent>> + '_ {
+		stream::unfold(self, |dev| async move {
+			let event = dev.next_event().await;
+			Some((event, dev))
+		})
+	}
+
+	pub async fn monitor<E: From<HubError>>(
+		&mut self,
+		mut on_power: impl FnMut(&mut AsyncDevice, &PowerStats) -> Result<ControlFlow<()>, E>,
+	) -> Result<(), E> {
+		loop {
+			match self.next_event().await? {
+				DeviceEvent::Power(stats) => {
+					if on_power(self, &stats)?.is_break() {
+						return Ok(());
+					}
+				}
+				DeviceEvent::Log(msg) => log_device_line(&msg),
+				DeviceEvent::Unknown(_) => {}
+			}
+		}
+	}
+
+	pub async fn factory_reset(&mut self) -> Result<()> {
+		self.send(&build_factory_reset()?).await
+	}
+}
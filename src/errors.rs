@@ -0,0 +1,105 @@
+use clap::ValueEnum;
+use hm_hub::HubError;
+use std::process::ExitCode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ErrorFormat {
+	Human,
+	Json,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ErrorKind {
+	General,
+	DeviceNotFound,
+	Timeout,
+	Serial,
+	Io,
+	Image,
+	Cancelled,
+}
+
+impl ErrorKind {
+	pub fn classify(err: &anyhow::Error) -> Self {
+		for cause in err.chain() {
+			if let Some(e) = cause.downcast_ref::<HubError>() {
+				return match e {
+					HubError::DeviceNotFound { .. } => ErrorKind::DeviceNotFound,
+					HubError::Timeout(_) => ErrorKind::Timeout,
+					HubError::Cancelled => ErrorKind::Cancelled,
+					HubError::Serial(e) => Self::from_serial(e),
+					HubError::Io(e) => Self::from_io(e),
+					_ => ErrorKind::General,
+				};
+			}
+			if let Some(e) = cause.downcast_ref::<serialport::Error>() {
+				return Self::from_serial(e);
+			}
+			if let Some(e) = cause.downcast_ref::<std::io::Error>() {
+				return Self::from_io(e);
+			}
+			if cause.is::<image::ImageError>() || cause.is::<gif::DecodingError>() {
+				return ErrorKind::Image;
+			}
+		}
+		ErrorKind::General
+	}
+
+	fn from_serial(e: &serialport::Error) -> Self {
+		match e.kind() {
+			serialport::ErrorKind::NoDevice => ErrorKind::DeviceNotFound,
+			serialport::ErrorKind::Io(std::io::ErrorKind::TimedOut) => ErrorKind::Timeout,
+			_ => ErrorKind::Serial,
+		}
+	}
+
+	fn from_io(e: &std::io::Error) -> Self {
+		match e.kind() {
+			std::io::ErrorKind::TimedOut => ErrorKind::Timeout,
+			_ => ErrorKind::Io,
+		}
+	}
+
+	pub fn name(self) -> &'static str {
+		match self {
+			ErrorKind::General => "general",
+			ErrorKind::DeviceNotFound => "device_not_found",
+			ErrorKind::Timeout => "timeout",
+			ErrorKind::Serial => "serial",
+			ErrorKind::Io => "io",
+			ErrorKind::Image => "image",
+			ErrorKind::Cancelled => "cancelled",
+		}
+	}
+
+	// 2 is left to clap for usage errors; 130 is the shell's code for Ctrl+C.
+	pub fn exit_code(self) -> u8 {
+		match self {
+			ErrorKind::General => 1,
+			ErrorKind::DeviceNotFound => 3,
+			ErrorKind::Timeout => 4,
+			ErrorKind::Serial => 5,
+			ErrorKind::Io => 6,
+			ErrorKind::Image => 7,
+			ErrorKind::Cancelled => 130,
+		}
+	}
+}
+
+pub fn report(err: &anyhow::Error, format: ErrorFormat) -> ExitCode {
+	let kind = ErrorKind::classify(err);
+	match format {
+		ErrorFormat::Human => eprintln!("Error: {err:?}"),
+		ErrorFormat::Json => {
+			let context: Vec<String> = err.chain().skip(1).map(|c| c.to_string()).collect();
+			let obj = serde_json::json!({
+				"kind": kind.name(),
+				"message": err.to_string(),
+				"context": context,
+				"exit_code": kind.exit_code(),
+			});
+			eprintln!("{obj}");
+		}
+	}
+	ExitCode::from(kind.exit_code())
+}
@@ -0,0 +1,184 @@
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::consts::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use crate::image::rgba_to_rgb565;
+use crate::types::Album;
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+pub const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+	pub version: u32,
+	pub albums: Vec<AlbumEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AlbumEntry {
+	pub index: usize,
+	pub width: u16,
+	pub height: u16,
+	pub frame_count: u16,
+	pub delay_ms: u16,
+	pub data_crc32: u32,
+	pub files: Vec<String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub sprite_sheet: Option<SpriteLayout>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SpriteLayout {
+	Horizontal,
+	Vertical,
+}
+
+impl SpriteLayout {
+	pub fn frame_origin(self, index: u32, width: u32, height: u32) -> (u32, u32) {
+		match self {
+			SpriteLayout::Horizontal => (index * width, 0),
+			SpriteLayout::Vertical => (0, index * height),
+		}
+	}
+}
+
+#[derive(Serialize)]
+pub struct SpriteSheetMeta {
+	pub image: String,
+	pub layout: SpriteLayout,
+	pub frame_width: u16,
+	pub frame_height: u16,
+	pub frames: Vec<SpriteFrame>,
+}
+
+#[derive(Serialize)]
+pub struct SpriteFrame {
+	pub x: u32,
+	pub y: u32,
+	pub w: u16,
+	pub h: u16,
+	pub duration_ms: u16,
+}
+
+impl Default for Manifest {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Manifest {
+	pub fn new() -> Self {
+		Self {
+			version: MANIFEST_VERSION,
+			albums: Vec::new(),
+		}
+	}
+
+	pub fn load(dir: &Path) -> Result<Self> {
+		let path = dir.join(MANIFEST_FILE);
+		let json = std::fs::read_to_string(&path)
+			.with_context(|| format!("failed to read {}", path.display()))?;
+		let manifest: Manifest = serde_json::from_str(&json)
+			.with_context(|| format!("invalid manifest {}", path.display()))?;
+		if manifest.version != MANIFEST_VERSION {
+			bail!("unsupported manifest version: {}", manifest.version);
+		}
+		Ok(manifest)
+	}
+
+	pub fn save(&self, dir: &Path) -> Result<()> {
+		let path = dir.join(MANIFEST_FILE);
+		let json = serde_json::to_string_pretty(self)?;
+		std::fs::write(&path, json).with_context(|| format!("failed to write {}", path.display()))
+	}
+}
+
+pub fn load_albums(dir: &Path) -> Result<Vec<Album>> {
+	let manifest = Manifest::load(dir)?;
+	let mut albums = Vec::with_capacity(manifest.albums.len());
+
+	for entry in &manifest.albums {
+		if entry.width as u32 != DISPLAY_WIDTH || entry.height as u32 != DISPLAY_HEIGHT {
+			bail!(
+				"album {} is {}x{}, expected {DISPLAY_WIDTH}x{DISPLAY_HEIGHT}",
+				entry.index,
+				entry.width,
+				entry.height
+			);
+		}
+		let images = load_frame_images(dir, entry)?;
+		if images.len() != entry.frame_count as usize {
+			bail!(
+				"album {} provides {} frame(s) but has {} frame(s)",
+				entry.index,
+				images.len(),
+				entry.frame_count
+			);
+		}
+
+		let mut frames = Vec::with_capacity(images.len());
+		let mut hasher = crc32fast::Hasher::new();
+		for img in images {
+			let data = rgba_to_rgb565(img.as_raw(), DISPLAY_WIDTH, DISPLAY_HEIGHT);
+			hasher.update(&data);
+			frames.push(data);
+		}
+
+		if hasher.finalize() != entry.data_crc32 {
+			tracing::warn!(
+				"Warning: album {} differs from the exported data (CRC mismatch)",
+				entry.index
+			);
+		}
+
+		albums.push(Album {
+			frames,
+			delay_ms: entry.delay_ms,
+		});
+	}
+
+	Ok(albums)
+}
+
+fn load_frame_images(dir: &Path, entry: &AlbumEntry) -> Result<Vec<::image::RgbaImage>> {
+	let mut images = Vec::new();
+	for name in &entry.files {
+		let path = dir.join(name);
+		let img = ::image::open(&path)
+			.with_context(|| format!("failed to load {}", path.display()))?
+			.to_rgba8();
+
+		match entry.sprite_sheet {
+			Some(layout) => {
+				for f in 0..entry.frame_count as u32 {
+					let (x, y) = layout.frame_origin(f, DISPLAY_WIDTH, DISPLAY_HEIGHT);
+					if x + DISPLAY_WIDTH > img.width() || y + DISPLAY_HEIGHT > img.height() {
+						bail!(
+							"{} is too small for {} frames",
+							path.display(),
+							entry.frame_count
+						);
+					}
+					let frame =
+						::image::imageops::crop_imm(&img, x, y, DISPLAY_WIDTH, DISPLAY_HEIGHT);
+					images.push(frame.to_image());
+				}
+			}
+			None => {
+				if img.width() != DISPLAY_WIDTH || img.height() != DISPLAY_HEIGHT {
+					bail!(
+						"{} is {}x{}, expected {DISPLAY_WIDTH}x{DISPLAY_HEIGHT}",
+						path.display(),
+						img.width(),
+						img.height()
+					);
+				}
+				images.push(img);
+			}
+		}
+	}
+	Ok(images)
+}
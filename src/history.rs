@@ -0,0 +1,139 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use crate::output::Render;
+use crate::paths;
+
+const HISTORY_FILE: &str = "history.jsonl";
+
+#[derive(Serialize, Deserialize)]
+pub struct Entry {
+	pub timestamp: String,
+	pub action: String,
+	pub device: String,
+	pub port: String,
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub files: Vec<String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub flash_crc32: Option<String>,
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub changes: Vec<String>,
+}
+
+impl Entry {
+	pub fn new(action: &str, hw_id: u32, port: &str) -> Self {
+		Self {
+			timestamp: chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, false),
+			action: action.to_string(),
+			device: format!("{hw_id:#010x}"),
+			port: port.to_string(),
+			files: Vec::new(),
+			flash_crc32: None,
+			changes: Vec::new(),
+		}
+	}
+
+	pub fn files(mut self, files: &[PathBuf]) -> Self {
+		self.files = files
+			.iter()
+			.map(|f| {
+				std::fs::canonicalize(f)
+					.unwrap_or_else(|_| f.clone())
+					.display()
+					.to_string()
+			})
+			.collect();
+		self
+	}
+
+	pub fn flash(mut self, data: &[u8]) -> Self {
+		self.flash_crc32 = Some(format!("{:08x}", crc32fast::hash(data)));
+		self
+	}
+
+	pub fn changes(mut self, changes: Vec<String>) -> Self {
+		self.changes = changes;
+		self
+	}
+}
+
+fn history_path() -> Result<PathBuf> {
+	Ok(paths::data_dir()?.join(HISTORY_FILE))
+}
+
+// The journal is a convenience; failing to write it must never fail the command.
+pub fn record(entry: Entry) {
+	if let Err(e) = append(&entry) {
+		tracing::warn!("warning: could not update history: {e:#}");
+	}
+}
+
+fn append(entry: &Entry) -> Result<()> {
+	let path = history_path()?;
+	if let Some(dir) = path.parent() {
+		std::fs::create_dir_all(dir)?;
+	}
+	let mut file = OpenOptions::new()
+		.create(true)
+		.append(true)
+		.open(&path)
+		.with_context(|| format!("failed to open {}", path.display()))?;
+	writeln!(file, "{}", serde_json::to_string(entry)?)?;
+	Ok(())
+}
+
+pub fn load() -> Result<Vec<Entry>> {
+	let path = history_path()?;
+	if !path.exists() {
+		return Ok(Vec::new());
+	}
+	read_entries(&path)
+}
+
+fn read_entries(path: &Path) -> Result<Vec<Entry>> {
+	let file = std::fs::File::open(path)?;
+	let mut entries = Vec::new();
+	for (n, line) in BufReader::new(file).lines().enumerate() {
+		let line = line?;
+		if line.trim().is_empty() {
+			continue;
+		}
+		let entry = serde_json::from_str(&line)
+			.with_context(|| format!("{}:{}: malformed history entry", path.display(), n + 1))?;
+		entries.push(entry);
+	}
+	Ok(entries)
+}
+
+#[derive(Serialize)]
+#[serde(transparent)]
+pub struct HistoryOutput(pub Vec<Entry>);
+
+impl Render for HistoryOutput {
+	fn human(&self) -> String {
+		if self.0.is_empty() {
+			return "No history recorded yet.".to_string();
+		}
+		let lines: Vec<String> = self
+			.0
+			.iter()
+			.map(|e| {
+				let mut line = format!("{}  {}  {:<9}", e.timestamp, e.device, e.action);
+				if !e.files.is_empty() {
+					line.push_str(&format!("  {} file(s)", e.files.len()));
+				}
+				if let Some(crc) = &e.flash_crc32 {
+					line.push_str(&format!("  crc {crc}"));
+				}
+				if !e.changes.is_empty() {
+					line.push_str(&format!("  {}", e.changes.join(", ")));
+				}
+				line
+			})
+			.collect();
+		lines.join("\n")
+	}
+}
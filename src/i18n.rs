@@ -0,0 +1,131 @@
+use clap::ValueEnum;
+use std::sync::OnceLock;
+
+static LANG: OnceLock<Lang> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Lang {
+	En,
+	De,
+	Zh,
+}
+
+impl Lang {
+	// Follows the usual POSIX precedence: LC_ALL, then LC_MESSAGES, then LANG.
+	pub fn detect() -> Self {
+		["LC_ALL", "LC_MESSAGES", "LANG"]
+			.iter()
+			.filter_map(|var| std::env::var(var).ok())
+			.find(|value| !value.is_empty())
+			.map(|value| Self::from_locale(&value))
+			.unwrap_or(Lang::En)
+	}
+
+	fn from_locale(locale: &str) -> Self {
+		match locale.get(..2).map(|p| p.to_ascii_lowercase()).as_deref() {
+			Some("de") => Lang::De,
+			Some("zh") => Lang::Zh,
+			_ => Lang::En,
+		}
+	}
+}
+
+pub fn init(lang: Option<Lang>) {
+	let _ = LANG.set(lang.unwrap_or_else(Lang::detect));
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Msg {
+	HardwareId,
+	Firmware,
+	FlashSize,
+	MaxFrames,
+	EraseBlock,
+	BusVoltage,
+	Port,
+	ConfirmOverwrite,
+	ContinuePrompt,
+	NeedsConfirmation,
+	Aborted,
+	Erasing,
+	UploadComplete,
+	UploadFailed,
+	ReadComplete,
+	ReadFailed,
+}
+
+pub fn tr(msg: Msg) -> &'static str {
+	let lang = LANG.get().copied().unwrap_or(Lang::En);
+	match (lang, msg) {
+		(Lang::En, Msg::HardwareId) => "Hardware ID",
+		(Lang::En, Msg::Firmware) => "Firmware",
+		(Lang::En, Msg::FlashSize) => "Flash size",
+		(Lang::En, Msg::MaxFrames) => "Max frames",
+		(Lang::En, Msg::EraseBlock) => "Erase block",
+		(Lang::En, Msg::BusVoltage) => "Bus voltage",
+		(Lang::En, Msg::Port) => "Port",
+		(Lang::En, Msg::ConfirmOverwrite) => {
+			"This will erase all images stored on memory page {page} ({flash} MB flash) \
+			 and replace them with {albums} album(s), {frames} frame(s)."
+		}
+		(Lang::En, Msg::ContinuePrompt) => "Continue? [y/N] ",
+		(Lang::En, Msg::NeedsConfirmation) => {
+			"Refusing to continue without confirmation (pass --yes)"
+		}
+		(Lang::En, Msg::Aborted) => "aborted",
+		(Lang::En, Msg::Erasing) => "Erasing flash...",
+		(Lang::En, Msg::UploadComplete) => "Upload complete!",
+		(Lang::En, Msg::UploadFailed) => "Upload failed",
+		(Lang::En, Msg::ReadComplete) => "Read complete!",
+		(Lang::En, Msg::ReadFailed) => "Read failed",
+
+		(Lang::De, Msg::HardwareId) => "Hardware-ID",
+		(Lang::De, Msg::Firmware) => "Firmware",
+		(Lang::De, Msg::FlashSize) => "Flash-Größe",
+		(Lang::De, Msg::MaxFrames) => "Max. Bilder",
+		(Lang::De, Msg::EraseBlock) => "Löschblock",
+		(Lang::De, Msg::BusVoltage) => "Busspannung",
+		(Lang::De, Msg::Port) => "Port",
+		(Lang::De, Msg::ConfirmOverwrite) => {
+			"Alle Bilder auf Speicherseite {page} ({flash} MB Flash) werden gelöscht \
+			 und durch {albums} Album/Alben mit {frames} Bild(ern) ersetzt."
+		}
+		(Lang::De, Msg::ContinuePrompt) => "Fortfahren? [y/N] ",
+		(Lang::De, Msg::NeedsConfirmation) => "Abbruch ohne Bestätigung (--yes angeben)",
+		(Lang::De, Msg::Aborted) => "abgebrochen",
+		(Lang::De, Msg::Erasing) => "Flash wird gelöscht...",
+		(Lang::De, Msg::UploadComplete) => "Hochladen abgeschlossen!",
+		(Lang::De, Msg::UploadFailed) => "Hochladen fehlgeschlagen",
+		(Lang::De, Msg::ReadComplete) => "Lesen abgeschlossen!",
+		(Lang::De, Msg::ReadFailed) => "Lesen fehlgeschlagen",
+
+		(Lang::Zh, Msg::HardwareId) => "硬件 ID",
+		(Lang::Zh, Msg::Firmware) => "固件",
+		(Lang::Zh, Msg::FlashSize) => "闪存大小",
+		(Lang::Zh, Msg::MaxFrames) => "最大帧数",
+		(Lang::Zh, Msg::EraseBlock) => "擦除块",
+		(Lang::Zh, Msg::BusVoltage) => "总线电压",
+		(Lang::Zh, Msg::Port) => "端口",
+		(Lang::Zh, Msg::ConfirmOverwrite) => {
+			"将清除存储页 {page}（{flash} MB 闪存）上的所有图片，\
+			 并替换为 {albums} 个相册、{frames} 帧。"
+		}
+		(Lang::Zh, Msg::ContinuePrompt) => "是否继续？[y/N] ",
+		(Lang::Zh, Msg::NeedsConfirmation) => "未确认，已拒绝继续（请使用 --yes）",
+		(Lang::Zh, Msg::Aborted) => "已取消",
+		(Lang::Zh, Msg::Erasing) => "正在擦除闪存...",
+		(Lang::Zh, Msg::UploadComplete) => "上传完成！",
+		(Lang::Zh, Msg::UploadFailed) => "上传失败",
+		(Lang::Zh, Msg::ReadComplete) => "读取完成！",
+		(Lang::Zh, Msg::ReadFailed) => "读取失败",
+	}
+}
+
+// Fills `{name}` placeholders in a translated template.
+pub fn trf(msg: Msg, args: &[(&str, String)]) -> String {
+	let mut text = tr(msg).to_string();
+	for (name, value) in args {
+		text = text.replace(&format!("{{{name}}}"), value);
+	}
+	text
+}
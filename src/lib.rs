@@ -0,0 +1,24 @@
+pub use hm_hub_core::{backup, consts, error, flash, image};
+
+pub mod ambient;
+pub mod device;
+pub mod export;
+pub mod i18n;
+pub mod mock;
+pub mod package;
+pub mod power;
+pub mod progress;
+pub mod protocol;
+pub mod trace;
+pub mod transport;
+pub mod types;
+
+#[cfg(feature = "async")]
+pub use device::AsyncDevice;
+pub use device::{
+	detect_hubs, detect_port, detect_ports, Device, DeviceBuilder, DeviceOptions, FlowControl,
+};
+pub use error::HubError;
+pub use flash::build_flash_buffer;
+pub use transport::Transport;
+pub use types::{Album, DeviceConfig, DeviceEvent, DeviceInfo, LinkStats, PowerStats};
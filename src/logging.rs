@@ -0,0 +1,80 @@
+use hm_hub::progress;
+use std::fmt;
+use std::io::{self, IsTerminal, Write};
+use tracing::level_filters::LevelFilter;
+use tracing::{Event, Subscriber};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{time, FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+
+const PACKET_TARGET: &str = "hm_hub::packet";
+
+// Without -v, only notices and warnings are shown, worded as they always were. -v adds
+// levels, targets and spans (handshake, config transfers, upload/readback phases).
+pub fn init(verbose: u8, log_packets: bool) {
+	let level = match verbose {
+		0 => LevelFilter::INFO,
+		1 => LevelFilter::DEBUG,
+		_ => LevelFilter::TRACE,
+	};
+	let packets = if log_packets {
+		LevelFilter::TRACE
+	} else {
+		LevelFilter::OFF
+	};
+	let filter = Targets::new()
+		.with_default(level)
+		.with_target(PACKET_TARGET, packets);
+
+	let ansi = io::stderr().is_terminal();
+	let base = tracing_subscriber::fmt()
+		.with_max_level(LevelFilter::TRACE)
+		.with_writer(|| Stderr)
+		.with_ansi(ansi);
+	if verbose == 0 && !log_packets {
+		base.event_format(Plain).finish().with(filter).init();
+	} else {
+		base.with_timer(time::uptime()).finish().with(filter).init();
+	}
+}
+
+struct Plain;
+
+impl<S, N> FormatEvent<S, N> for Plain
+where
+	S: Subscriber + for<'a> LookupSpan<'a>,
+	N: for<'a> FormatFields<'a> + 'static,
+{
+	fn format_event(
+		&self,
+		ctx: &FmtContext<'_, S, N>,
+		mut writer: Writer<'_>,
+		event: &Event<'_>,
+	) -> fmt::Result {
+		ctx.field_format().format_fields(writer.by_ref(), event)?;
+		writeln!(writer)
+	}
+}
+
+// Log lines go above any progress bar, and clear a `\r` status line first.
+struct Stderr;
+
+impl Write for Stderr {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		progress::suspend(|| {
+			let mut stderr = io::stderr().lock();
+			if stderr.is_terminal() {
+				stderr.write_all(b"\r\x1b[2K")?;
+			}
+			stderr.write_all(buf)
+		})?;
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		io::stderr().flush()
+	}
+}
@@ -1,224 +1,1737 @@
+mod bridge;
 mod cli;
-mod consts;
-mod device;
-mod flash;
-mod image;
-mod protocol;
-mod types;
-
-use anyhow::{bail, Result};
-use clap::Parser;
-use std::path::Path;
-
-use cli::{Cli, Commands, ConfigAction};
+mod errors;
+mod history;
+mod logging;
+mod output;
+mod paths;
+mod sinks;
+mod state;
+mod watchdog;
+
+use hm_hub::{
+	ambient, backup, consts, device, export, flash, i18n, image, package, power, progress,
+	protocol, trace, types,
+};
+
+use anyhow::{bail, Context, Result};
+use chrono::{Local, NaiveTime};
+use clap::{CommandFactory, Parser};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io::{IsTerminal, Write};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+use ambient::{BrightnessMapper, LightSource};
+use backup::Backup;
+use cli::{
+	Cli, Commands, ConfigAction, FleetAction, ImageArgs, ImageSpec, MonitorArgs, NightAction,
+	PageAction, UploadArgs, WatchArgs,
+};
 use consts::*;
-use device::Device;
-use types::FrameHeader;
+use device::{Device, DeviceOptions};
+use export::{AlbumEntry, Manifest, SpriteFrame, SpriteLayout, SpriteSheetMeta};
+use i18n::{tr, trf, Msg};
+use image::LoadOptions;
+use output::{
+	BackupOutput, ConfigDiffOutput, ConfigDumpOutput, ConfigFieldOutput, ConfigOutput, DeviceRow,
+	DevicesOutput, DoctorOutput, DuOutput, InfoOutput, InternalsOutput, ListOutput, OutputFormat,
+	PageEntry, PageListOutput, PowerOutput, QualityReport, QualityRow, ReadOutput,
+};
+use package::Package;
+use power::{Edge, PowerSession, Rating};
+use progress::ProgressMode;
+use sinks::{Event, Ndjson, Sink, StatusLine};
+use state::{ConfigFile, State};
+use trace::Replayer;
+use types::{
+	config_field_names, Album, DeviceConfig, FrameHeader, LinkStats, PowerStats, MAX_BRIGHTNESS,
+};
+use watchdog::{Health, Watchdog};
 
-fn main() -> Result<()> {
+fn main() -> ExitCode {
 	let cli = Cli::parse();
+	let error_format = cli.error_format;
+	match run(cli) {
+		Ok(()) => ExitCode::SUCCESS,
+		Err(e) => errors::report(&e, error_format),
+	}
+}
 
-	let port = match &cli.port {
-		Some(p) => p.clone(),
-		None => {
-			if matches!(cli.command, Commands::Config { action: Some(ConfigAction::Set { ref field, .. }) } if field.is_none())
-			{
-				String::new()
-			} else {
-				device::detect_port()?
-			}
-		}
+fn run(cli: Cli) -> Result<()> {
+	i18n::init(cli.lang);
+	logging::init(cli.verbose, cli.log_packets);
+	let format = cli.output_format();
+
+	match &cli.command {
+		Commands::Man { out } => return cmd_man(out),
+		Commands::Preview {
+			images,
+			image,
+			output,
+			report,
+		} => return cmd_preview(images, image, output, *report, format),
+		Commands::History {
+			hw_id,
+			action,
+			limit,
+		} => return cmd_history(hw_id.as_deref(), action.as_deref(), *limit, format),
+		Commands::Replay { file, quiet } => return cmd_replay(file, *quiet),
+		Commands::Internals => return cmd_internals(),
+		_ => {}
+	}
+
+	let yes = cli.yes;
+	let skip_port = matches!(cli.command, Commands::Config { action: Some(ConfigAction::Set { ref args }) } if args.is_empty())
+		|| matches!(cli.command, Commands::Fleet { .. })
+		|| matches!(cli.command, Commands::Devices);
+	let mut target = Target {
+		port: cli.port.clone().unwrap_or_default(),
+		options: DeviceOptions {
+			baud: cli.baud,
+			timeout: cli.timeout,
+			erase_timeout: cli.erase_timeout,
+			retries: cli.retries,
+			flow_control: cli.flow_control,
+		},
+		op_retries: cli.op_retries,
+		backoff: cli.backoff,
+		skip_handshake: cli.skip_handshake,
+		record: cli.record.clone(),
+		keepalive: cli.keepalive,
+		progress: if cli.minimal {
+			ProgressMode::Minimal
+		} else {
+			ProgressMode::detect(cli.no_progress)
+		},
 	};
+	if cli.port.is_none() && !skip_port {
+		target.port = match &cli.device {
+			Some(selector) => select_device(&target.options, selector)?,
+			None => device::detect_port()?,
+		};
+	}
 
 	match cli.command {
-		Commands::Info => cmd_info(&port),
-		Commands::Config { action } => cmd_config(&port, action),
-		Commands::Upload {
-			images,
-			no_crop,
-			preview,
-		} => cmd_upload(&port, &images, !no_crop, preview.as_deref()),
-		Commands::Slideshow { dir, no_crop } => cmd_slideshow(&port, &dir, !no_crop),
-		Commands::Power { watch } => cmd_power(&port, watch),
-		Commands::Monitor => cmd_monitor(&port),
-		Commands::Read { output } => cmd_read(&port, &output),
-		Commands::Reset => cmd_reset(&port),
-		Commands::Backup { file } => cmd_backup(&port, &file),
-		Commands::Restore { file } => cmd_restore(&port, &file),
+		Commands::Man { .. }
+		| Commands::Preview { .. }
+		| Commands::History { .. }
+		| Commands::Replay { .. }
+		| Commands::Internals => {
+			unreachable!("handled before connecting")
+		}
+		Commands::Info => cmd_info(&target, format),
+		Commands::Devices => cmd_devices(&target, format),
+		Commands::List => cmd_list(&target, format),
+		Commands::Du { fps } => cmd_du(&target, fps, format),
+		Commands::Delete { index } => cmd_delete(&target, index, yes),
+		Commands::Reorder { order, from, to } => cmd_reorder(&target, order, from.zip(to), yes),
+		Commands::SetDelay { index, ms } => cmd_set_delay(&target, index, ms, yes),
+		Commands::Config { action } => cmd_config(&target, action, yes, format),
+		Commands::Brightness { level } => cmd_set(&target, "brightness", level, yes, format),
+		Commands::Rotation { degrees } => cmd_set(&target, "rotation", degrees, yes, format),
+		Commands::Upload(args) => cmd_upload(&target, &args, yes),
+		Commands::Slideshow {
+			dir,
+			image,
+			blank,
+			dedupe,
+		} => cmd_slideshow(&target, &dir, &image, blank, dedupe, yes),
+		Commands::Sync { dir, image, blank } => cmd_sync(&target, &dir, &image, blank, yes),
+		Commands::Apply { file, plan, blank } => cmd_apply(&target, &file, plan, blank, yes),
+		Commands::Fleet {
+			action: FleetAction::Push {
+				file,
+				canary,
+				ports,
+				blank,
+			},
+		} => cmd_fleet_push(&target, &file, canary, ports, blank, yes),
+		Commands::Power { watch, session } => cmd_power(&target, watch, &session, format),
+		Commands::Monitor(args) => cmd_monitor(&target, &args),
+		Commands::Read {
+			output,
+			sprite_sheet,
+		} => cmd_read(&target, &output, sprite_sheet, format),
+		Commands::Night { action } => cmd_night(&target, action),
+		Commands::Page {
+			page: Some(page), ..
+		} => cmd_set(&target, "page", page, yes, format),
+		Commands::Page { action: None, .. } => {
+			let page = target.open()?.read_config()?.memory_page;
+			println!("Showing page {page}");
+			Ok(())
+		}
+		Commands::Page {
+			action: Some(action),
+			..
+		} => cmd_page(&target, action, yes, format),
+		Commands::Reset => cmd_reset(&target, yes),
+		Commands::Bridge { listen } => cmd_bridge(&target, &listen),
+		Commands::Selftest { backup_file } => cmd_selftest(&target, backup_file.as_deref(), yes),
+		Commands::Doctor { rounds } => cmd_doctor(&target, rounds, format),
+		Commands::Export { file } => cmd_export(&target, &file),
+		Commands::Import {
+			file,
+			no_config,
+			blank,
+		} => cmd_import(&target, &file, no_config, blank, yes),
+		Commands::Backup { file } => cmd_backup(&target, &file, format),
+		Commands::Restore { file } => cmd_restore(&target, &file, yes),
 		Commands::Rotate {
 			dir,
 			interval,
-			no_crop,
-		} => cmd_rotate(&port, &dir, interval, !no_crop),
+			image,
+			blank,
+			dedupe,
+		} => cmd_rotate(&target, &dir, interval, &image, blank, dedupe, yes),
+		Commands::Dashboard {
+			file,
+			image,
+			edge,
+			bar_height,
+			full_scale,
+			min_change,
+			min_interval,
+			interval,
+		} => cmd_dashboard(
+			&target,
+			&file,
+			&image,
+			DashboardBar {
+				edge,
+				height: bar_height,
+				full_scale,
+				min_change,
+				min_interval,
+			},
+			interval,
+			yes,
+		),
+		Commands::Schedule {
+			screen_off,
+			screen_on,
+			brightness,
+			interval,
+		} => cmd_schedule(&target, screen_off, screen_on, brightness, interval),
+		Commands::Ambient {
+			command,
+			file,
+			iio,
+			min,
+			max,
+			min_brightness,
+			max_brightness,
+			steps,
+			hysteresis,
+			interval,
+		} => {
+			let source = match (command, file, iio) {
+				(Some(cmd), _, _) => LightSource::Command(cmd),
+				(_, Some(path), _) => LightSource::File(path),
+				(_, _, Some(dev)) => LightSource::iio(&dev)?,
+				_ => unreachable!(),
+			};
+			let mapper =
+				BrightnessMapper::new(min, max, min_brightness, max_brightness, steps, hysteresis)?;
+			cmd_ambient(&target, source, mapper, interval)
+		}
+	}
+}
+
+#[derive(Clone)]
+struct Target {
+	port: String,
+	options: DeviceOptions,
+	op_retries: u32,
+	backoff: Duration,
+	skip_handshake: bool,
+	record: Option<PathBuf>,
+	keepalive: Duration,
+	progress: ProgressMode,
+}
+
+impl Target {
+	fn entry(&self, dev: &Device, action: &str) -> history::Entry {
+		history::Entry::new(action, dev.info.hw_id, &self.port)
+	}
+
+	fn open(&self) -> Result<Device> {
+		let mut dev = Device::builder(&self.port)
+			.options(self.options.clone())
+			.op_retries(self.op_retries, self.backoff)
+			.skip_handshake(self.skip_handshake)
+			.record(self.record.clone())
+			.cancel(interrupt_flag()?)
+			.open()?;
+		dev.progress = self.progress;
+		Ok(dev)
+	}
+
+	fn watchdog(&self) -> Result<Watchdog> {
+		Ok(Watchdog::new(self.keepalive).with_stop(interrupt_flag()?))
+	}
+
+	fn ping(&self) -> Result<Duration> {
+		Ok(self.open()?.ping()?)
+	}
+}
+
+fn cmd_info(target: &Target, format: OutputFormat) -> Result<()> {
+	let dev = target.open()?;
+	output::emit(&InfoOutput::new(&dev.info), format)
+}
+
+// Matches the USB serial number first, which needs no handshake, then the hardware ID
+// of every hub that answers. Ports are tried in name order, and more than one match is
+// an error rather than a guess.
+fn select_device(options: &DeviceOptions, selector: &str) -> Result<String> {
+	let mut hubs = device::detect_hubs()?;
+	if hubs.is_empty() {
+		bail!("no hubs attached");
+	}
+	hubs.sort_by(|a, b| a.port.cmp(&b.port));
+
+	let by_serial: Vec<_> = hubs
+		.iter()
+		.filter(|h| {
+			h.serial
+				.as_deref()
+				.is_some_and(|s| s.eq_ignore_ascii_case(selector))
+		})
+		.map(|h| h.port.clone())
+		.collect();
+	let matches = if !by_serial.is_empty() {
+		by_serial
+	} else if let Some(hw_id) = parse_hw_id(selector) {
+		hubs.into_iter()
+			.filter(|h| {
+				Device::builder(&h.port)
+					.options(options.clone())
+					.open()
+					.is_ok_and(|dev| dev.info.hw_id == hw_id)
+			})
+			.map(|h| h.port)
+			.collect()
+	} else {
+		Vec::new()
+	};
+
+	match matches.as_slice() {
+		[port] => Ok(port.clone()),
+		[] => bail!(
+			"no attached hub has serial number or hardware ID {selector} (see `hm-hub devices`)"
+		),
+		ports => bail!(
+			"{} hubs match {selector}: {}; use --port to pick one",
+			ports.len(),
+			ports.join(", ")
+		),
+	}
+}
+
+// Hardware IDs are shown as 0x-prefixed hex; plain decimal is accepted too.
+fn parse_hw_id(s: &str) -> Option<u32> {
+	match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+		Some(hex) => u32::from_str_radix(hex, 16).ok(),
+		None => s.parse().ok(),
+	}
+}
+
+// A hub that doesn't answer is listed with its error rather than failing the command.
+fn cmd_devices(target: &Target, format: OutputFormat) -> Result<()> {
+	let rows = device::detect_hubs()?
+		.into_iter()
+		.map(|hub| {
+			let target = Target {
+				port: hub.port.clone(),
+				..target.clone()
+			};
+			match target.open() {
+				Ok(dev) => DeviceRow::new(hub, &dev.info),
+				Err(e) => DeviceRow::failed(hub, &e),
+			}
+		})
+		.collect();
+	output::emit(&DevicesOutput(rows), format)
+}
+
+fn cmd_list(target: &Target, format: OutputFormat) -> Result<()> {
+	let mut dev = target.open()?;
+	dev.progress = ProgressMode::Hidden;
+	let header = dev.read_flash_range(0, FLASH_HEADER_AREA)?;
+	let headers = flash::read_headers(&header)?;
+	output::emit(&ListOutput::new(&headers), format)
+}
+
+fn cmd_du(target: &Target, fps: u32, format: OutputFormat) -> Result<()> {
+	if fps == 0 {
+		bail!("--fps must be at least 1");
 	}
+	let mut dev = target.open()?;
+	dev.progress = ProgressMode::Hidden;
+	let header = dev.read_flash_range(0, FLASH_HEADER_AREA)?;
+	let used = flash::used_length(&header)?;
+	let slots = flash::header_slots_used(&header);
+	output::emit(
+		&DuOutput::new(dev.info.flash_size, used, slots, fps),
+		format,
+	)
 }
 
-fn cmd_info(port: &str) -> Result<()> {
-	let dev = Device::open(port)?;
-	let info = &dev.info;
-	println!("HM Lab Z-NEO 8K USB Hub");
-	println!("  Hardware ID:    {:#010x}", info.hw_id);
-	println!("  Firmware:       {}", info.fw_version_string());
-	println!("  Flash size:     {} MB", info.flash_size / 1024 / 1024);
-	println!("  Max frames:     {}", info.max_frames());
+// Only the used part of flash is read back and rewritten, up to the new end of data.
+fn cmd_delete(target: &Target, index: usize, yes: bool) -> Result<()> {
+	let mut dev = target.open()?;
+	let header = dev.read_flash_range(0, FLASH_HEADER_AREA)?;
+	let headers = flash::read_headers(&header)?;
+	let Some(album) = headers.get(index) else {
+		bail!("no album {index}; the device holds {}", headers.len());
+	};
+	confirm(
+		yes,
+		&format!(
+			"This will remove album {index} ({} frame(s)) and move the albums after it up.",
+			album.frame_count
+		),
+	)?;
+
+	let data = dev.read_flash_range(0, flash::used_length(&header)?)?;
+	let keep: Vec<usize> = (0..headers.len()).filter(|&i| i != index).collect();
+	let buffer = flash::select_albums(&data, &keep, dev.info.flash_size)?;
+	dev.upload_flash(&buffer)?;
+	history::record(
+		target
+			.entry(&dev, &format!("delete {index}"))
+			.flash(&buffer),
+	);
+	println!(
+		"Deleted album {index}; {} album(s) left, {} KB rewritten.",
+		keep.len(),
+		buffer.len() / 1024
+	);
 	Ok(())
 }
 
-fn cmd_config(port: &str, action: Option<ConfigAction>) -> Result<()> {
+// Uploading just the header area leaves the image data after it in place, but only if
+// the firmware erases no more than it is sent. That hasn't been confirmed on hardware.
+fn cmd_reorder(
+	target: &Target,
+	order: Vec<usize>,
+	moved: Option<(usize, usize)>,
+	yes: bool,
+) -> Result<()> {
+	let mut dev = target.open()?;
+	let header = dev.read_flash_range(0, FLASH_HEADER_AREA)?;
+	let count = flash::header_slots_used(&header);
+	let order = album_order(count, order, moved)?;
+	if order.iter().copied().eq(0..count) {
+		println!("Album order unchanged.");
+		return Ok(());
+	}
+	let buffer = flash::reorder_headers(&header, &order)?;
+	confirm_header_only(yes, &format!("This will reorder all {count} albums."))?;
+	dev.upload_flash(&buffer)?;
+	let order: Vec<String> = order.iter().map(usize::to_string).collect();
+	history::record(
+		target
+			.entry(&dev, &format!("reorder {}", order.join(" ")))
+			.flash(&buffer),
+	);
+	println!(
+		"Reordered albums (old indices in new order): {}",
+		order.join(" ")
+	);
+	Ok(())
+}
+
+// Same header-only upload, and the same unverified erase assumption, as reorder.
+fn cmd_set_delay(target: &Target, index: usize, ms: u16, yes: bool) -> Result<()> {
+	let mut dev = target.open()?;
+	let header = dev.read_flash_range(0, FLASH_HEADER_AREA)?;
+	let buffer = flash::set_album_delay(&header, index, ms)?;
+	let old = &flash::read_headers(&header)?[index];
+	if old.frame_count == 1 {
+		eprintln!("Album {index} is a single image; the delay only matters for animations.");
+	}
+	confirm_header_only(
+		yes,
+		&format!(
+			"This will change album {index}'s frame delay from {}ms to {ms}ms.",
+			old.delay_ms
+		),
+	)?;
+	dev.upload_flash(&buffer)?;
+	history::record(
+		target
+			.entry(&dev, &format!("set-delay {index} {ms}"))
+			.flash(&buffer),
+	);
+	println!(
+		"Album {index} now shows each frame for {ms}ms (was {}ms).",
+		old.delay_ms
+	);
+	Ok(())
+}
+
+// The full new order: either the listed indices followed by the rest, or one album
+// moved to a new position.
+fn album_order(
+	count: usize,
+	listed: Vec<usize>,
+	moved: Option<(usize, usize)>,
+) -> Result<Vec<usize>> {
+	let in_range = |i: usize| {
+		if i >= count {
+			bail!("no album {i}; the device holds {count}");
+		}
+		Ok(())
+	};
+	if let Some((from, to)) = moved {
+		in_range(from)?;
+		in_range(to)?;
+		let mut order: Vec<usize> = (0..count).collect();
+		order.remove(from);
+		order.insert(to, from);
+		return Ok(order);
+	}
+	let mut order = Vec::with_capacity(count);
+	for i in listed {
+		in_range(i)?;
+		if order.contains(&i) {
+			bail!("album {i} is listed twice");
+		}
+		order.push(i);
+	}
+	order.extend(
+		(0..count)
+			.filter(|i| !order.contains(i))
+			.collect::<Vec<_>>(),
+	);
+	Ok(order)
+}
+
+// Errors are counted rather than returned: a flaky link is what this is looking for.
+fn cmd_doctor(target: &Target, rounds: u32, format: OutputFormat) -> Result<()> {
+	let mut dev = target.open()?;
+	dev.progress = ProgressMode::Hidden;
+	let mut rtts = Vec::new();
+	for _ in 0..rounds {
+		if let Ok(rtt) = dev.ping() {
+			rtts.push(rtt);
+		}
+	}
+	let config_ok = dev.read_config().is_ok();
+	let readback_ok = dev.read_flash_range(0, consts::FLASH_HEADER_AREA).is_ok();
+	output::emit(
+		&DoctorOutput::new(rounds, &rtts, config_ok, readback_ok, &dev.link_stats()),
+		format,
+	)
+}
+
+fn cmd_config(
+	target: &Target,
+	action: Option<ConfigAction>,
+	yes: bool,
+	format: OutputFormat,
+) -> Result<()> {
 	match action {
 		None => {
-			let mut dev = Device::open(port)?;
+			let mut dev = target.open()?;
 			let config = dev.read_config()?;
-			println!("{config}");
+			output::emit(&ConfigOutput::new(&config), format)?;
 		}
-		Some(ConfigAction::Set { field, value }) => match (field, value) {
-			(Some(f), Some(v)) => {
-				let mut dev = Device::open(port)?;
-				let mut config = dev.read_config()?;
-				config.set_field(&f, &v)?;
-				dev.write_config(&config)?;
-				println!("Set {f} = {v}");
-			}
-			_ => {
-				println!("Available config fields:");
-				println!("  brightness <0-30>        Screen brightness");
-				println!("  rotation <0|90|180|270>  Screen rotation");
-				println!("  interval <seconds>       Image switch interval");
-				println!("  random <0|1>             Random image order");
-				println!("  crop <0|1>               Crop to fill (1) or letterbox (0)");
-				println!("  shake_sens <0-255>       Shake sensitivity");
-				println!("  screen_onoff_by_usb <0|1> Screen on/off with USB");
-				println!("  power_style <0-255>      Power display style");
-				println!("  srgb_style <0-255>       sRGB style");
-				println!("  switch_mode <0-65535>    Image switch mode");
-				println!("  page <0-255>             Memory page");
+		Some(ConfigAction::Set { args }) if args.is_empty() => {
+			print!("{}", types::config_fields_help())
+		}
+		Some(ConfigAction::Set { args }) => {
+			let assignments = config_assignments(&args)?;
+			let mut dev = target.open()?;
+			let mut config = dev.read_config()?;
+			// Every field is checked before anything is written, so a bad value leaves
+			// the device untouched.
+			for (f, v) in &assignments {
+				config.set_field(f, v)?;
 			}
-		},
+			dev.write_config(&config)?;
+			let changes: Vec<String> = assignments
+				.iter()
+				.map(|(f, v)| format!("{f} = {v}"))
+				.collect();
+			for change in &changes {
+				println!("Set {change}");
+			}
+			history::record(target.entry(&dev, "config").changes(changes));
+		}
+		Some(ConfigAction::Get { field }) => {
+			let mut dev = target.open()?;
+			let value = dev.read_config()?.get_field(&field)?;
+			output::emit(&ConfigFieldOutput { field, value }, format)?;
+		}
+		Some(ConfigAction::Export { file }) => {
+			let mut dev = target.open()?;
+			let config = dev.read_config()?;
+			ConfigFile::new(&config).save(&file)?;
+			println!("Config exported to {}", file.display());
+		}
+		Some(ConfigAction::Import { file }) => {
+			let state = State::load(&file)?;
+			if state.images.is_some() {
+				bail!(
+					"{} has an [images] section; use `hm-hub apply` for it",
+					file.display()
+				);
+			}
+			let mut dev = target.open()?;
+			let (desired, changes) = state.config_changes(&dev.read_config()?)?;
+			if changes.is_empty() {
+				println!("Config already matches {}", file.display());
+				return Ok(());
+			}
+			dev.write_config(&desired)?;
+			for change in &changes {
+				println!("Set {change}");
+			}
+			history::record(target.entry(&dev, "config").changes(changes));
+		}
+		Some(ConfigAction::Diff { file, against_port }) => {
+			let mut dev = target.open()?;
+			let current = dev.read_config()?;
+			let (name, reference) = match (file, against_port) {
+				(Some(file), _) => {
+					let (desired, _) = State::load(&file)?.config_changes(&current)?;
+					(file.display().to_string(), desired)
+				}
+				(None, Some(port)) => {
+					let other = Target {
+						port: port.clone(),
+						..target.clone()
+					};
+					(port, other.open()?.read_config()?)
+				}
+				(None, None) => unreachable!("clap requires a file or --against-port"),
+			};
+			output::emit(&ConfigDiffOutput::new(&name, &current, &reference), format)?;
+		}
+		Some(ConfigAction::Reset { pages }) => {
+			let mut dev = target.open()?;
+			let current = dev.read_config()?;
+			let active = current.memory_page;
+			// A factory reset erases every page but only the active one is put back, so
+			// refuse while any other page holds albums. A page whose header area matches
+			// the active one's (the headers carry each album's CRC) holds nothing extra;
+			// that is also what every page looks like if the firmware has only one.
+			let mut config = current.clone();
+			let mut occupied = Vec::new();
+			dev.progress = ProgressMode::Hidden;
+			let active_header = dev.read_flash_range(0, FLASH_HEADER_AREA)?;
+			let scan = (0..pages).filter(|&p| p != active).try_for_each(|page| {
+				select_page(&mut dev, &mut config, page)?;
+				let header = dev.read_flash_range(0, FLASH_HEADER_AREA)?;
+				if flash::header_slots_used(&header) > 0 && header != active_header {
+					occupied.push(page.to_string());
+				}
+				Ok::<_, anyhow::Error>(())
+			});
+			select_page(&mut dev, &mut config, active)?;
+			scan?;
+			if !occupied.is_empty() {
+				bail!(
+					"memory page(s) {} hold albums that a factory reset would erase; \
+					 only the active page {active} can be put back",
+					occupied.join(", ")
+				);
+			}
+			dev.progress = target.progress;
+
+			confirm(
+				yes,
+				&format!(
+					"This is DESTRUCTIVE: it factory resets the device, erasing all flash and \
+					 settings, to let the firmware restore its own default config. The albums on \
+					 page {active} are backed up first and uploaded again afterwards; if that \
+					 upload fails, restore them from the backup."
+				),
+			)?;
+			// There's no known way to ask the firmware for its defaults, so let it restore
+			// them and put the images back.
+			let images = if flash::header_slots_used(&active_header) > 0 {
+				dev.read_flash_range(0, flash::used_length(&active_header)?)?
+			} else {
+				Vec::new()
+			};
+			let backup = Backup {
+				config: current.clone(),
+				flash: images,
+			};
+			let backup_path = save_safety_backup(&dev, "config-reset", &backup)?;
+
+			dev.factory_reset()?;
+			// The hub may restart after a reset.
+			dev.reconnect()?;
+			let mut defaults = dev.read_config()?;
+			if !backup.flash.is_empty() {
+				select_page(&mut dev, &mut defaults, active)?;
+				dev.upload_flash(&backup.flash)?;
+			}
+			std::fs::remove_file(&backup_path)?;
+
+			let changes: Vec<String> = config_field_names()
+				.filter_map(|f| {
+					let value = defaults.get_field(f).ok()?;
+					(current.get_field(f).ok() != Some(value)).then(|| format!("{f} = {value}"))
+				})
+				.collect();
+			if changes.is_empty() {
+				println!("Config was already at the firmware defaults");
+			}
+			for change in &changes {
+				println!("Set {change}");
+			}
+			if !backup.flash.is_empty() && active != 0 {
+				println!("Kept page {active} selected so the restored albums stay visible.");
+			}
+			history::record(
+				target
+					.entry(&dev, "config reset")
+					.changes(changes)
+					.flash(&backup.flash),
+			);
+		}
 		Some(ConfigAction::Dump) => {
-			let mut dev = Device::open(port)?;
+			let mut dev = target.open()?;
 			let config = dev.read_config()?;
-			let bytes = config.to_bytes();
-			for (i, b) in bytes.iter().enumerate() {
-				if i > 0 && i % 16 == 0 {
-					println!();
+			output::emit(&ConfigDumpOutput::new(&config.to_bytes()), format)?;
+		}
+	}
+	Ok(())
+}
+
+// The one-field shortcuts (`hm-hub brightness 20`) go through config set.
+fn cmd_set(
+	target: &Target,
+	field: &str,
+	value: impl ToString,
+	yes: bool,
+	format: OutputFormat,
+) -> Result<()> {
+	let args = vec![field.to_string(), value.to_string()];
+	cmd_config(target, Some(ConfigAction::Set { args }), yes, format)
+}
+
+// `config set` takes either one `field value` pair or any number of `field=value`.
+fn config_assignments(args: &[String]) -> Result<Vec<(String, String)>> {
+	if let [field, value] = args {
+		if !field.contains('=') && !value.contains('=') {
+			return Ok(vec![(field.clone(), value.clone())]);
+		}
+	}
+	args.iter()
+		.map(|arg| match arg.split_once('=') {
+			Some((f, v)) if !f.is_empty() => Ok((f.to_string(), v.to_string())),
+			_ => bail!("expected FIELD=VALUE, got '{arg}'"),
+		})
+		.collect()
+}
+
+fn cmd_man(out: &Path) -> Result<()> {
+	std::fs::create_dir_all(out)?;
+	clap_mangen::generate_to(Cli::command(), out)?;
+	println!("Man pages written to {}", out.display());
+	Ok(())
+}
+
+fn cmd_internals() -> Result<()> {
+	println!("{}", serde_json::to_string_pretty(&InternalsOutput::new())?);
+	Ok(())
+}
+
+fn cmd_preview(
+	specs: &[ImageSpec],
+	args: &ImageArgs,
+	out: &Path,
+	report: bool,
+	format: OutputFormat,
+) -> Result<()> {
+	let opts = args.load_options();
+	let specs = expand_image_specs(specs)?;
+
+	if report {
+		let mut rows = Vec::new();
+		for spec in &specs {
+			let opts = LoadOptions {
+				crop: spec.crop.unwrap_or(opts.crop),
+				..opts
+			};
+			let quality = image::conversion_quality(&spec.path, &opts)?;
+			rows.push(QualityRow::new(&spec.path, &quality));
+		}
+		return output::emit(&QualityReport(rows), format);
+	}
+
+	std::fs::create_dir_all(out)?;
+	for spec in &specs {
+		let opts = LoadOptions {
+			crop: spec.crop.unwrap_or(opts.crop),
+			..opts
+		};
+		let album = image::load_image(&spec.path, &opts)?;
+		let stem = spec
+			.path
+			.file_stem()
+			.map(|s| s.to_string_lossy())
+			.unwrap_or_default();
+		let dest = out.join(format!("{stem}.preview.png"));
+		image::rgb565_to_image(
+			&album.frames[0],
+			DISPLAY_WIDTH as u16,
+			DISPLAY_HEIGHT as u16,
+		)
+		.save(&dest)?;
+		println!("Preview saved to {}", dest.display());
+	}
+	Ok(())
+}
+
+fn cmd_history(
+	device: Option<&str>,
+	action: Option<&str>,
+	limit: Option<usize>,
+	format: OutputFormat,
+) -> Result<()> {
+	let mut entries = history::load()?;
+	entries.retain(|e| {
+		device.is_none_or(|d| e.device.eq_ignore_ascii_case(d))
+			&& action.is_none_or(|a| e.action == a)
+	});
+	if let Some(limit) = limit {
+		let skip = entries.len().saturating_sub(limit);
+		entries.drain(..skip);
+	}
+	output::emit(&history::HistoryOutput(entries), format)
+}
+
+fn cmd_upload(target: &Target, args: &UploadArgs, yes: bool) -> Result<()> {
+	let opts = args.image.load_options();
+	let mut sources = Vec::new();
+	let mut albums = match &args.from_export {
+		Some(dir) => {
+			sources.push(dir.clone());
+			eprintln!("Loading export from {}...", dir.display());
+			let albums = export::load_albums(dir)?;
+			eprintln!("  {} album(s)", albums.len());
+			albums
+		}
+		None => {
+			let jobs = expand_image_specs(&args.images)?
+				.into_iter()
+				.map(|spec| {
+					let opts = LoadOptions {
+						crop: spec.crop.unwrap_or(opts.crop),
+						..opts
+					};
+					(spec.path, opts)
+				})
+				.collect::<Vec<_>>();
+			sources.extend(jobs.iter().map(|(path, _)| path.clone()));
+			load_images(jobs)?
+		}
+	};
+
+	if let Some(preview_path) = &args.preview {
+		if let Some(first_frame) = albums.first().and_then(|a| a.frames.first()) {
+			let img =
+				image::rgb565_to_image(first_frame, DISPLAY_WIDTH as u16, DISPLAY_HEIGHT as u16);
+			img.save(preview_path)?;
+			println!("Preview saved to {}", preview_path.display());
+		}
+		return Ok(());
+	}
+
+	let mut dev = target.open()?;
+	if args.append {
+		return upload_append(target, &mut dev, albums, args, &sources);
+	}
+	if let Some(slot) = args.slot {
+		let Ok([album]) = <[Album; 1]>::try_from(albums) else {
+			bail!("--slot replaces one album; give a single image or GIF");
+		};
+		return upload_slot(target, &mut dev, slot, album, args, &sources, yes);
+	}
+	let max = dev.info.max_frames();
+	interpolate_within_budget(&mut albums, args.image.interpolate, max);
+	let total_frames: usize = albums.iter().map(|a| a.frames.len()).sum();
+	eprintln!("Total: {total_frames} frame(s) (max: {max})");
+	confirm_overwrite(&mut dev, albums.len(), total_frames, yes)?;
+
+	if args.split_pages && total_frames > max {
+		upload_split(&mut dev, albums, args.blank)?;
+		history::record(target.entry(&dev, "upload").files(&sources));
+		return Ok(());
+	}
+
+	let flash_data = flash::build_flash_buffer(&albums, dev.info.flash_size)?;
+	upload_blanked(&mut dev, &flash_data, args.blank)?;
+	history::record(
+		target
+			.entry(&dev, "upload")
+			.files(&sources)
+			.flash(&flash_data),
+	);
+	Ok(())
+}
+
+// The device always writes from the start of flash, so the existing albums are read
+// back (only as far as they reach) and sent again ahead of the new ones.
+fn upload_append(
+	target: &Target,
+	dev: &mut Device,
+	mut albums: Vec<Album>,
+	args: &UploadArgs,
+	sources: &[PathBuf],
+) -> Result<()> {
+	let header = dev.read_flash_range(0, FLASH_HEADER_AREA)?;
+	let existing = flash::read_headers(&header)?;
+	let used: usize = existing.iter().map(|h| h.frame_count as usize).sum();
+	let budget = dev.info.max_frames().saturating_sub(used);
+	interpolate_within_budget(&mut albums, args.image.interpolate, budget);
+	let frames: usize = albums.iter().map(|a| a.frames.len()).sum();
+	eprintln!(
+		"Appending {} album(s), {frames} frame(s), after the {} on the device ({budget} frame(s) free)",
+		albums.len(),
+		existing.len()
+	);
+
+	let data = dev.read_flash_range(0, flash::used_length(&header)?)?;
+	let flash_data = flash::append_albums(&data, albums, dev.info.flash_size)?;
+	upload_blanked(dev, &flash_data, args.blank)?;
+	history::record(
+		target
+			.entry(dev, "upload --append")
+			.files(sources)
+			.flash(&flash_data),
+	);
+	Ok(())
+}
+
+fn upload_slot(
+	target: &Target,
+	dev: &mut Device,
+	slot: usize,
+	album: Album,
+	args: &UploadArgs,
+	sources: &[PathBuf],
+	yes: bool,
+) -> Result<()> {
+	let header = dev.read_flash_range(0, FLASH_HEADER_AREA)?;
+	let extent = flash::replace_extent(&header, slot, album.frames.len(), args.in_place)?;
+	let old = &flash::read_headers(&header)?[slot];
+	let summary = format!(
+		"This will replace album {slot} ({} frame(s)) with {} frame(s).",
+		old.frame_count,
+		album.frames.len()
+	);
+	if extent < flash::used_length(&header)? {
+		confirm(
+			yes,
+			&format!(
+				"{summary} Only the first {} KB is uploaded, which keeps the albums after it \
+				 only if the hub erases no more than it is sent. That is unverified; if it \
+				 erases the whole flash, they are lost. Take a backup first.",
+				extent.div_ceil(1024)
+			),
+		)?;
+	} else {
+		confirm(yes, &summary)?;
+	}
+	let data = dev.read_flash_range(0, extent)?;
+	let flash_data = flash::replace_album(&data, slot, album, dev.info.flash_size, args.in_place)?;
+	upload_blanked(dev, &flash_data, args.blank)?;
+	history::record(
+		target
+			.entry(dev, &format!("upload --slot {slot}"))
+			.files(sources)
+			.flash(&flash_data),
+	);
+	Ok(())
+}
+
+fn expand_image_specs(specs: &[ImageSpec]) -> Result<Vec<ImageSpec>> {
+	let mut expanded: Vec<ImageSpec> = Vec::new();
+	let mut seen = HashSet::new();
+	for spec in specs {
+		let paths = if spec.path.is_dir() {
+			let paths = collect_images(&spec.path)?;
+			if paths.is_empty() {
+				bail!("no images found in {}", spec.path.display());
+			}
+			eprintln!("Found {} image(s) in {}", paths.len(), spec.path.display());
+			paths
+		} else if is_glob(&spec.path) {
+			// cmd.exe leaves wildcards to the program, so expand them ourselves.
+			let pattern = spec.path.to_string_lossy();
+			let mut paths = glob::glob(&pattern)?.collect::<Result<Vec<_>, _>>()?;
+			paths.retain(|p| p.is_file());
+			if paths.is_empty() {
+				bail!("no files match {pattern}");
+			}
+			paths.sort();
+			image::select_variants(paths, DISPLAY_WIDTH, DISPLAY_HEIGHT)
+		} else {
+			vec![spec.path.clone()]
+		};
+
+		for path in paths {
+			let key = std::fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+			if !seen.insert(key) {
+				eprintln!("Skipping duplicate {}", path.display());
+				continue;
+			}
+			expanded.push(ImageSpec {
+				path,
+				crop: spec.crop,
+			});
+		}
+	}
+	Ok(expanded)
+}
+
+fn is_glob(path: &Path) -> bool {
+	!path.exists() && path.to_string_lossy().contains(['*', '?', '['])
+}
+
+fn interpolate_within_budget(albums: &mut [Album], requested: u8, max_frames: usize) {
+	if requested == 0 {
+		return;
+	}
+	let static_frames: usize = albums
+		.iter()
+		.filter(|a| a.frames.len() < 2)
+		.map(|a| a.frames.len())
+		.sum();
+	let animated_frames: usize = albums
+		.iter()
+		.filter(|a| a.frames.len() >= 2)
+		.map(|a| a.frames.len())
+		.sum();
+	if animated_frames == 0 {
+		return;
+	}
+
+	let mut steps = requested as usize;
+	while steps > 0 && static_frames + animated_frames * (steps + 1) > max_frames {
+		steps -= 1;
+	}
+	if steps < requested as usize {
+		eprintln!("Interpolation reduced to {steps} frame(s) to fit device capacity");
+	}
+	if steps == 0 {
+		return;
+	}
+
+	for album in albums.iter_mut().filter(|a| a.frames.len() >= 2) {
+		crate::image::interpolate(album, steps);
+	}
+}
+
+fn cmd_slideshow(
+	target: &Target,
+	dir: &Path,
+	image: &ImageArgs,
+	blank: bool,
+	dedupe: bool,
+	yes: bool,
+) -> Result<()> {
+	let mut albums = load_directory(dir, image, dedupe)?;
+
+	let mut dev = target.open()?;
+	let max = dev.info.max_frames();
+	interpolate_within_budget(&mut albums, image.interpolate, max);
+	let total_frames: usize = albums.iter().map(|a| a.frames.len()).sum();
+	eprintln!("Total: {total_frames} frame(s) (max: {max})");
+	confirm_overwrite(&mut dev, albums.len(), total_frames, yes)?;
+
+	let flash_data = flash::build_flash_buffer(&albums, dev.info.flash_size)?;
+	upload_blanked(&mut dev, &flash_data, blank)?;
+	history::record(
+		target
+			.entry(&dev, "slideshow")
+			.files(&[dir.to_path_buf()])
+			.flash(&flash_data),
+	);
+	Ok(())
+}
+
+fn cmd_sync(target: &Target, dir: &Path, image: &ImageArgs, blank: bool, yes: bool) -> Result<()> {
+	let mut albums = load_directory(dir, image, false)?;
+
+	let mut dev = target.open()?;
+	let plan = SyncPlan::new(&mut dev, &mut albums, image.interpolate)?;
+	match plan.current {
+		Some(current) if current == plan.hash => {
+			println!("Already in sync ({:08x}), nothing to do", plan.hash);
+			return Ok(());
+		}
+		Some(current) => eprintln!(
+			"Device content {current:08x} differs from {:08x}",
+			plan.hash
+		),
+		None => eprintln!("Device content was not written by sync"),
+	}
+
+	let total_frames: usize = albums.iter().map(|a| a.frames.len()).sum();
+	confirm_overwrite(&mut dev, albums.len(), total_frames, yes)?;
+	upload_blanked(&mut dev, &plan.flash_data, blank)?;
+	history::record(
+		target
+			.entry(&dev, "sync")
+			.files(&[dir.to_path_buf()])
+			.flash(&plan.flash_data),
+	);
+	println!("Synced {} album(s) ({:08x})", albums.len(), plan.hash);
+	Ok(())
+}
+
+struct SyncPlan {
+	flash_data: Vec<u8>,
+	hash: u32,
+	current: Option<u32>,
+}
+
+impl SyncPlan {
+	fn new(dev: &mut Device, albums: &mut [Album], interpolate: u8) -> Result<Self> {
+		interpolate_within_budget(albums, interpolate, dev.info.max_frames());
+		let mut flash_data = flash::build_flash_buffer(albums, dev.info.flash_size)?;
+		let hash = flash::stamp_sync_hash(&mut flash_data)?;
+		let header = dev.read_flash_range(0, FLASH_HEADER_AREA)?;
+		Ok(Self {
+			flash_data,
+			hash,
+			current: flash::read_sync_hash(&header),
+		})
+	}
+
+	fn in_sync(&self) -> bool {
+		self.current == Some(self.hash)
+	}
+}
+
+fn cmd_apply(target: &Target, path: &Path, plan_only: bool, blank: bool, yes: bool) -> Result<()> {
+	let state = State::load(path)?;
+	let mut dev = target.open()?;
+
+	let current = dev.read_config()?;
+	let (desired, config_changes) = state.config_changes(&current)?;
+
+	let mut images = None;
+	if let Some(image_state) = &state.images {
+		let args = image_state.image_args()?;
+		let mut albums = load_directory(&image_state.dir, &args, false)?;
+		let plan = SyncPlan::new(&mut dev, &mut albums, args.interpolate)?;
+		if !plan.in_sync() {
+			images = Some((albums, plan));
+		}
+	}
+
+	if config_changes.is_empty() && images.is_none() {
+		println!("Device matches {}, nothing to do", path.display());
+		return Ok(());
+	}
+
+	println!("Plan:");
+	for change in &config_changes {
+		println!("  ~ config {change}");
+	}
+	if let Some((albums, plan)) = &images {
+		let frames: usize = albums.iter().map(|a| a.frames.len()).sum();
+		println!(
+			"  ~ images: upload {} album(s), {frames} frame(s) ({:08x})",
+			albums.len(),
+			plan.hash
+		);
+	}
+	if plan_only {
+		return Ok(());
+	}
+
+	// One confirmation for the whole plan, before anything is written.
+	if let Some((albums, _)) = &images {
+		let frames: usize = albums.iter().map(|a| a.frames.len()).sum();
+		confirm_overwrite(&mut dev, albums.len(), frames, yes)?;
+	}
+
+	let mut entry = target.entry(&dev, "apply").files(&[path.to_path_buf()]);
+	if !config_changes.is_empty() {
+		dev.write_config(&desired)?;
+		println!("Applied {} config change(s)", config_changes.len());
+		entry = entry.changes(config_changes);
+	}
+	if let Some((albums, plan)) = images {
+		// The config is already written, so keep a record of it even if the upload fails.
+		if let Err(e) = upload_blanked(&mut dev, &plan.flash_data, blank) {
+			history::record(entry);
+			return Err(e);
+		}
+		println!("Uploaded {} album(s)", albums.len());
+		entry = entry.flash(&plan.flash_data);
+	}
+	history::record(entry);
+	Ok(())
+}
+
+fn cmd_fleet_push(
+	target: &Target,
+	path: &Path,
+	canary: usize,
+	listed: Vec<String>,
+	blank: bool,
+	yes: bool,
+) -> Result<()> {
+	let state = State::load(path)?;
+	let ports = if listed.is_empty() {
+		device::detect_ports()?
+	} else {
+		listed
+	};
+	if ports.is_empty() {
+		bail!("no hubs attached; pass --port to name them");
+	}
+	let canary = canary.min(ports.len());
+	confirm(
+		yes,
+		&format!(
+			"This will apply {} to {} hub(s), replacing their images if they differ.",
+			path.display(),
+			ports.len()
+		),
+	)?;
+
+	// Canary hubs are a stage of their own: every byte written is read back, and the
+	// rest of the fleet waits for a second confirmation.
+	for (i, port) in ports.iter().enumerate() {
+		if i == canary && canary > 0 && canary < ports.len() {
+			confirm(
+				yes,
+				&format!(
+					"{canary} canary hub(s) updated and read back in full. Roll out to the remaining {} hub(s)?",
+					ports.len() - canary
+				),
+			)?;
+			println!(
+				"Canary passed, rolling out to {} more hub(s)",
+				ports.len() - canary
+			);
+		}
+		let is_canary = i < canary;
+		let label = if is_canary { "canary " } else { "" };
+		println!("[{label}{}/{}] {port}", i + 1, ports.len());
+		let target = Target {
+			port: port.clone(),
+			..target.clone()
+		};
+		match push_state(&target, path, &state, blank, is_canary) {
+			Ok(summary) if is_canary => println!("  {summary}, verified in full"),
+			Ok(summary) => println!("  {summary}, verified"),
+			Err(e) => {
+				let untouched = ports.len() - i - 1;
+				let stage = if is_canary { "canary failed, " } else { "" };
+				bail!("{port} failed: {e:#}\n{stage}rollout aborted, {untouched} hub(s) left untouched");
+			}
+		}
+	}
+	println!("All {} hub(s) updated", ports.len());
+	Ok(())
+}
+
+// Applies `state` without prompting, then reads config and the flash header back to
+// check the hub really holds it. With `full_readback` the whole image area is read
+// back and compared, not just the header.
+fn push_state(
+	target: &Target,
+	path: &Path,
+	state: &State,
+	blank: bool,
+	full_readback: bool,
+) -> Result<String> {
+	let mut dev = target.open()?;
+	let current = dev.read_config()?;
+	let (desired, config_changes) = state.config_changes(&current)?;
+	let mut entry = target
+		.entry(&dev, "fleet push")
+		.files(&[path.to_path_buf()]);
+	let mut summary = Vec::new();
+
+	if !config_changes.is_empty() {
+		dev.write_config(&desired)?;
+		summary.push(format!("{} config change(s)", config_changes.len()));
+		entry = entry.changes(config_changes);
+	}
+	let mut hash = None;
+	let mut expected = None;
+	if let Some(image_state) = &state.images {
+		let args = image_state.image_args()?;
+		let mut albums = load_directory(&image_state.dir, &args, false)?;
+		let plan = SyncPlan::new(&mut dev, &mut albums, args.interpolate)?;
+		if !plan.in_sync() {
+			upload_blanked(&mut dev, &plan.flash_data, blank)?;
+			summary.push(format!("uploaded {} album(s)", albums.len()));
+			entry = entry.flash(&plan.flash_data);
+		}
+		hash = Some(plan.hash);
+		if full_readback {
+			expected = Some(plan.flash_data);
+		}
+	}
+	history::record(entry);
+
+	if dev.read_config()?.to_bytes() != desired.to_bytes() {
+		bail!("config read back differs from what was written");
+	}
+	if let Some(hash) = hash {
+		let header = dev.read_flash_range(0, FLASH_HEADER_AREA)?;
+		if flash::read_sync_hash(&header) != Some(hash) {
+			bail!("flash header read back does not match the upload");
+		}
+	}
+	if let Some(expected) = expected {
+		if dev.read_flash_range(0, expected.len())? != expected {
+			bail!("image data read back does not match the upload");
+		}
+	}
+
+	Ok(if summary.is_empty() {
+		"already up to date".to_string()
+	} else {
+		summary.join(", ")
+	})
+}
+
+fn load_directory(dir: &Path, image: &ImageArgs, dedupe: bool) -> Result<Vec<Album>> {
+	if !dir.is_dir() {
+		bail!("{} is not a directory", dir.display());
+	}
+
+	let mut paths = collect_images(dir)?;
+	if paths.is_empty() {
+		bail!("no images found in {}", dir.display());
+	}
+
+	eprintln!("Found {} image(s) in {}", paths.len(), dir.display());
+	let opts = image.load_options();
+	if dedupe {
+		paths = dedupe_images(paths, &opts)?;
+	}
+
+	load_images(paths.into_iter().map(|path| (path, opts)).collect())
+}
+
+// Decodes on all cores; results arrive through a bounded channel as they finish and
+// are put back in argument order.
+fn load_images(jobs: Vec<(PathBuf, LoadOptions)>) -> Result<Vec<Album>> {
+	let workers = std::thread::available_parallelism()
+		.map(|n| n.get())
+		.unwrap_or(1)
+		.min(jobs.len().max(1));
+	let next = AtomicUsize::new(0);
+	let mut albums: Vec<Option<Album>> = (0..jobs.len()).map(|_| None).collect();
+
+	std::thread::scope(|scope| -> Result<()> {
+		let (tx, rx) = mpsc::sync_channel(workers);
+		for _ in 0..workers {
+			let tx = tx.clone();
+			let (jobs, next) = (&jobs, &next);
+			scope.spawn(move || loop {
+				let i = next.fetch_add(1, Ordering::SeqCst);
+				let Some((path, opts)) = jobs.get(i) else {
+					break;
+				};
+				if tx.send((i, crate::image::load_image(path, opts))).is_err() {
+					break;
+				}
+			});
+		}
+		drop(tx);
+
+		for (i, result) in rx {
+			let path = &jobs[i].0;
+			let album = match result {
+				Ok(album) => album,
+				Err(e) => {
+					// Stop handing out work; running decodes finish and are dropped.
+					next.store(jobs.len(), Ordering::SeqCst);
+					return Err(e.context(format!("failed to load {}", path.display())));
+				}
+			};
+			eprintln!(
+				"Loaded {} ({} frame(s))",
+				path.display(),
+				album.frames.len()
+			);
+			albums[i] = Some(album);
+		}
+		Ok(())
+	})?;
+
+	Ok(albums.into_iter().flatten().collect())
+}
+
+const DEDUPE_MAX_DISTANCE: u32 = 5;
+
+fn dedupe_images(
+	paths: Vec<std::path::PathBuf>,
+	opts: &LoadOptions,
+) -> Result<Vec<std::path::PathBuf>> {
+	let mut kept: Vec<(std::path::PathBuf, u64)> = Vec::new();
+	for path in paths {
+		let hash = crate::image::perceptual_hash(&path, opts.raw_size)?;
+		match kept
+			.iter()
+			.find(|(_, other)| (hash ^ other).count_ones() <= DEDUPE_MAX_DISTANCE)
+		{
+			Some((original, _)) => eprintln!(
+				"Skipping {} (looks like {})",
+				path.display(),
+				original.display()
+			),
+			None => kept.push((path, hash)),
+		}
+	}
+	Ok(kept.into_iter().map(|(path, _)| path).collect())
+}
+
+fn confirm(yes: bool, summary: &str) -> Result<()> {
+	if yes {
+		return Ok(());
+	}
+	if !std::io::stdin().is_terminal() {
+		bail!("{summary}\n{}", tr(Msg::NeedsConfirmation));
+	}
+	eprint!("{summary}\n{}", tr(Msg::ContinuePrompt));
+	std::io::stderr().flush()?;
+	let mut answer = String::new();
+	std::io::stdin().read_line(&mut answer)?;
+	match answer.trim().to_lowercase().as_str() {
+		"y" | "yes" => Ok(()),
+		_ => bail!("{}", tr(Msg::Aborted)),
+	}
+}
+
+// For commands that upload only the header area and count on the image data after it
+// surviving the erase.
+fn confirm_header_only(yes: bool, summary: &str) -> Result<()> {
+	confirm(
+		yes,
+		&format!(
+			"{summary} Only the {} KB header area is uploaded, which keeps the images only if \
+			 the hub erases no more than it is sent. That is unverified; if it erases the \
+			 whole flash, every album is lost. Take a backup first.",
+			FLASH_HEADER_AREA / 1024
+		),
+	)
+}
+
+fn confirm_overwrite(dev: &mut Device, albums: usize, frames: usize, yes: bool) -> Result<()> {
+	if yes {
+		return Ok(());
+	}
+	let page = dev.read_config()?.memory_page;
+	confirm(
+		false,
+		&trf(
+			Msg::ConfirmOverwrite,
+			&[
+				("page", page.to_string()),
+				("flash", (dev.info.flash_size / 1024 / 1024).to_string()),
+				("albums", albums.to_string()),
+				("frames", frames.to_string()),
+			],
+		),
+	)
+}
+
+fn upload_split(dev: &mut Device, albums: Vec<Album>, blank: bool) -> Result<()> {
+	let pages = flash::split_into_pages(albums, dev.info.flash_size)?;
+	let mut config = dev.read_config()?;
+	let first_page = config.memory_page;
+	let last_page = first_page as usize + pages.len() - 1;
+	if last_page > u8::MAX as usize {
+		bail!("not enough memory pages after page {first_page}");
+	}
+
+	for (i, page_albums) in pages.iter().enumerate() {
+		let page = first_page + i as u8;
+		eprintln!("Writing page {page} ({}/{})...", i + 1, pages.len());
+		config.memory_page = page;
+		dev.write_config(&config)?;
+		let flash_data = flash::build_flash_buffer(page_albums, dev.info.flash_size)?;
+		upload_blanked(dev, &flash_data, blank)?;
+	}
+
+	config.memory_page = first_page;
+	dev.write_config(&config)?;
+	println!("Content spans pages {first_page}-{last_page}.");
+	println!("Switch pages with `hm-hub config set page <n>`.");
+	Ok(())
+}
+
+fn upload_blanked(dev: &mut Device, flash_data: &[u8], blank: bool) -> Result<()> {
+	if !blank {
+		return Ok(dev.upload_flash(flash_data)?);
+	}
+
+	let config = dev.read_config()?;
+	let mut dark = config.clone();
+	dark.screen_brightness = 0;
+	dev.write_config(&dark)?;
+
+	let result = dev.upload_flash(flash_data);
+	dev.write_config(&config)?;
+	Ok(result?)
+}
+
+fn cmd_power(target: &Target, watch: bool, args: &WatchArgs, format: OutputFormat) -> Result<()> {
+	let mut dev = target.open()?;
+
+	if !watch {
+		let stats = dev.read_power()?;
+		return output::emit(&PowerOutput::new(&stats), format);
+	}
+
+	let mut session = watch_session(args)?;
+	let stop = interrupt_flag()?;
+	let mut status: Vec<Box<dyn Sink>> = vec![Box::new(StatusLine)];
+
+	while !stop.load(Ordering::SeqCst) && !session.is_complete() {
+		let stats = dev.read_power()?;
+		if format == OutputFormat::Json {
+			session.record(&stats);
+			if session.should_display() {
+				println!("{}", serde_json::to_string(&PowerOutput::new(&stats))?);
+			}
+		} else {
+			report_power_sample(&mut session, &stats, None, &mut status)?;
+		}
+	}
+
+	if format == OutputFormat::Human {
+		eprintln!();
+	}
+	session.print_summary();
+	Ok(())
+}
+
+fn cmd_monitor(target: &Target, args: &MonitorArgs) -> Result<()> {
+	let mut sinks = monitor_sinks(args)?;
+	let mut dev = target.open()?;
+	let mut session = watch_session(&args.session)?;
+	let stop = interrupt_flag()?;
+	let mut restore_page: Option<u8> = None;
+	let mut watchdog = target.watchdog()?;
+
+	let mut link_errors = link_error_count(&dev.link_stats());
+
+	eprintln!("Monitoring device (Ctrl+C to stop)...");
+	dev.monitor(|dev, stats| -> Result<ControlFlow<()>> {
+		if let Some(message) = watchdog.check(|| Ok(dev.ping()?)) {
+			let up = matches!(watchdog.health(), Health::Up(_));
+			emit(&mut sinks, &Event::Link { up, message })?;
+		}
+		let link = dev.link_stats();
+		if link_error_count(&link) != link_errors {
+			link_errors = link_error_count(&link);
+			emit(&mut sinks, &Event::LinkStats(link))?;
+		}
+		report_power_sample(&mut session, stats, Some(watchdog.health()), &mut sinks)?;
+
+		if let Some(page) = args.alert_page {
+			let critical = Rating::from_voltage(stats.bus_voltage) == Rating::Critical;
+			if critical && restore_page.is_none() {
+				restore_page = Some(switch_page(dev, page)?);
+				let alert =
+					Event::Alert(format!("Bus voltage critical, showing alert page {page}"));
+				emit(&mut sinks, &alert)?;
+			} else if !critical {
+				if let Some(previous) = restore_page.take() {
+					switch_page(dev, previous)?;
+					let alert =
+						Event::Alert(format!("Bus voltage recovered, restored page {previous}"));
+					emit(&mut sinks, &alert)?;
 				}
-				print!("{b:02x} ");
 			}
-			println!();
 		}
+
+		if stop.load(Ordering::SeqCst) || session.is_complete() {
+			Ok(ControlFlow::Break(()))
+		} else {
+			Ok(ControlFlow::Continue(()))
+		}
+	})?;
+
+	if let Some(previous) = restore_page {
+		switch_page(&mut dev, previous)?;
+	}
+
+	for sink in &mut sinks {
+		sink.finish()?;
 	}
+	session.print_summary();
+	eprintln!("  Link: {}", dev.link_stats());
 	Ok(())
 }
 
-fn cmd_upload(
-	port: &str,
-	images: &[std::path::PathBuf],
-	crop: bool,
-	preview: Option<&Path>,
-) -> Result<()> {
-	let mut albums = Vec::new();
-	for path in images {
-		eprintln!("Loading {}...", path.display());
-		let album = crate::image::load_image(path, crop)?;
-		eprintln!(
-			"  {} frame(s), {}x{}",
-			album.frames.len(),
-			DISPLAY_WIDTH,
-			DISPLAY_HEIGHT
-		);
-		albums.push(album);
-	}
+fn link_error_count(stats: &LinkStats) -> u64 {
+	stats.errors() + stats.retransmissions + stats.resyncs
+}
 
-	if let Some(preview_path) = preview {
-		if let Some(first_frame) = albums.first().and_then(|a| a.frames.first()) {
-			let img = rgb565_to_image(first_frame, DISPLAY_WIDTH as u16, DISPLAY_HEIGHT as u16);
-			img.save(preview_path)?;
-			println!("Preview saved to {}", preview_path.display());
-		}
-		return Ok(());
+fn monitor_sinks(args: &MonitorArgs) -> Result<Vec<Box<dyn Sink>>> {
+	let mut sinks: Vec<Box<dyn Sink>> = Vec::new();
+	if !args.quiet {
+		sinks.push(Box::new(StatusLine));
 	}
+	if args.ndjson {
+		sinks.push(Box::new(Ndjson::stdout()));
+	}
+	if let Some(path) = &args.log_file {
+		sinks.push(Box::new(Ndjson::file(path)?));
+	}
+	if args.syslog {
+		#[cfg(unix)]
+		sinks.push(Box::new(sinks::Syslog::connect()?));
+		#[cfg(not(unix))]
+		bail!("--syslog is only supported on Unix");
+	}
+	Ok(sinks)
+}
 
-	let mut dev = Device::open(port)?;
-	let max = dev.info.max_frames();
-	let total_frames: usize = albums.iter().map(|a| a.frames.len()).sum();
-	eprintln!("Total: {total_frames} frame(s) (max: {max})");
-
-	let flash_data = flash::build_flash_buffer(&albums, dev.info.flash_size)?;
-	dev.upload_flash(&flash_data)?;
+fn emit(sinks: &mut [Box<dyn Sink>], event: &Event) -> Result<()> {
+	for sink in sinks {
+		sink.emit(event)?;
+	}
 	Ok(())
 }
 
-fn cmd_slideshow(port: &str, dir: &Path, crop: bool) -> Result<()> {
-	if !dir.is_dir() {
-		bail!("{} is not a directory", dir.display());
+fn switch_page(dev: &mut Device, page: u8) -> Result<u8> {
+	let mut config = dev.read_config()?;
+	let previous = config.memory_page;
+	if previous != page {
+		config.memory_page = page;
+		dev.write_config(&config)?;
 	}
+	Ok(previous)
+}
 
-	let paths = collect_images(dir)?;
-	if paths.is_empty() {
-		bail!("no images found in {}", dir.display());
+fn report_power_sample(
+	session: &mut PowerSession,
+	stats: &PowerStats,
+	link: Option<&Health>,
+	sinks: &mut [Box<dyn Sink>],
+) -> Result<()> {
+	for port in session.record(stats) {
+		let event = Event::Limit {
+			port,
+			current_ma: power::port_currents(stats)[port - 1],
+			limit_ma: session.limit(port).unwrap_or_default(),
+		};
+		emit(sinks, &event)?;
 	}
-
-	eprintln!("Found {} image(s) in {}", paths.len(), dir.display());
-
-	let mut albums = Vec::new();
-	for path in &paths {
-		eprintln!("Loading {}...", path.display());
-		let album = crate::image::load_image(path, crop)?;
-		eprintln!("  {} frame(s)", album.frames.len());
-		albums.push(album);
+	if session.should_display() {
+		let mut status = session.status_line(stats);
+		if let Some(link) = link {
+			status.push_str(&format!(" | {}", link.describe()));
+		}
+		let event = Event::Sample { stats, status };
+		emit(sinks, &event)?;
 	}
-
-	let mut dev = Device::open(port)?;
-	let max = dev.info.max_frames();
-	let total_frames: usize = albums.iter().map(|a| a.frames.len()).sum();
-	eprintln!("Total: {total_frames} frame(s) (max: {max})");
-
-	let flash_data = flash::build_flash_buffer(&albums, dev.info.flash_size)?;
-	dev.upload_flash(&flash_data)?;
 	Ok(())
 }
 
-fn cmd_power(port: &str, watch: bool) -> Result<()> {
-	let mut dev = Device::open(port)?;
+fn watch_session(args: &WatchArgs) -> Result<PowerSession> {
+	let interval = Duration::from_millis(args.interval.unwrap_or(0));
+	Ok(PowerSession::new(&args.limits)?
+		.with_bounds(args.duration, args.count)
+		.with_display_interval(interval))
+}
 
-	loop {
-		let stats = dev.read_power()?;
-		let voltage = stats.bus_voltage as f64 / 1000.0;
-		let rating = if stats.bus_voltage >= 4750 {
-			"Healthy"
-		} else if stats.bus_voltage >= 4250 {
-			"Warning"
-		} else {
-			"Critical"
-		};
+// One handler for the whole process: the first Ctrl+C lets device operations and loops
+// wind down cleanly, a second one exits on the spot.
+fn interrupt_flag() -> Result<Arc<AtomicBool>> {
+	static FLAG: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+	if let Some(flag) = FLAG.get() {
+		return Ok(flag.clone());
+	}
+	let flag = Arc::new(AtomicBool::new(false));
+	let handler_flag = flag.clone();
+	ctrlc::set_handler(move || {
+		if handler_flag.swap(true, Ordering::SeqCst) {
+			std::process::exit(130);
+		}
+	})?;
+	Ok(FLAG.get_or_init(|| flag).clone())
+}
 
-		if watch {
-			eprint!(
-				"\rBus: {voltage:.2}V ({rating}) | Ports: {}mA {}mA {}mA   ",
-				stats.current_port1, stats.current_port2, stats.current_port3
-			);
-		} else {
-			println!("Bus voltage:  {voltage:.2}V ({rating})");
-			println!("Port 1:       {}mA", stats.current_port1);
-			println!("Port 2:       {}mA", stats.current_port2);
-			println!("Port 3:       {}mA", stats.current_port3);
-			return Ok(());
+fn cmd_replay(file: &Path, quiet: bool) -> Result<()> {
+	let entries = trace::read_trace(file)?;
+	let mut replayer = Replayer::new();
+	for entry in &entries {
+		for line in replayer.step(entry) {
+			if !quiet || line.starts_with('!') {
+				println!("{line}");
+			}
 		}
 	}
+	for line in replayer.finish() {
+		println!("{line}");
+	}
+	let span = match (entries.first(), entries.last()) {
+		(Some(first), Some(last)) => last.ts_ms.saturating_sub(first.ts_ms) as f64 / 1000.0,
+		_ => 0.0,
+	};
+	println!(
+		"{} packet(s) sent, {} received over {span:.1}s; {} anomal{}",
+		replayer.tx,
+		replayer.rx,
+		replayer.anomalies,
+		if replayer.anomalies == 1 { "y" } else { "ies" }
+	);
+	Ok(())
 }
 
-fn cmd_monitor(port: &str) -> Result<()> {
-	let mut dev = Device::open(port)?;
-	eprintln!("Monitoring device (Ctrl+C to stop)...");
-	dev.monitor()
+fn cmd_bridge(target: &Target, listen: &str) -> Result<()> {
+	if target.port.starts_with("tcp://") || target.port.starts_with("mock:") {
+		bail!("bridge needs a local serial port, not {}", target.port);
+	}
+	let port = serialport::new(&target.port, target.options.baud)
+		.flow_control(target.options.flow_control)
+		.timeout(target.options.timeout)
+		.open()?;
+	let listener = std::net::TcpListener::bind(listen)
+		.with_context(|| format!("failed to listen on {listen}"))?;
+	eprintln!(
+		"Sharing {} on {}; connect with --port tcp://{}",
+		target.port,
+		listener.local_addr()?,
+		listener.local_addr()?
+	);
+	let stop = interrupt_flag()?;
+	bridge::serve(listener, port, &stop)
 }
 
-fn cmd_read(port: &str, output: &Path) -> Result<()> {
-	let mut dev = Device::open(port)?;
+fn cmd_read(
+	target: &Target,
+	output: &Path,
+	sprite_sheet: Option<SpriteLayout>,
+	format: OutputFormat,
+) -> Result<()> {
+	let mut dev = target.open()?;
 	let flash_data = dev.read_flash()?;
 
 	std::fs::create_dir_all(output)?;
 
+	let mut manifest = Manifest::new();
 	let mut i = 0;
 	loop {
 		let offset = i * FRAME_HEADER_SIZE;
@@ -237,12 +1750,23 @@ fn cmd_read(port: &str, output: &Path) -> Result<()> {
 		}
 
 		let pixel_data = &flash_data[start..end];
+		let mut files = Vec::new();
 
 		if header.frame_count == 1 {
-			let img = rgb565_to_image(pixel_data, header.width, header.height);
-			let out_path = output.join(format!("frame_{i}.png"));
+			let img = image::rgb565_to_image(pixel_data, header.width, header.height);
+			let name = format!("frame_{i}.png");
+			let out_path = output.join(&name);
 			img.save(&out_path)?;
-			println!("Saved {}", out_path.display());
+			if format == OutputFormat::Human {
+				println!("Saved {}", out_path.display());
+			}
+			files.push(name);
+		} else if let Some(layout) = sprite_sheet {
+			let name = save_sprite_sheet(output, i, &header, pixel_data, layout)?;
+			if format == OutputFormat::Human {
+				println!("Saved {}", output.join(&name).display());
+			}
+			files.push(name);
 		} else {
 			let frame_size = (header.width as usize) * (header.height as usize) * 2;
 			for f in 0..header.frame_count as usize {
@@ -251,103 +1775,537 @@ fn cmd_read(port: &str, output: &Path) -> Result<()> {
 				if fend > pixel_data.len() {
 					break;
 				}
-				let img = rgb565_to_image(&pixel_data[fstart..fend], header.width, header.height);
-				let out_path = output.join(format!("frame_{i}_{f}.png"));
+				let img =
+					image::rgb565_to_image(&pixel_data[fstart..fend], header.width, header.height);
+				let name = format!("frame_{i}_{f}.png");
+				let out_path = output.join(&name);
 				img.save(&out_path)?;
-				println!("Saved {}", out_path.display());
+				if format == OutputFormat::Human {
+					println!("Saved {}", out_path.display());
+				}
+				files.push(name);
 			}
 		}
 
+		manifest.albums.push(AlbumEntry {
+			index: i,
+			width: header.width,
+			height: header.height,
+			frame_count: header.frame_count,
+			delay_ms: header.delay_ms,
+			data_crc32: header.data_crc32,
+			files,
+			sprite_sheet: sprite_sheet.filter(|_| header.frame_count > 1),
+		});
+
 		i += 1;
 	}
 
-	if i == 0 {
-		println!("No images found on device.");
+	let manifest_path = if manifest.albums.is_empty() {
+		None
+	} else {
+		manifest.save(output)?;
+		Some(output.join(export::MANIFEST_FILE))
+	};
+	output::emit(
+		&ReadOutput::new(output, manifest_path, manifest.albums),
+		format,
+	)
+}
+
+// Assumes flash transfers go to whichever page the config selects and that each page
+// keeps its own images. Neither is documented or confirmed on hardware, so copy and
+// clear only run with --yes. Each step switches to its page first. The page that was
+// showing is selected again at the end, even after a failure, so content can be
+// staged on an inactive page and shown with `config set page` once it is complete.
+fn cmd_page(target: &Target, action: PageAction, yes: bool, format: OutputFormat) -> Result<()> {
+	let mut dev = target.open()?;
+	let mut config = dev.read_config()?;
+	let active = config.memory_page;
+
+	let result = match action {
+		PageAction::List { pages } => page_list(&mut dev, &mut config, active, pages, format),
+		PageAction::Copy { src, dst } => {
+			if src == dst {
+				bail!("source and destination page are the same");
+			}
+			require_page_yes(yes, &format!("replace the images on memory page {dst}"))?;
+			page_copy(target, &mut dev, &mut config, src, dst)
+		}
+		PageAction::Clear { page } => {
+			require_page_yes(yes, &format!("erase the images on memory page {page}"))?;
+			page_clear(target, &mut dev, &mut config, page)
+		}
+	};
+
+	if config.memory_page != active {
+		config.memory_page = active;
+		dev.write_config(&config)?;
+	}
+	result
+}
+
+// No prompt to answer: until per-page flash is confirmed, --yes is the acknowledgement.
+fn require_page_yes(yes: bool, what: &str) -> Result<()> {
+	if !yes {
+		bail!(
+			"This would {what}. It relies on each memory page holding its own images and on \
+			 uploads going to the selected page, which is unverified; if the hub has one \
+			 image set it overwrites what is showing. Pass --yes to run it anyway."
+		);
 	}
 	Ok(())
 }
 
-fn cmd_reset(port: &str) -> Result<()> {
-	let mut dev = Device::open(port)?;
-	dev.factory_reset()?;
-	println!("Factory reset sent.");
+fn select_page(dev: &mut Device, config: &mut DeviceConfig, page: u8) -> Result<()> {
+	if config.memory_page != page {
+		config.memory_page = page;
+		dev.write_config(config)?;
+	}
+	Ok(())
+}
+
+fn page_list(
+	dev: &mut Device,
+	config: &mut DeviceConfig,
+	active: u8,
+	pages: u8,
+	format: OutputFormat,
+) -> Result<()> {
+	// One 8 KB header read per page isn't worth a progress bar.
+	dev.progress = ProgressMode::Hidden;
+	let mut list = PageListOutput {
+		active,
+		pages: Vec::new(),
+	};
+	for page in 0..pages {
+		select_page(dev, config, page)?;
+		let header = dev.read_flash_range(0, FLASH_HEADER_AREA)?;
+		let headers = flash::read_headers(&header)
+			.with_context(|| format!("page {page} holds a damaged frame header"))?;
+		list.pages.push(PageEntry {
+			page,
+			albums: headers.len(),
+			frames: headers.iter().map(|h| h.frame_count as usize).sum(),
+			bytes: headers.iter().map(|h| h.data_length as usize).sum(),
+		});
+	}
+	output::emit(&list, format)
+}
+
+fn page_copy(
+	target: &Target,
+	dev: &mut Device,
+	config: &mut DeviceConfig,
+	src: u8,
+	dst: u8,
+) -> Result<()> {
+	select_page(dev, config, src)?;
+	let header = dev.read_flash_range(0, FLASH_HEADER_AREA)?;
+	let albums = flash::header_slots_used(&header);
+	let len = flash::used_length(&header)
+		.with_context(|| format!("page {src} holds a damaged frame header"))?;
+	if len > dev.info.flash_size as usize {
+		bail!(
+			"page {src} claims {len} bytes, more than the {} byte flash",
+			dev.info.flash_size
+		);
+	}
+	eprintln!(
+		"Reading page {src} ({albums} album(s), {} KB)...",
+		len / 1024
+	);
+	let data = dev.read_flash_range(0, len)?;
+
+	select_page(dev, config, dst)?;
+	eprintln!("Writing page {dst}...");
+	dev.upload_flash(&data)?;
+	if dev.read_flash_range(0, FLASH_HEADER_AREA)? != data[..FLASH_HEADER_AREA] {
+		bail!("page {dst} doesn't match page {src} after the copy");
+	}
+	history::record(
+		target
+			.entry(dev, &format!("page copy {src} {dst}"))
+			.flash(&data),
+	);
+	println!("Copied page {src} to page {dst}.");
+	Ok(())
+}
+
+fn page_clear(
+	target: &Target,
+	dev: &mut Device,
+	config: &mut DeviceConfig,
+	page: u8,
+) -> Result<()> {
+	select_page(dev, config, page)?;
+	// An empty header area is enough: the firmware stops at the first missing header.
+	let empty = flash::build_flash_buffer(&[], dev.info.flash_size)?;
+	dev.upload_flash(&empty)?;
+	history::record(
+		target
+			.entry(dev, &format!("page clear {page}"))
+			.flash(&empty),
+	);
+	println!("Cleared page {page}.");
 	Ok(())
 }
 
-fn cmd_backup(port: &str, file: &Path) -> Result<()> {
-	let mut dev = Device::open(port)?;
+#[derive(Serialize, Deserialize)]
+struct NightState {
+	brightness: u8,
+	page: u8,
+}
 
-	eprintln!("Reading config...");
-	let config = dev.read_config()?;
-	let config_bytes = config.to_bytes();
+fn cmd_night(target: &Target, action: NightAction) -> Result<()> {
+	let state_path = paths::state_dir()?.join("night.json");
 
-	eprintln!("Reading flash...");
-	let flash_data = dev.read_flash()?;
+	match action {
+		NightAction::On { brightness, page } => {
+			if brightness > MAX_BRIGHTNESS {
+				bail!("brightness must be 0-{MAX_BRIGHTNESS}");
+			}
+			let mut dev = target.open()?;
+			let mut config = dev.read_config()?;
 
-	let mut backup = Vec::new();
-	backup.extend_from_slice(b"HMHUB\x01");
-	backup.extend_from_slice(&(config_bytes.len() as u32).to_le_bytes());
-	backup.extend_from_slice(&config_bytes);
-	backup.extend_from_slice(&(flash_data.len() as u32).to_le_bytes());
-	backup.extend_from_slice(&flash_data);
-	let checksum = crc32fast::hash(&backup);
-	backup.extend_from_slice(&checksum.to_le_bytes());
+			if !state_path.exists() {
+				let state = NightState {
+					brightness: config.screen_brightness,
+					page: config.memory_page,
+				};
+				std::fs::create_dir_all(state_path.parent().unwrap())?;
+				std::fs::write(&state_path, serde_json::to_string(&state)?)?;
+			}
 
-	std::fs::write(file, &backup)?;
+			config.screen_brightness = brightness;
+			if let Some(page) = page {
+				config.memory_page = page;
+			}
+			dev.write_config(&config)?;
+			history::record(
+				target
+					.entry(&dev, "night")
+					.changes(vec![format!("brightness = {brightness}")]),
+			);
+			println!("Night mode on (brightness {brightness})");
+		}
+		NightAction::Off => {
+			if !state_path.exists() {
+				println!("Night mode is not on.");
+				return Ok(());
+			}
+			let state: NightState = serde_json::from_str(&std::fs::read_to_string(&state_path)?)?;
+
+			let mut dev = target.open()?;
+			let mut config = dev.read_config()?;
+			config.screen_brightness = state.brightness;
+			config.memory_page = state.page;
+			dev.write_config(&config)?;
+			std::fs::remove_file(&state_path)?;
+			history::record(target.entry(&dev, "night").changes(vec![
+				format!("brightness = {}", state.brightness),
+				format!("page = {}", state.page),
+			]));
+			println!(
+				"Night mode off (brightness {}, page {})",
+				state.brightness, state.page
+			);
+		}
+	}
+	Ok(())
+}
+
+fn save_sprite_sheet(
+	output: &Path,
+	index: usize,
+	header: &FrameHeader,
+	pixel_data: &[u8],
+	layout: SpriteLayout,
+) -> Result<String> {
+	let w = header.width as u32;
+	let h = header.height as u32;
+	let frame_size = (header.width as usize) * (header.height as usize) * 2;
+	if frame_size == 0 {
+		bail!(
+			"album {index} has a {}x{} frame size",
+			header.width,
+			header.height
+		);
+	}
+	let count = (pixel_data.len() / frame_size).min(header.frame_count as usize) as u32;
+	if count == 0 {
+		bail!("album {index} holds no complete frame");
+	}
+	let (sheet_w, sheet_h) = match layout {
+		SpriteLayout::Horizontal => (w.checked_mul(count), Some(h)),
+		SpriteLayout::Vertical => (Some(w), h.checked_mul(count)),
+	};
+	let (Some(sheet_w), Some(sheet_h)) = (sheet_w, sheet_h) else {
+		bail!("album {index}'s {count} frames don't fit in one sprite sheet");
+	};
+
+	let mut sheet = ::image::RgbaImage::new(sheet_w, sheet_h);
+	let mut frames = Vec::with_capacity(count as usize);
+	for f in 0..count {
+		let start = f as usize * frame_size;
+		let img = image::rgb565_to_image(
+			&pixel_data[start..start + frame_size],
+			header.width,
+			header.height,
+		);
+		let (x, y) = layout.frame_origin(f, w, h);
+		::image::imageops::replace(&mut sheet, &img, x as i64, y as i64);
+		frames.push(SpriteFrame {
+			x,
+			y,
+			w: header.width,
+			h: header.height,
+			duration_ms: header.delay_ms,
+		});
+	}
+
+	let name = format!("frame_{index}_sheet.png");
+	let out_path = output.join(&name);
+	sheet.save(&out_path)?;
+
+	let meta = SpriteSheetMeta {
+		image: name.clone(),
+		layout,
+		frame_width: header.width,
+		frame_height: header.height,
+		frames,
+	};
+	let meta_path = output.join(format!("frame_{index}_sheet.json"));
+	std::fs::write(&meta_path, serde_json::to_string_pretty(&meta)?)?;
+	println!("Saved {}", meta_path.display());
+
+	Ok(name)
+}
+
+fn cmd_reset(target: &Target, yes: bool) -> Result<()> {
+	let mut dev = target.open()?;
+	confirm(
+		yes,
+		"This will factory reset the device, erasing all stored images and settings.",
+	)?;
+	dev.factory_reset()?;
+	history::record(target.entry(&dev, "reset"));
+	println!("Factory reset sent.");
+	Ok(())
+}
+
+fn cmd_export(target: &Target, file: &Path) -> Result<()> {
+	let mut dev = target.open()?;
+	let config = dev.read_config()?;
+	let albums = flash::parse_albums(&dev.read_flash()?)?;
+	if albums.is_empty() {
+		bail!("no images found on device");
+	}
+
+	let frames: usize = albums.iter().map(|a| a.frames.len()).sum();
+	let count = albums.len();
+	Package::new(&dev.info, &config, albums).write(file)?;
 	println!(
-		"Backup saved to {} ({:.1} MB)",
-		file.display(),
-		backup.len() as f64 / 1_048_576.0
+		"Exported {count} album(s), {frames} frame(s) to {}",
+		file.display()
 	);
 	Ok(())
 }
 
-fn cmd_restore(port: &str, file: &Path) -> Result<()> {
-	let data = std::fs::read(file)?;
+fn cmd_import(target: &Target, file: &Path, no_config: bool, blank: bool, yes: bool) -> Result<()> {
+	let package = Package::read(file)?;
+	let mut albums = package.albums;
 
-	if data.len() < 14 || &data[..5] != b"HMHUB" {
-		bail!("not a valid hm-hub backup file");
+	let mut dev = target.open()?;
+	let max = dev.info.max_frames();
+	let mut total_frames = 0;
+	let fitting = albums
+		.iter()
+		.take_while(|a| {
+			total_frames += a.frames.len();
+			total_frames <= max
+		})
+		.count();
+	if fitting < albums.len() {
+		eprintln!(
+			"Package has {} album(s) but only the first {fitting} fit in this device's {max} frames",
+			albums.len()
+		);
+		albums.truncate(fitting);
 	}
-	if data[5] != 1 {
-		bail!("unsupported backup version: {}", data[5]);
+	if albums.is_empty() {
+		bail!(
+			"the first album in {} doesn't fit on this device",
+			file.display()
+		);
 	}
+	let total_frames: usize = albums.iter().map(|a| a.frames.len()).sum();
+	confirm_overwrite(&mut dev, albums.len(), total_frames, yes)?;
+
+	let flash_data = flash::build_flash_buffer(&albums, dev.info.flash_size)?;
+	upload_blanked(&mut dev, &flash_data, blank)?;
 
-	let stored_crc = u32::from_le_bytes([
-		data[data.len() - 4],
-		data[data.len() - 3],
-		data[data.len() - 2],
-		data[data.len() - 1],
-	]);
-	let computed_crc = crc32fast::hash(&data[..data.len() - 4]);
-	if stored_crc != computed_crc {
-		bail!("backup file is corrupted (CRC mismatch)");
+	let mut changes = Vec::new();
+	if !no_config && !package.config.is_empty() {
+		let current = dev.read_config()?;
+		let mut config = current.clone();
+		for (field, value) in &package.config {
+			config.set_field(field, value)?;
+		}
+		if config.to_bytes() != current.to_bytes() {
+			dev.write_config(&config)?;
+			changes = package
+				.config
+				.iter()
+				.map(|(field, value)| format!("{field} = {value}"))
+				.collect();
+		}
 	}
 
-	let mut pos = 6;
+	history::record(
+		target
+			.entry(&dev, "import")
+			.files(&[file.to_path_buf()])
+			.flash(&flash_data)
+			.changes(changes),
+	);
+	println!("Imported {} album(s) from {}", albums.len(), file.display());
+	Ok(())
+}
+
+fn cmd_backup(target: &Target, file: &Path, format: OutputFormat) -> Result<()> {
+	let mut dev = target.open()?;
+
+	eprintln!("Reading config...");
+	let config = dev.read_config()?;
+
+	eprintln!("Reading flash...");
+	let flash = dev.read_flash()?;
 
-	let config_len =
-		u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]) as usize;
-	pos += 4;
-	let config = types::DeviceConfig::from_bytes(&data[pos..pos + config_len])?;
-	pos += config_len;
+	let backup = Backup { config, flash }.to_bytes();
+	std::fs::write(file, &backup)?;
+	output::emit(&BackupOutput::new(file, backup.len()), format)
+}
 
-	let flash_len =
-		u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]) as usize;
-	pos += 4;
-	let flash_data = &data[pos..pos + flash_len];
+fn cmd_restore(target: &Target, file: &Path, yes: bool) -> Result<()> {
+	let data = std::fs::read(file)?;
+	let backup = Backup::from_bytes(&data)?;
 
-	let mut dev = Device::open(port)?;
+	let mut dev = target.open()?;
+	confirm(
+		yes,
+		&format!(
+			"This will overwrite the device config and {:.1} MB of flash with {}.",
+			backup.flash.len() as f64 / 1_048_576.0,
+			file.display()
+		),
+	)?;
 
 	eprintln!("Restoring config...");
-	dev.write_config(&config)?;
+	dev.write_config(&backup.config)?;
 
 	eprintln!("Restoring flash...");
-	dev.upload_flash(flash_data)?;
+	dev.upload_flash(&backup.flash)?;
+	history::record(
+		target
+			.entry(&dev, "restore")
+			.files(&[file.to_path_buf()])
+			.flash(&backup.flash),
+	);
 
 	println!("Restore complete.");
 	Ok(())
 }
 
+fn cmd_selftest(target: &Target, backup_file: Option<&Path>, yes: bool) -> Result<()> {
+	let mut dev = target.open()?;
+	confirm(
+		yes,
+		"This will temporarily replace the flash content with a test album and then \
+		 restore it from a backup.",
+	)?;
+
+	eprintln!("Backing up current flash...");
+	let config = dev.read_config()?;
+	let previous = dev.read_flash()?;
+	let backup = Backup {
+		config,
+		flash: previous,
+	};
+	let backup_path = match backup_file {
+		Some(path) => {
+			std::fs::write(path, backup.to_bytes())?;
+			eprintln!("Backup saved to {}", path.display());
+			path.to_path_buf()
+		}
+		None => save_safety_backup(&dev, "selftest", &backup)?,
+	};
+
+	let album = crate::image::test_pattern_album();
+	let expected = flash::build_flash_buffer(&[album], dev.info.flash_size)?;
+
+	eprintln!("Uploading test album...");
+	let outcome = dev.upload_flash(&expected).and_then(|()| {
+		eprintln!("Reading back...");
+		dev.read_flash()
+	});
+
+	eprintln!("Restoring previous flash content...");
+	dev.upload_flash(&backup.flash)?;
+
+	let readback = outcome?;
+	let mismatches = expected
+		.iter()
+		.zip(readback.iter())
+		.filter(|(a, b)| a != b)
+		.count();
+	let first = expected
+		.iter()
+		.zip(readback.iter())
+		.position(|(a, b)| a != b);
+
+	if readback.len() < expected.len() {
+		bail!(
+			"selftest FAILED: read back {} of {} bytes",
+			readback.len(),
+			expected.len()
+		);
+	}
+	if let Some(offset) = first {
+		bail!(
+			"selftest FAILED: {mismatches} of {} bytes differ (first at offset {offset:#x})",
+			expected.len()
+		);
+	}
+
+	println!("selftest PASSED: {} bytes verified", expected.len());
+	std::fs::remove_file(&backup_path)?;
+	Ok(())
+}
+
+// The backup that holds the only copy of the images while flash is rewritten. Each
+// run gets a new file in the state directory, named after the device and the time,
+// so parallel runs don't share one and nothing already at the path is followed.
+fn save_safety_backup(dev: &Device, what: &str, backup: &Backup) -> Result<PathBuf> {
+	let dir = paths::state_dir()?.join("backups");
+	std::fs::create_dir_all(&dir)?;
+	let path = dir.join(format!(
+		"{what}-{:08x}-{}-{}.bak",
+		dev.info.hw_id,
+		Local::now().format("%Y%m%d-%H%M%S"),
+		std::process::id()
+	));
+	let mut file = std::fs::OpenOptions::new()
+		.write(true)
+		.create_new(true)
+		.open(&path)
+		.with_context(|| format!("creating backup {}", path.display()))?;
+	file.write_all(&backup.to_bytes())?;
+	file.sync_all()?;
+	eprintln!("Backup saved to {}", path.display());
+	Ok(path)
+}
+
 fn collect_images(dir: &Path) -> Result<Vec<std::path::PathBuf>> {
 	let mut paths: Vec<_> = std::fs::read_dir(dir)?
 		.filter_map(|e| e.ok())
@@ -358,14 +2316,14 @@ fn collect_images(dir: &Path) -> Result<Vec<std::path::PathBuf>> {
 				.map(|e| {
 					matches!(
 						e.to_lowercase().as_str(),
-						"png" | "jpg" | "jpeg" | "bmp" | "webp" | "gif"
+						"png" | "jpg" | "jpeg" | "bmp" | "webp" | "gif" | "rgb565"
 					)
 				})
 				.unwrap_or(false)
 		})
 		.collect();
 	paths.sort();
-	Ok(paths)
+	Ok(image::select_variants(paths, DISPLAY_WIDTH, DISPLAY_HEIGHT))
 }
 
 fn dir_fingerprint(dir: &Path) -> Result<u32> {
@@ -384,10 +2342,26 @@ fn dir_fingerprint(dir: &Path) -> Result<u32> {
 	Ok(hasher.finalize())
 }
 
-fn cmd_rotate(port: &str, dir: &Path, interval: u64, crop: bool) -> Result<()> {
+fn cmd_rotate(
+	target: &Target,
+	dir: &Path,
+	interval: u64,
+	image: &ImageArgs,
+	blank: bool,
+	dedupe: bool,
+	yes: bool,
+) -> Result<()> {
+	let opts = image.load_options();
 	if !dir.is_dir() {
 		bail!("{} is not a directory", dir.display());
 	}
+	confirm(
+		yes,
+		&format!(
+			"This will replace all images on the device whenever {} changes.",
+			dir.display()
+		),
+	)?;
 
 	eprintln!(
 		"Watching {} for changes every {}s (Ctrl+C to stop)...",
@@ -396,57 +2370,235 @@ fn cmd_rotate(port: &str, dir: &Path, interval: u64, crop: bool) -> Result<()> {
 	);
 
 	let mut last_fingerprint: u32 = 0;
+	let mut watchdog = target.watchdog()?;
 
 	loop {
 		let fingerprint = dir_fingerprint(dir)?;
 		if fingerprint != last_fingerprint {
-			let paths = collect_images(dir)?;
+			let mut paths = collect_images(dir)?;
+			if dedupe {
+				paths = dedupe_images(paths, &opts)?;
+			}
 			if paths.is_empty() {
 				eprintln!("No images found, waiting...");
 			} else {
 				eprintln!("Change detected, uploading {} image(s)...", paths.len());
 				let mut albums = Vec::new();
 				for path in &paths {
-					let album = crate::image::load_image(path, crop)?;
+					let album = crate::image::load_image(path, &opts)?;
 					albums.push(album);
 				}
 
-				let mut dev = Device::open(port)?;
+				let mut dev = target.open()?;
+				interpolate_within_budget(&mut albums, image.interpolate, dev.info.max_frames());
 				let flash_data = flash::build_flash_buffer(&albums, dev.info.flash_size)?;
-				dev.upload_flash(&flash_data)?;
+				upload_blanked(&mut dev, &flash_data, blank)?;
+				history::record(
+					target
+						.entry(&dev, "rotate")
+						.files(&paths)
+						.flash(&flash_data),
+				);
 				eprintln!("Upload complete, watching for changes...");
 			}
 			last_fingerprint = fingerprint;
 		}
-		std::thread::sleep(std::time::Duration::from_secs(interval));
+		watchdog.idle(Duration::from_secs(interval), || target.ping())?;
+	}
+}
+
+struct DashboardBar {
+	edge: Edge,
+	height: u32,
+	full_scale: u16,
+	min_change: u16,
+	min_interval: Duration,
+}
+
+fn cmd_dashboard(
+	target: &Target,
+	file: &Path,
+	image: &ImageArgs,
+	bar: DashboardBar,
+	interval: u64,
+	yes: bool,
+) -> Result<()> {
+	let base = crate::image::load_image(file, &image.load_options())?
+		.frames
+		.swap_remove(0);
+	confirm(
+		yes,
+		"This will replace all images on the device and re-upload whenever power readings change.",
+	)?;
+
+	eprintln!(
+		"Showing {} with a power bar, re-uploading at most every {:?} (Ctrl+C to stop)...",
+		file.display(),
+		bar.min_interval
+	);
+
+	let mut drawn: Option<(PowerStats, Instant)> = None;
+	let mut watchdog = target.watchdog()?;
+
+	loop {
+		let mut dev = target.open()?;
+		let stats = dev.read_power()?;
+		let due = drawn.as_ref().is_none_or(|(previous, at)| {
+			at.elapsed() >= bar.min_interval
+				&& power::changed_meaningfully(previous, &stats, bar.min_change)
+		});
+		if due {
+			let mut frame = base.clone();
+			power::draw_power_bar(&mut frame, &stats, bar.edge, bar.height, bar.full_scale);
+			let album = Album {
+				frames: vec![frame],
+				delay_ms: 0,
+			};
+			let flash_data = flash::build_flash_buffer(&[album], dev.info.flash_size)?;
+			dev.upload_flash(&flash_data)?;
+			if drawn.is_none() {
+				history::record(
+					target
+						.entry(&dev, "dashboard")
+						.files(&[file.to_path_buf()])
+						.flash(&flash_data),
+				);
+			}
+			let [p1, p2, p3] = power::port_currents(&stats);
+			eprintln!(
+				"[{}] Bar updated: {:.2}V, ports {p1}/{p2}/{p3}mA",
+				Local::now().format("%H:%M"),
+				stats.bus_voltage as f64 / 1000.0
+			);
+			drawn = Some((stats, Instant::now()));
+		}
+		drop(dev);
+		watchdog.idle(Duration::from_secs(interval), || target.ping())?;
+	}
+}
+
+fn in_off_window(now: NaiveTime, off: NaiveTime, on: NaiveTime) -> bool {
+	if off <= on {
+		now >= off && now < on
+	} else {
+		now >= off || now < on
 	}
 }
 
-fn rgb565_to_image(data: &[u8], width: u16, height: u16) -> ::image::RgbaImage {
-	let w = width as u32;
-	let h = height as u32;
-	let mut img = ::image::RgbaImage::new(w, h);
+fn cmd_schedule(
+	target: &Target,
+	screen_off: NaiveTime,
+	screen_on: NaiveTime,
+	brightness: Option<u8>,
+	interval: u64,
+) -> Result<()> {
+	if screen_off == screen_on {
+		bail!("screen-off and screen-on times must differ");
+	}
+	if brightness.is_some_and(|b| b > MAX_BRIGHTNESS) {
+		bail!("brightness must be 0-{MAX_BRIGHTNESS}");
+	}
+
+	eprintln!(
+		"Screen off at {}, on at {} (Ctrl+C to stop)...",
+		screen_off.format("%H:%M"),
+		screen_on.format("%H:%M")
+	);
+
+	let mut saved_brightness: Option<u8> = None;
+	let mut watchdog = target.watchdog()?;
+
+	loop {
+		let now = Local::now().time();
+		let want_off = in_off_window(now, screen_off, screen_on);
 
-	for y in 0..h {
-		for x in 0..w {
-			let idx = ((y * w + x) * 2) as usize;
-			if idx + 1 >= data.len() {
-				break;
+		if want_off && saved_brightness.is_none() {
+			let mut dev = target.open()?;
+			let mut config = dev.read_config()?;
+			saved_brightness = Some(config.screen_brightness);
+			if config.screen_brightness != 0 {
+				config.screen_brightness = 0;
+				dev.write_config(&config)?;
 			}
-			let hi = data[idx] as u16;
-			let lo = data[idx + 1] as u16;
-			let pixel = (hi << 8) | lo;
+			eprintln!("[{}] Screen off", now.format("%H:%M"));
+		} else if !want_off {
+			if let Some(previous) = saved_brightness.take() {
+				let level = brightness.unwrap_or(previous);
+				let mut dev = target.open()?;
+				let mut config = dev.read_config()?;
+				config.screen_brightness = level;
+				dev.write_config(&config)?;
+				eprintln!("[{}] Screen on (brightness {level})", now.format("%H:%M"));
+			}
+		}
 
-			let r = ((pixel >> 11) & 0x1F) as u8;
-			let g = ((pixel >> 5) & 0x3F) as u8;
-			let b = (pixel & 0x1F) as u8;
+		// Checks land on wall-clock boundaries, so a switch due at HH:MM happens on the minute.
+		watchdog.idle_aligned(Duration::from_secs(interval), || target.ping())?;
+	}
+}
 
-			let r8 = (r << 3) | (r >> 2);
-			let g8 = (g << 2) | (g >> 4);
-			let b8 = (b << 3) | (b >> 2);
+fn cmd_ambient(
+	target: &Target,
+	source: LightSource,
+	mut mapper: BrightnessMapper,
+	interval: u64,
+) -> Result<()> {
+	eprintln!("Adjusting brightness every {interval}s (Ctrl+C to stop)...");
+	let mut watchdog = target.watchdog()?;
 
-			img.put_pixel(x, y, ::image::Rgba([r8, g8, b8, 255]));
+	loop {
+		match source.read() {
+			Ok(value) => {
+				if let Some(level) = mapper.update(value) {
+					let mut dev = target.open()?;
+					let mut config = dev.read_config()?;
+					if config.screen_brightness != level {
+						config.screen_brightness = level;
+						dev.write_config(&config)?;
+						eprintln!("Light level {value} -> brightness {level}");
+					}
+				}
+			}
+			Err(e) => eprintln!("Failed to read light level: {e:#}"),
 		}
+		watchdog.idle(Duration::from_secs(interval), || target.ping())?;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn t(h: u32, m: u32) -> NaiveTime {
+		NaiveTime::from_hms_opt(h, m, 0).unwrap()
+	}
+
+	#[test]
+	fn off_window_within_one_day() {
+		let (off, on) = (t(9, 0), t(17, 0));
+		assert!(!in_off_window(t(8, 59), off, on));
+		assert!(in_off_window(t(9, 0), off, on));
+		assert!(in_off_window(t(16, 59), off, on));
+		assert!(!in_off_window(t(17, 0), off, on));
+	}
+
+	#[test]
+	fn off_window_wrapping_midnight() {
+		let (off, on) = (t(22, 0), t(7, 0));
+		assert!(!in_off_window(t(21, 59), off, on));
+		assert!(in_off_window(t(22, 0), off, on));
+		assert!(in_off_window(t(23, 59), off, on));
+		assert!(in_off_window(t(0, 0), off, on));
+		assert!(in_off_window(t(6, 59), off, on));
+		assert!(!in_off_window(t(7, 0), off, on));
+		assert!(!in_off_window(t(12, 0), off, on));
+	}
+
+	#[test]
+	fn off_window_ending_at_midnight() {
+		let (off, on) = (t(20, 0), t(0, 0));
+		assert!(in_off_window(t(23, 59), off, on));
+		assert!(!in_off_window(t(0, 0), off, on));
+		assert!(!in_off_window(t(19, 59), off, on));
 	}
-	img
 }
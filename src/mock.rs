@@ -0,0 +1,365 @@
+// In-memory emulation of the hub firmware, so the upload, read, backup and config
+// flows can run without hardware (`--port mock:`). It answers the same packets the
+// real device does: handshake, chunked config reads and writes, erase followed by
+// device-driven data requests, streamed readback, factory reset and periodic power
+// samples. With a state file (`--port mock:./state.bin`) flash and config survive
+// between runs; the file uses the backup format, so `restore` accepts it too.
+//
+// Two behaviours here are assumptions nobody has checked against real firmware:
+// erasing only the uploaded length rounded up to FLASH_ERASE_BLOCK, and honouring
+// the offset and length of a ranged readback. The header-only commands (reorder,
+// set-delay, in-place slot uploads) and the ranged reads lean on both, so tests that
+// pass here only show the host agrees with itself. Set `full_erase` to emulate a
+// firmware that wipes the whole chip on every upload instead.
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::backup::Backup;
+use crate::consts::*;
+use crate::transport::Transport;
+use crate::types::{DeviceConfig, Packet};
+
+pub const MOCK_HW_ID: u32 = 0x4D4F_434B;
+pub const MOCK_FW_VERSION: u32 = 0x0001_0000;
+pub const MOCK_FLASH_SIZE: u32 = 16 * 1024 * 1024;
+// The emulator's config at power-on and after a factory reset. Made up for the mock,
+// not captured from a real hub.
+pub const MOCK_CONFIG: [u8; 24] = [
+	0, 0, 0, 0, 20, 1, 0, 0, 0, 0, 0, 128, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 5, 0,
+];
+
+// Largest data block that fits in a flash packet after the sub-code, offset and length.
+const FLASH_BLOCK: usize = PAYLOAD_SIZE - 7;
+// Largest config reply chunk after the sub-code and chunk header.
+const CONFIG_BLOCK: usize = PAYLOAD_SIZE - 5;
+
+struct Upload {
+	total: usize,
+	next: usize,
+}
+
+pub struct MockDevice {
+	pub flash: Vec<u8>,
+	pub config: [u8; 24],
+	pub power_interval: Duration,
+	pub full_erase: bool,
+	timeout: Duration,
+	incoming: Vec<u8>,
+	outgoing: VecDeque<u8>,
+	upload: Option<Upload>,
+	config_chunks: Vec<Option<Vec<u8>>>,
+	next_power: Instant,
+	samples: u32,
+	state_file: Option<PathBuf>,
+}
+
+impl Default for MockDevice {
+	fn default() -> Self {
+		Self::new(MOCK_FLASH_SIZE)
+	}
+}
+
+impl MockDevice {
+	pub fn new(flash_size: u32) -> Self {
+		Self {
+			flash: vec![0xFF; flash_size as usize],
+			config: MOCK_CONFIG,
+			power_interval: Duration::from_secs(1),
+			full_erase: false,
+			timeout: NORMAL_TIMEOUT,
+			incoming: Vec::new(),
+			outgoing: VecDeque::new(),
+			upload: None,
+			config_chunks: Vec::new(),
+			next_power: Instant::now(),
+			samples: 0,
+			state_file: None,
+		}
+	}
+
+	// Loads the state file if it exists; otherwise starts blank and creates it on the
+	// first change.
+	pub fn open(path: &Path) -> io::Result<Self> {
+		let mut dev = match std::fs::read(path) {
+			Ok(data) => {
+				let backup = Backup::from_bytes(&data).map_err(|e| {
+					io::Error::new(
+						io::ErrorKind::InvalidData,
+						format!("{}: {e}", path.display()),
+					)
+				})?;
+				let mut dev = Self::new(backup.flash.len() as u32);
+				dev.flash = backup.flash;
+				dev.config = backup.config.to_bytes();
+				dev
+			}
+			Err(e) if e.kind() == io::ErrorKind::NotFound => Self::default(),
+			Err(e) => return Err(e),
+		};
+		dev.state_file = Some(path.to_path_buf());
+		Ok(dev)
+	}
+
+	// Written to a temporary file first so an interrupted run can't leave a torn state.
+	fn save(&mut self) {
+		let Some(path) = &self.state_file else {
+			return;
+		};
+		let backup = Backup {
+			config: self.device_config(),
+			flash: self.flash.clone(),
+		};
+		let tmp = path.with_extension("tmp");
+		let result =
+			std::fs::write(&tmp, backup.to_bytes()).and_then(|_| std::fs::rename(&tmp, path));
+		if let Err(e) = result {
+			let msg = format!("failed to save {}: {e}", path.display());
+			self.log(&msg);
+		}
+	}
+
+	pub fn device_config(&self) -> DeviceConfig {
+		DeviceConfig::from_bytes(&self.config).expect("config is always 24 bytes")
+	}
+
+	fn reply(&mut self, cmd_id: u8, payload: &[u8]) {
+		let pkt = Packet::new(cmd_id, payload).expect("mock replies fit in a packet");
+		self.outgoing.extend(pkt.buf);
+	}
+
+	fn log(&mut self, msg: &str) {
+		let bytes = &msg.as_bytes()[..msg.len().min(PAYLOAD_SIZE - 1)];
+		let mut payload = vec![bytes.len() as u8];
+		payload.extend_from_slice(bytes);
+		self.reply(CMD_LOG, &payload);
+	}
+
+	fn handle(&mut self, pkt: &Packet) {
+		let p = pkt.payload();
+		match pkt.cmd_id() {
+			CMD_HANDSHAKE => {
+				let mut payload = [0u8; 12];
+				payload[0..4].copy_from_slice(&MOCK_HW_ID.to_le_bytes());
+				payload[4..8].copy_from_slice(&MOCK_FW_VERSION.to_le_bytes());
+				payload[8..12].copy_from_slice(&(self.flash.len() as u32).to_le_bytes());
+				self.reply(CMD_HANDSHAKE, &payload);
+			}
+			CMD_CONFIG => match p[0] {
+				1 => self.send_config(),
+				2 => self.receive_config_chunk(p),
+				other => self.log(&format!("unknown config sub-command {other}")),
+			},
+			CMD_FLASH => match p[0] {
+				1 => {
+					let total = u32::from_le_bytes([p[1], p[2], p[3], p[4]]) as usize;
+					self.start_upload(total);
+				}
+				2 => {
+					let offset = u32::from_le_bytes([p[1], p[2], p[3], p[4]]) as usize;
+					let length = u16::from_le_bytes([p[5], p[6]]) as usize;
+					self.receive_flash_data(offset, &p[7..7 + length.min(FLASH_BLOCK)]);
+				}
+				3 => {
+					let offset = u32::from_le_bytes([p[1], p[2], p[3], p[4]]) as usize;
+					let length = u32::from_le_bytes([p[5], p[6], p[7], p[8]]) as usize;
+					self.send_readback(offset, length);
+				}
+				other => self.log(&format!("unknown flash sub-command {other}")),
+			},
+			CMD_FACTORY_RESET => {
+				self.flash.fill(0xFF);
+				self.config = MOCK_CONFIG;
+				self.save();
+			}
+			other => self.log(&format!("unknown command {other}")),
+		}
+	}
+
+	// The firmware acknowledges with a status packet, then sends the config in chunks
+	// laid out as [index, total, length(2), data], with a CRC32 after the last byte.
+	fn send_config(&mut self) {
+		self.reply(CMD_CONFIG, &[1, 0]);
+		let mut data = self.config.to_vec();
+		data.extend_from_slice(&crc32fast::hash(&self.config).to_le_bytes());
+		let chunks: Vec<Vec<u8>> = data.chunks(CONFIG_BLOCK).map(<[u8]>::to_vec).collect();
+		let total = chunks.len() as u8;
+		for (i, chunk) in chunks.iter().enumerate() {
+			let mut payload = vec![2, i as u8, total];
+			payload.extend_from_slice(&(chunk.len() as u16).to_le_bytes());
+			payload.extend_from_slice(chunk);
+			self.reply(CMD_CONFIG, &payload);
+		}
+	}
+
+	// Config writes use the host's chunk layout: [total(2), index(2), length(2), data].
+	fn receive_config_chunk(&mut self, p: &[u8]) {
+		let total = u16::from_le_bytes([p[1], p[2]]) as usize;
+		let index = u16::from_le_bytes([p[3], p[4]]) as usize;
+		let length = (u16::from_le_bytes([p[5], p[6]]) as usize).min(CHUNK_DATA_SIZE);
+		if total == 0 || index >= total {
+			self.log(&format!("bad config chunk {index}/{total}"));
+			return;
+		}
+		if self.config_chunks.len() != total {
+			self.config_chunks = vec![None; total];
+		}
+		self.config_chunks[index] = Some(p[7..7 + length].to_vec());
+		if self.config_chunks.iter().any(Option::is_none) {
+			return;
+		}
+
+		let data: Vec<u8> = self.config_chunks.drain(..).flatten().flatten().collect();
+		if data.len() < 4 + self.config.len() {
+			self.log(&format!("config write too short: {} bytes", data.len()));
+			return;
+		}
+		let (body, crc) = data.split_at(data.len() - 4);
+		if crc32fast::hash(body).to_le_bytes() != crc {
+			self.log("config write failed CRC check");
+			return;
+		}
+		self.config.copy_from_slice(&body[..MOCK_CONFIG.len()]);
+		self.save();
+	}
+
+	fn start_upload(&mut self, total: usize) {
+		if total == 0 || total > self.flash.len() {
+			self.reply(CMD_FLASH, &[1, 3]);
+			return;
+		}
+		self.reply(CMD_FLASH, &[1, 2]);
+		// Unverified: see the note at the top of the file.
+		let erase_end = if self.full_erase {
+			self.flash.len()
+		} else {
+			(total.div_ceil(FLASH_ERASE_BLOCK) * FLASH_ERASE_BLOCK).min(self.flash.len())
+		};
+		self.flash[..erase_end].fill(0xFF);
+		self.upload = Some(Upload { total, next: 0 });
+		self.request_flash_data();
+	}
+
+	fn request_flash_data(&mut self) {
+		let Some(upload) = &self.upload else {
+			return;
+		};
+		let offset = upload.next;
+		let length = (upload.total - offset).min(FLASH_BLOCK);
+		let mut payload = vec![2];
+		payload.extend_from_slice(&(offset as u32).to_le_bytes());
+		payload.extend_from_slice(&(length as u16).to_le_bytes());
+		self.reply(CMD_FLASH, &payload);
+	}
+
+	// Once the last block lands the firmware goes quiet; the host stops on its own.
+	fn receive_flash_data(&mut self, offset: usize, data: &[u8]) {
+		let Some(upload) = &mut self.upload else {
+			self.log("flash data without an upload in progress");
+			return;
+		};
+		if offset != upload.next {
+			let expected = upload.next;
+			self.log(&format!("flash data at {offset}, expected {expected}"));
+			self.upload = None;
+			self.reply(CMD_FLASH, &[1, 4]);
+			return;
+		}
+		let end = (offset + data.len()).min(upload.total);
+		upload.next = end;
+		let done = end >= upload.total;
+		self.flash[offset..end].copy_from_slice(&data[..end - offset]);
+		if done {
+			self.upload = None;
+			self.save();
+		} else {
+			self.request_flash_data();
+		}
+	}
+
+	// A zero length reads the whole flash, as sent by `build_flash_readback`. Whether
+	// the firmware honours a nonzero range is unverified.
+	fn send_readback(&mut self, offset: usize, length: usize) {
+		let (start, end) = if length == 0 {
+			(0, self.flash.len())
+		} else {
+			(
+				offset.min(self.flash.len()),
+				(offset + length).min(self.flash.len()),
+			)
+		};
+		for block_start in (start..end).step_by(FLASH_BLOCK) {
+			let block_end = (block_start + FLASH_BLOCK).min(end);
+			let mut payload = vec![3];
+			payload.extend_from_slice(&(block_start as u32).to_le_bytes());
+			payload.extend_from_slice(&((block_end - block_start) as u16).to_le_bytes());
+			payload.extend_from_slice(&self.flash[block_start..block_end]);
+			self.reply(CMD_FLASH, &payload);
+		}
+		self.reply(CMD_FLASH, &[4]);
+	}
+
+	// Slowly wandering readings so gauges and thresholds have something to react to.
+	fn power_sample(&mut self) {
+		self.samples = self.samples.wrapping_add(1);
+		let wobble = |period: u32, amplitude: u32| {
+			let phase = self.samples % period;
+			let tri = if phase < period / 2 {
+				phase
+			} else {
+				period - phase
+			};
+			(tri * amplitude * 2 / period) as u16
+		};
+		let mut payload = [0u8; 8];
+		payload[0..2].copy_from_slice(&(5050 + wobble(20, 60)).to_le_bytes());
+		payload[2..4].copy_from_slice(&(420 + wobble(12, 80)).to_le_bytes());
+		payload[4..6].copy_from_slice(&(900 + wobble(30, 400)).to_le_bytes());
+		payload[6..8].copy_from_slice(&wobble(8, 15).to_le_bytes());
+		self.reply(CMD_POWER, &payload);
+	}
+}
+
+impl Read for MockDevice {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		if self.outgoing.is_empty() {
+			let wait = self.next_power.saturating_duration_since(Instant::now());
+			if wait > self.timeout {
+				std::thread::sleep(self.timeout);
+				return Err(io::Error::new(io::ErrorKind::TimedOut, "no data"));
+			}
+			std::thread::sleep(wait);
+			self.next_power = Instant::now() + self.power_interval;
+			self.power_sample();
+		}
+		self.outgoing.read(buf)
+	}
+}
+
+impl Write for MockDevice {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.incoming.extend_from_slice(buf);
+		while self.incoming.len() >= PACKET_SIZE {
+			let mut raw = [0u8; PACKET_SIZE];
+			raw.copy_from_slice(&self.incoming[..PACKET_SIZE]);
+			self.incoming.drain(..PACKET_SIZE);
+			match Packet::from_bytes(raw) {
+				Ok(pkt) => self.handle(&pkt),
+				Err(e) => self.log(&format!("dropped packet: {e}")),
+			}
+		}
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+impl Transport for MockDevice {
+	fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+		self.timeout = timeout;
+		Ok(())
+	}
+}
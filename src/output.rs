@@ -0,0 +1,771 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use crate::consts::*;
+use crate::device::HubPort;
+use crate::export::AlbumEntry;
+use crate::i18n::{tr, Msg};
+use crate::image::Quality;
+use crate::power::Rating;
+use crate::protocol::KNOWN_COMMANDS;
+use crate::types::{
+	config_field_names, DeviceConfig, DeviceInfo, FrameHeader, LinkStats, PowerStats, Rotation,
+	METADATA_VERSION,
+};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+	Human,
+	Json,
+}
+
+pub trait Render: Serialize {
+	fn human(&self) -> String;
+}
+
+pub fn emit<T: Render>(value: &T, format: OutputFormat) -> Result<()> {
+	match format {
+		OutputFormat::Human => println!("{}", value.human()),
+		OutputFormat::Json => println!("{}", serde_json::to_string_pretty(value)?),
+	}
+	Ok(())
+}
+
+#[derive(Serialize)]
+pub struct InfoOutput {
+	pub hw_id: u32,
+	pub firmware: String,
+	pub flash_size: u32,
+	pub max_frames: usize,
+	pub erase_block_size: usize,
+}
+
+impl InfoOutput {
+	pub fn new(info: &DeviceInfo) -> Self {
+		Self {
+			hw_id: info.hw_id,
+			firmware: info.fw_version_string(),
+			flash_size: info.flash_size,
+			max_frames: info.max_frames(),
+			erase_block_size: info.erase_block_size(),
+		}
+	}
+}
+
+impl Render for InfoOutput {
+	fn human(&self) -> String {
+		format!(
+			"HM Lab Z-NEO 8K USB Hub\n  {}{:#010x}\n  {}{}\n  {}{} MB\n  {}{}\n  {}{} bytes",
+			label(Msg::HardwareId, 16),
+			self.hw_id,
+			label(Msg::Firmware, 16),
+			self.firmware,
+			label(Msg::FlashSize, 16),
+			self.flash_size / 1024 / 1024,
+			label(Msg::MaxFrames, 16),
+			self.max_frames,
+			label(Msg::EraseBlock, 16),
+			self.erase_block_size
+		)
+	}
+}
+
+#[derive(Serialize)]
+pub struct DeviceRow {
+	pub port: String,
+	pub serial: Option<String>,
+	pub hw_id: Option<u32>,
+	pub firmware: Option<String>,
+	pub flash_size: Option<u32>,
+	pub error: Option<String>,
+}
+
+impl DeviceRow {
+	pub fn new(hub: HubPort, info: &DeviceInfo) -> Self {
+		Self {
+			port: hub.port,
+			serial: hub.serial,
+			hw_id: Some(info.hw_id),
+			firmware: Some(info.fw_version_string()),
+			flash_size: Some(info.flash_size),
+			error: None,
+		}
+	}
+
+	pub fn failed(hub: HubPort, error: &anyhow::Error) -> Self {
+		Self {
+			port: hub.port,
+			serial: hub.serial,
+			hw_id: None,
+			firmware: None,
+			flash_size: None,
+			error: Some(format!("{error:#}")),
+		}
+	}
+}
+
+#[derive(Serialize)]
+pub struct DevicesOutput(pub Vec<DeviceRow>);
+
+impl Render for DevicesOutput {
+	fn human(&self) -> String {
+		if self.0.is_empty() {
+			return "No hubs found".to_string();
+		}
+		let width = self
+			.0
+			.iter()
+			.map(|r| r.port.len())
+			.max()
+			.unwrap_or(0)
+			.max(4);
+		let serial_width = self
+			.0
+			.iter()
+			.filter_map(|r| r.serial.as_ref().map(String::len))
+			.max()
+			.unwrap_or(0)
+			.max(6);
+		let mut lines = vec![format!(
+			"{:<width$}  {:<serial_width$}  {:<10}  {:<8}  {:>6}",
+			"Port", "Serial", "HW ID", "Firmware", "Flash"
+		)];
+		for row in &self.0 {
+			let serial = row.serial.as_deref().unwrap_or("-");
+			lines.push(match (row.hw_id, &row.firmware, row.flash_size) {
+				(Some(hw_id), Some(firmware), Some(flash)) => format!(
+					"{:<width$}  {serial:<serial_width$}  {hw_id:#010x}  {firmware:<8}  {:>3} MB",
+					row.port,
+					flash / 1024 / 1024
+				),
+				_ => format!(
+					"{:<width$}  {serial:<serial_width$}  error: {}",
+					row.port,
+					row.error.as_deref().unwrap_or("no reply")
+				),
+			});
+		}
+		lines.join("\n")
+	}
+}
+
+#[derive(Serialize)]
+pub struct AlbumRow {
+	pub index: usize,
+	pub width: u16,
+	pub height: u16,
+	pub frames: u16,
+	pub delay_ms: u16,
+	pub offset: u32,
+	pub size_bytes: u32,
+	pub crc32: u32,
+}
+
+#[derive(Serialize)]
+pub struct ListOutput(pub Vec<AlbumRow>);
+
+impl ListOutput {
+	pub fn new(headers: &[FrameHeader]) -> Self {
+		Self(
+			headers
+				.iter()
+				.enumerate()
+				.map(|(index, h)| AlbumRow {
+					index,
+					width: h.width,
+					height: h.height,
+					frames: h.frame_count,
+					delay_ms: h.delay_ms,
+					offset: h.data_offset,
+					size_bytes: h.data_length,
+					crc32: h.data_crc32,
+				})
+				.collect(),
+		)
+	}
+}
+
+impl Render for ListOutput {
+	fn human(&self) -> String {
+		if self.0.is_empty() {
+			return "No images on device".to_string();
+		}
+		let mut lines = vec![format!(
+			"{:>5}  {:>10}  {:>6}  {:>7}  {:>9}  {:>8}",
+			"Album", "Dimensions", "Frames", "Delay", "Size", "CRC32"
+		)];
+		for row in &self.0 {
+			lines.push(format!(
+				"{:>5}  {:>10}  {:>6}  {:>5}ms  {:>6} KB  {:08x}",
+				row.index,
+				format!("{}x{}", row.width, row.height),
+				row.frames,
+				row.delay_ms,
+				row.size_bytes.div_ceil(1024),
+				row.crc32
+			));
+		}
+		lines.join("\n")
+	}
+}
+
+#[derive(Serialize)]
+pub struct DuOutput {
+	pub flash_bytes: u32,
+	pub used_bytes: usize,
+	pub free_bytes: usize,
+	pub slots_used: usize,
+	pub slots_total: usize,
+	pub free_frames: usize,
+	pub more_images: usize,
+	pub gif_seconds: f64,
+	pub gif_fps: u32,
+}
+
+impl DuOutput {
+	// `used` counts the header area, so an empty device still uses 8 KB.
+	pub fn new(flash_size: u32, used: usize, slots_used: usize, fps: u32) -> Self {
+		let free_bytes = (flash_size as usize).saturating_sub(used);
+		let free_frames = free_bytes / FRAME_PIXEL_SIZE;
+		let free_slots = MAX_FRAME_HEADERS.saturating_sub(slots_used);
+		// A still image takes a header slot each; a GIF of any length takes one.
+		let gif_frames = if free_slots > 0 { free_frames } else { 0 };
+		Self {
+			flash_bytes: flash_size,
+			used_bytes: used,
+			free_bytes,
+			slots_used,
+			slots_total: MAX_FRAME_HEADERS,
+			free_frames,
+			more_images: free_frames.min(free_slots),
+			gif_seconds: gif_frames as f64 / fps as f64,
+			gif_fps: fps,
+		}
+	}
+}
+
+impl Render for DuOutput {
+	fn human(&self) -> String {
+		format!(
+			"Flash:  {} KB used of {} KB ({:.1}%), {} KB free\n\
+			 Slots:  {} of {} album headers used\n\
+			 Room for about {} more still images, or {:.1}s of GIF at {} fps",
+			self.used_bytes.div_ceil(1024),
+			self.flash_bytes / 1024,
+			self.used_bytes as f64 * 100.0 / self.flash_bytes.max(1) as f64,
+			self.free_bytes / 1024,
+			self.slots_used,
+			self.slots_total,
+			self.more_images,
+			self.gif_seconds,
+			self.gif_fps
+		)
+	}
+}
+
+#[derive(Serialize)]
+pub struct ConfigOutput {
+	pub brightness: u8,
+	pub rotation: Option<u16>,
+	pub screen_onoff_by_usb: bool,
+	pub memory_page: u8,
+	pub crop: bool,
+	pub cut_frame: bool,
+	pub random: bool,
+	pub switch_mode: u16,
+	pub interval_secs: u8,
+	pub single_click: u8,
+	pub double_click: u8,
+	pub tilt: u8,
+	pub shake: u8,
+	pub shake_sens: u8,
+	pub power_style: u8,
+	pub srgb_style: u8,
+	pub language: u8,
+	pub web_help: bool,
+	#[serde(skip)]
+	config: DeviceConfig,
+}
+
+impl ConfigOutput {
+	pub fn new(config: &DeviceConfig) -> Self {
+		Self {
+			brightness: config.screen_brightness,
+			rotation: config.screen_rotation().map(Rotation::degrees),
+			screen_onoff_by_usb: config.screen_onoff_by_usb != 0,
+			memory_page: config.memory_page,
+			crop: config.album_cut_black != 0,
+			cut_frame: config.album_cut_frame != 0,
+			random: config.image_switch_random != 0,
+			switch_mode: config.image_switch_mode,
+			interval_secs: config.image_switch_interval,
+			single_click: config.fun_single_click,
+			double_click: config.fun_double_click,
+			tilt: config.fun_tilt,
+			shake: config.fun_shake,
+			shake_sens: config.fun_shake_sens,
+			power_style: config.power_style,
+			srgb_style: config.srgb_style,
+			language: config.cur_lang,
+			web_help: config.web_help_onoff != 0,
+			config: config.clone(),
+		}
+	}
+}
+
+impl Render for ConfigOutput {
+	fn human(&self) -> String {
+		self.config.to_string()
+	}
+}
+
+#[derive(Serialize)]
+pub struct ConfigFieldOutput {
+	pub field: String,
+	pub value: u32,
+}
+
+impl Render for ConfigFieldOutput {
+	fn human(&self) -> String {
+		self.value.to_string()
+	}
+}
+
+// A field is None where the config holds a value get_field can't express (an
+// unknown rotation code).
+#[derive(Serialize)]
+pub struct FieldDiff {
+	pub field: &'static str,
+	pub current: Option<u32>,
+	pub reference: Option<u32>,
+}
+
+#[derive(Serialize)]
+pub struct ConfigDiffOutput {
+	pub reference: String,
+	pub differences: Vec<FieldDiff>,
+}
+
+impl ConfigDiffOutput {
+	pub fn new(reference: &str, current: &DeviceConfig, other: &DeviceConfig) -> Self {
+		Self {
+			reference: reference.to_string(),
+			differences: config_field_names()
+				.map(|field| FieldDiff {
+					field,
+					current: current.get_field(field).ok(),
+					reference: other.get_field(field).ok(),
+				})
+				.filter(|d| d.current != d.reference)
+				.collect(),
+		}
+	}
+}
+
+impl Render for ConfigDiffOutput {
+	fn human(&self) -> String {
+		if self.differences.is_empty() {
+			return format!("Config matches {}", self.reference);
+		}
+		let show = |v: Option<u32>| v.map_or("?".to_string(), |v| v.to_string());
+		let mut out = format!(
+			"Differences from {} (current -> reference):",
+			self.reference
+		);
+		for d in &self.differences {
+			out.push_str(&format!(
+				"\n  {:<20}{} -> {}",
+				d.field,
+				show(d.current),
+				show(d.reference)
+			));
+		}
+		out
+	}
+}
+
+#[derive(Serialize)]
+pub struct ConfigDumpOutput {
+	pub bytes: String,
+}
+
+impl ConfigDumpOutput {
+	pub fn new(bytes: &[u8]) -> Self {
+		Self {
+			bytes: bytes.iter().map(|b| format!("{b:02x}")).collect(),
+		}
+	}
+}
+
+impl Render for ConfigDumpOutput {
+	fn human(&self) -> String {
+		let bytes: Vec<&str> = (0..self.bytes.len())
+			.step_by(2)
+			.map(|i| &self.bytes[i..i + 2])
+			.collect();
+		bytes
+			.chunks(16)
+			.map(|line| format!("{} ", line.join(" ")))
+			.collect::<Vec<_>>()
+			.join("\n")
+	}
+}
+
+#[derive(Serialize)]
+pub struct PowerOutput {
+	pub timestamp_ms: u64,
+	pub bus_voltage_mv: u16,
+	pub rating: String,
+	pub port1_current_ma: u16,
+	pub port2_current_ma: u16,
+	pub port3_current_ma: u16,
+}
+
+impl PowerOutput {
+	pub fn new(stats: &PowerStats) -> Self {
+		let timestamp_ms = std::time::SystemTime::now()
+			.duration_since(std::time::UNIX_EPOCH)
+			.unwrap_or_default()
+			.as_millis() as u64;
+		Self {
+			timestamp_ms,
+			bus_voltage_mv: stats.bus_voltage,
+			rating: Rating::from_voltage(stats.bus_voltage).to_string(),
+			port1_current_ma: stats.current_port1,
+			port2_current_ma: stats.current_port2,
+			port3_current_ma: stats.current_port3,
+		}
+	}
+}
+
+impl Render for PowerOutput {
+	fn human(&self) -> String {
+		let voltage = self.bus_voltage_mv as f64 / 1000.0;
+		let port = |n: u8| format!("{:<14}", format!("{} {n}:", tr(Msg::Port)));
+		format!(
+			"{}{voltage:.2}V ({})\n{}{}mA\n{}{}mA\n{}{}mA",
+			label(Msg::BusVoltage, 14),
+			self.rating,
+			port(1),
+			self.port1_current_ma,
+			port(2),
+			self.port2_current_ma,
+			port(3),
+			self.port3_current_ma
+		)
+	}
+}
+
+#[derive(Serialize)]
+pub struct LinkOutput {
+	pub packets_sent: u64,
+	pub packets_received: u64,
+	pub crc_errors: u64,
+	pub timeouts: u64,
+	pub retransmissions: u64,
+	pub resyncs: u64,
+}
+
+impl LinkOutput {
+	pub fn new(stats: &LinkStats) -> Self {
+		Self {
+			packets_sent: stats.packets_sent,
+			packets_received: stats.packets_received,
+			crc_errors: stats.crc_errors,
+			timeouts: stats.timeouts,
+			retransmissions: stats.retransmissions,
+			resyncs: stats.resyncs,
+		}
+	}
+}
+
+#[derive(Serialize)]
+pub struct ReadOutput {
+	pub output: PathBuf,
+	// None when the device holds no images.
+	pub manifest: Option<PathBuf>,
+	pub albums: Vec<AlbumEntry>,
+}
+
+impl ReadOutput {
+	pub fn new(output: &Path, manifest: Option<PathBuf>, albums: Vec<AlbumEntry>) -> Self {
+		Self {
+			output: output.to_path_buf(),
+			manifest,
+			albums,
+		}
+	}
+}
+
+impl Render for ReadOutput {
+	fn human(&self) -> String {
+		match &self.manifest {
+			Some(path) => format!("Saved {}", path.display()),
+			None => "No images found on device.".to_string(),
+		}
+	}
+}
+
+#[derive(Serialize)]
+pub struct BackupOutput {
+	pub file: PathBuf,
+	pub size_bytes: usize,
+}
+
+impl BackupOutput {
+	pub fn new(file: &Path, size_bytes: usize) -> Self {
+		Self {
+			file: file.to_path_buf(),
+			size_bytes,
+		}
+	}
+}
+
+impl Render for BackupOutput {
+	fn human(&self) -> String {
+		format!(
+			"Backup saved to {} ({:.1} MB)",
+			self.file.display(),
+			self.size_bytes as f64 / 1_048_576.0
+		)
+	}
+}
+
+// What this build speaks, for bug reports and tools that need to match it.
+#[derive(Serialize)]
+pub struct InternalsOutput {
+	pub version: &'static str,
+	pub packet_size: usize,
+	pub payload_size: usize,
+	pub crc_offset: usize,
+	pub chunk_data_size: usize,
+	pub flash_header_area: usize,
+	pub flash_erase_block: usize,
+	pub frame_header_size: usize,
+	pub max_frame_headers: usize,
+	pub frame_magic: String,
+	pub metadata_version: u8,
+	pub display_width: u32,
+	pub display_height: u32,
+	pub commands: Vec<CommandOutput>,
+	pub features: Vec<&'static str>,
+}
+
+#[derive(Serialize)]
+pub struct CommandOutput {
+	pub id: u8,
+	pub name: &'static str,
+}
+
+impl InternalsOutput {
+	pub fn new() -> Self {
+		let features = [
+			("async", cfg!(feature = "async")),
+			("serde", cfg!(feature = "serde")),
+		];
+		Self {
+			version: env!("CARGO_PKG_VERSION"),
+			packet_size: PACKET_SIZE,
+			payload_size: PAYLOAD_SIZE,
+			crc_offset: CRC_OFFSET,
+			chunk_data_size: CHUNK_DATA_SIZE,
+			flash_header_area: FLASH_HEADER_AREA,
+			flash_erase_block: FLASH_ERASE_BLOCK,
+			frame_header_size: FRAME_HEADER_SIZE,
+			max_frame_headers: MAX_FRAME_HEADERS,
+			frame_magic: format!("{FRAME_MAGIC:#010x}"),
+			metadata_version: METADATA_VERSION,
+			display_width: DISPLAY_WIDTH,
+			display_height: DISPLAY_HEIGHT,
+			commands: KNOWN_COMMANDS
+				.iter()
+				.map(|&(id, name)| CommandOutput { id, name })
+				.collect(),
+			features: features
+				.into_iter()
+				.filter_map(|(name, on)| on.then_some(name))
+				.collect(),
+		}
+	}
+}
+
+#[derive(Serialize)]
+pub struct DoctorOutput {
+	pub rounds: u32,
+	pub replies: usize,
+	pub rtt_min_ms: Option<f64>,
+	pub rtt_avg_ms: Option<f64>,
+	pub rtt_max_ms: Option<f64>,
+	pub config_read_ok: bool,
+	pub readback_ok: bool,
+	pub error_rate: f64,
+	pub link: LinkOutput,
+	pub verdict: String,
+}
+
+impl DoctorOutput {
+	pub fn new(
+		rounds: u32,
+		rtts: &[Duration],
+		config_read_ok: bool,
+		readback_ok: bool,
+		stats: &LinkStats,
+	) -> Self {
+		let ms = |d: &Duration| d.as_secs_f64() * 1000.0;
+		let verdict = if rtts.is_empty() {
+			"no replies: check the port, cable and that nothing else has it open"
+		} else if stats.errors() == 0 && stats.retransmissions == 0 && config_read_ok && readback_ok
+		{
+			"link looks healthy"
+		} else {
+			"marginal link: try another cable, port or hub, and compare"
+		};
+		Self {
+			rounds,
+			replies: rtts.len(),
+			rtt_min_ms: rtts.iter().map(ms).reduce(f64::min),
+			rtt_avg_ms: (!rtts.is_empty())
+				.then(|| rtts.iter().map(ms).sum::<f64>() / rtts.len() as f64),
+			rtt_max_ms: rtts.iter().map(ms).reduce(f64::max),
+			config_read_ok,
+			readback_ok,
+			error_rate: stats.error_rate(),
+			link: LinkOutput::new(stats),
+			verdict: verdict.to_string(),
+		}
+	}
+}
+
+impl Render for DoctorOutput {
+	fn human(&self) -> String {
+		let ok = |b: bool| if b { "ok" } else { "FAILED" };
+		let rtt = match (self.rtt_min_ms, self.rtt_avg_ms, self.rtt_max_ms) {
+			(Some(min), Some(avg), Some(max)) => {
+				format!("min {min:.1}ms  avg {avg:.1}ms  max {max:.1}ms")
+			}
+			_ => "-".to_string(),
+		};
+		let l = &self.link;
+		format!(
+			"Handshakes:      {}/{} answered\n\
+			 Round trip:      {rtt}\n\
+			 Config read:     {}\n\
+			 Flash readback:  {}\n\
+			 Packets:         {} sent, {} received\n\
+			 CRC errors:      {}\n\
+			 Timeouts:        {}\n\
+			 Retransmissions: {}\n\
+			 Resyncs:         {}\n\
+			 Error rate:      {:.2}%\n\n\
+			 {}",
+			self.replies,
+			self.rounds,
+			ok(self.config_read_ok),
+			ok(self.readback_ok),
+			l.packets_sent,
+			l.packets_received,
+			l.crc_errors,
+			l.timeouts,
+			l.retransmissions,
+			l.resyncs,
+			self.error_rate * 100.0,
+			self.verdict
+		)
+	}
+}
+
+// Pads by character count so the columns still line up for non-ASCII labels.
+fn label(msg: Msg, width: usize) -> String {
+	format!("{:<width$}", format!("{}:", tr(msg)))
+}
+
+#[derive(Serialize)]
+pub struct PageEntry {
+	pub page: u8,
+	pub albums: usize,
+	pub frames: usize,
+	pub bytes: usize,
+}
+
+#[derive(Serialize)]
+pub struct PageListOutput {
+	pub active: u8,
+	pub pages: Vec<PageEntry>,
+}
+
+impl Render for PageListOutput {
+	fn human(&self) -> String {
+		let mut lines = vec![format!(
+			"  {:>4}  {:>6}  {:>6}  {:>9}",
+			"Page", "Albums", "Frames", "Size"
+		)];
+		for p in &self.pages {
+			let marker = if p.page == self.active { '*' } else { ' ' };
+			let size = if p.albums == 0 {
+				"empty".to_string()
+			} else {
+				format!("{} KB", p.bytes.div_ceil(1024))
+			};
+			lines.push(format!(
+				"{marker} {:>4}  {:>6}  {:>6}  {size:>9}",
+				p.page, p.albums, p.frames
+			));
+		}
+		lines.join("\n")
+	}
+}
+
+#[derive(Serialize)]
+pub struct QualityRow {
+	pub path: String,
+	pub psnr_db: Option<f64>,
+	pub ssim: f64,
+	pub clipped_shadows_pct: f64,
+	pub clipped_highlights_pct: f64,
+}
+
+impl QualityRow {
+	pub fn new(path: &Path, quality: &Quality) -> Self {
+		Self {
+			path: path.display().to_string(),
+			// Lossless conversions have infinite PSNR, which JSON can't express.
+			psnr_db: quality.psnr.is_finite().then_some(quality.psnr),
+			ssim: quality.ssim,
+			clipped_shadows_pct: quality.clipped_shadows,
+			clipped_highlights_pct: quality.clipped_highlights,
+		}
+	}
+}
+
+#[derive(Serialize)]
+pub struct QualityReport(pub Vec<QualityRow>);
+
+impl Render for QualityReport {
+	fn human(&self) -> String {
+		let width = self
+			.0
+			.iter()
+			.map(|r| r.path.len())
+			.max()
+			.unwrap_or(0)
+			.max(5);
+		let mut lines = vec![format!(
+			"{:<width$}  {:>8}  {:>6}  {:>8}  {:>10}",
+			"Image", "PSNR", "SSIM", "Shadows", "Highlights"
+		)];
+		for row in &self.0 {
+			let psnr = match row.psnr_db {
+				Some(db) => format!("{db:.1}dB"),
+				None => "lossless".to_string(),
+			};
+			lines.push(format!(
+				"{:<width$}  {:>8}  {:>6.4}  {:>7.1}%  {:>9.1}%",
+				row.path, psnr, row.ssim, row.clipped_shadows_pct, row.clipped_highlights_pct
+			));
+		}
+		lines.join("\n")
+	}
+}
@@ -0,0 +1,189 @@
+// A .hmpkg is a zip archive holding `package.json` plus one PNG per frame under
+// `albums/NNN/`. PNG keeps the RGB565 pixels lossless, unlike a 256-colour GIF.
+// The JSON lists albums in playback order, so a package can be re-imported onto
+// a device with a different flash size by dropping albums from the end.
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+
+use crate::consts::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use crate::image::{rgb565_to_image, rgba_to_rgb565};
+use crate::types::{Album, DeviceConfig, DeviceInfo};
+
+pub const PACKAGE_VERSION: u32 = 1;
+const INDEX_FILE: &str = "package.json";
+
+// Settings that describe how content plays rather than the hub it sits on.
+const PORTABLE_FIELDS: &[&str] = &[
+	"brightness",
+	"rotation",
+	"interval",
+	"random",
+	"crop",
+	"switch_mode",
+];
+
+#[derive(Serialize, Deserialize)]
+struct Index {
+	version: u32,
+	created: String,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	source: Option<Source>,
+	#[serde(default)]
+	config: BTreeMap<String, String>,
+	playlist: Vec<PlaylistEntry>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Source {
+	pub hw_id: u32,
+	pub firmware: String,
+	pub flash_size: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PlaylistEntry {
+	delay_ms: u16,
+	frames: Vec<String>,
+}
+
+pub struct Package {
+	pub source: Option<Source>,
+	pub config: BTreeMap<String, String>,
+	pub albums: Vec<Album>,
+}
+
+impl Package {
+	pub fn new(info: &DeviceInfo, config: &DeviceConfig, albums: Vec<Album>) -> Self {
+		Self {
+			source: Some(Source {
+				hw_id: info.hw_id,
+				firmware: info.fw_version_string(),
+				flash_size: info.flash_size,
+			}),
+			config: portable_config(config),
+			albums,
+		}
+	}
+
+	pub fn write(&self, path: &Path) -> Result<()> {
+		let file =
+			File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+		let mut zip = zip::ZipWriter::new(file);
+		let options = zip::write::SimpleFileOptions::default();
+
+		let mut playlist = Vec::with_capacity(self.albums.len());
+		for (i, album) in self.albums.iter().enumerate() {
+			let mut frames = Vec::with_capacity(album.frames.len());
+			for (f, frame) in album.frames.iter().enumerate() {
+				let name = format!("albums/{i:03}/{f:03}.png");
+				let img = rgb565_to_image(frame, DISPLAY_WIDTH as u16, DISPLAY_HEIGHT as u16);
+				let mut png = Vec::new();
+				img.write_to(&mut Cursor::new(&mut png), ::image::ImageFormat::Png)?;
+				zip.start_file(name.as_str(), options)?;
+				zip.write_all(&png)?;
+				frames.push(name);
+			}
+			playlist.push(PlaylistEntry {
+				delay_ms: album.delay_ms,
+				frames,
+			});
+		}
+
+		let index = Index {
+			version: PACKAGE_VERSION,
+			created: chrono::Local::now().to_rfc3339(),
+			source: self.source.clone(),
+			config: self.config.clone(),
+			playlist,
+		};
+		zip.start_file(INDEX_FILE, options)?;
+		zip.write_all(serde_json::to_string_pretty(&index)?.as_bytes())?;
+		zip.finish()?;
+		Ok(())
+	}
+
+	pub fn read(path: &Path) -> Result<Self> {
+		let file =
+			File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+		let mut zip = zip::ZipArchive::new(file)
+			.with_context(|| format!("{} is not a valid package", path.display()))?;
+
+		let index: Index = {
+			let mut entry = zip
+				.by_name(INDEX_FILE)
+				.with_context(|| format!("{} has no {INDEX_FILE}", path.display()))?;
+			let mut json = String::new();
+			entry.read_to_string(&mut json)?;
+			serde_json::from_str(&json).with_context(|| format!("invalid {INDEX_FILE}"))?
+		};
+		if index.version != PACKAGE_VERSION {
+			bail!("unsupported package version: {}", index.version);
+		}
+		for field in index.config.keys() {
+			if !PORTABLE_FIELDS.contains(&field.as_str()) {
+				bail!("package config has unsupported field '{field}'");
+			}
+		}
+
+		let mut albums = Vec::with_capacity(index.playlist.len());
+		for entry in &index.playlist {
+			if entry.frames.is_empty() {
+				bail!("package has an album with no frames");
+			}
+			let mut frames = Vec::with_capacity(entry.frames.len());
+			for name in &entry.frames {
+				let mut png = Vec::new();
+				zip.by_name(name)
+					.with_context(|| format!("package is missing {name}"))?
+					.read_to_end(&mut png)?;
+				let img = ::image::load_from_memory(&png)
+					.with_context(|| format!("failed to decode {name}"))?
+					.to_rgba8();
+				if img.width() != DISPLAY_WIDTH || img.height() != DISPLAY_HEIGHT {
+					bail!(
+						"{name} is {}x{}, expected {DISPLAY_WIDTH}x{DISPLAY_HEIGHT}",
+						img.width(),
+						img.height()
+					);
+				}
+				frames.push(rgba_to_rgb565(img.as_raw(), DISPLAY_WIDTH, DISPLAY_HEIGHT));
+			}
+			albums.push(Album {
+				frames,
+				delay_ms: entry.delay_ms,
+			});
+		}
+
+		Ok(Self {
+			source: index.source,
+			config: index.config,
+			albums,
+		})
+	}
+}
+
+fn portable_config(config: &DeviceConfig) -> BTreeMap<String, String> {
+	let mut fields = BTreeMap::new();
+	fields.insert(
+		"brightness".to_string(),
+		config.screen_brightness.to_string(),
+	);
+	if let Some(rotation) = config.screen_rotation() {
+		fields.insert("rotation".to_string(), rotation.degrees().to_string());
+	}
+	fields.insert(
+		"interval".to_string(),
+		config.image_switch_interval.to_string(),
+	);
+	fields.insert("random".to_string(), config.image_switch_random.to_string());
+	fields.insert("crop".to_string(), config.album_cut_black.to_string());
+	fields.insert(
+		"switch_mode".to_string(),
+		config.image_switch_mode.to_string(),
+	);
+	fields
+}
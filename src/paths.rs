@@ -0,0 +1,23 @@
+use anyhow::{bail, Result};
+use std::path::PathBuf;
+
+fn xdg_dir(var: &str, fallback: &str) -> Result<PathBuf> {
+	if let Some(dir) = std::env::var_os(var).filter(|d| !d.is_empty()) {
+		return Ok(PathBuf::from(dir).join("hm-hub"));
+	}
+	if let Some(dir) = std::env::var_os("LOCALAPPDATA") {
+		return Ok(PathBuf::from(dir).join("hm-hub"));
+	}
+	match std::env::var_os("HOME") {
+		Some(home) => Ok(PathBuf::from(home).join(fallback).join("hm-hub")),
+		None => bail!("cannot determine home directory (set HOME or {var})"),
+	}
+}
+
+pub fn state_dir() -> Result<PathBuf> {
+	xdg_dir("XDG_STATE_HOME", ".local/state")
+}
+
+pub fn data_dir() -> Result<PathBuf> {
+	xdg_dir("XDG_DATA_HOME", ".local/share")
+}
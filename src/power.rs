@@ -0,0 +1,344 @@
+use anyhow::{bail, Result};
+use clap::ValueEnum;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::consts::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use crate::image::fill_rect;
+use crate::types::PowerStats;
+
+pub const PORT_COUNT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rating {
+	Healthy,
+	Warning,
+	Critical,
+}
+
+impl Rating {
+	pub fn from_voltage(millivolts: u16) -> Self {
+		if millivolts >= 4750 {
+			Rating::Healthy
+		} else if millivolts >= 4250 {
+			Rating::Warning
+		} else {
+			Rating::Critical
+		}
+	}
+}
+
+impl fmt::Display for Rating {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Rating::Healthy => "Healthy",
+			Rating::Warning => "Warning",
+			Rating::Critical => "Critical",
+		})
+	}
+}
+
+pub fn port_currents(stats: &PowerStats) -> [u16; PORT_COUNT] {
+	[
+		stats.current_port1,
+		stats.current_port2,
+		stats.current_port3,
+	]
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PortLimit {
+	pub port: usize,
+	pub max_ma: u16,
+}
+
+impl FromStr for PortLimit {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (port, max) = s
+			.split_once('=')
+			.ok_or_else(|| format!("invalid limit '{s}', expected portN=MA (e.g. port2=900)"))?;
+		let port = port.trim_start_matches("port");
+		let port: usize = port.parse().map_err(|_| format!("invalid port in '{s}'"))?;
+		if !(1..=PORT_COUNT).contains(&port) {
+			return Err(format!("port must be 1-{PORT_COUNT}, got {port}"));
+		}
+		let max_ma = max
+			.trim_end_matches("mA")
+			.trim_end_matches("ma")
+			.parse()
+			.map_err(|_| format!("invalid current in '{s}'"))?;
+		Ok(PortLimit { port, max_ma })
+	}
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Stat {
+	min: u16,
+	max: u16,
+	sum: u64,
+	count: u64,
+}
+
+impl Stat {
+	fn add(&mut self, value: u16) {
+		if self.count == 0 {
+			self.min = value;
+			self.max = value;
+		} else {
+			self.min = self.min.min(value);
+			self.max = self.max.max(value);
+		}
+		self.sum += value as u64;
+		self.count += 1;
+	}
+
+	fn avg(&self) -> f64 {
+		if self.count == 0 {
+			0.0
+		} else {
+			self.sum as f64 / self.count as f64
+		}
+	}
+}
+
+pub struct PowerSession {
+	limits: [Option<u16>; PORT_COUNT],
+	over_limit: [bool; PORT_COUNT],
+	violations: [u32; PORT_COUNT],
+	samples: u64,
+	voltage: Stat,
+	currents: [Stat; PORT_COUNT],
+	rating: Rating,
+	warnings: u32,
+	criticals: u32,
+	started: Instant,
+	max_duration: Option<Duration>,
+	max_samples: Option<u64>,
+	display_interval: Duration,
+	last_display: Option<Instant>,
+}
+
+impl PowerSession {
+	pub fn new(limits: &[PortLimit]) -> Result<Self> {
+		let mut per_port = [None; PORT_COUNT];
+		for limit in limits {
+			if per_port[limit.port - 1].is_some() {
+				bail!("duplicate limit for port {}", limit.port);
+			}
+			per_port[limit.port - 1] = Some(limit.max_ma);
+		}
+		Ok(Self {
+			limits: per_port,
+			over_limit: [false; PORT_COUNT],
+			violations: [0; PORT_COUNT],
+			samples: 0,
+			voltage: Stat::default(),
+			currents: [Stat::default(); PORT_COUNT],
+			rating: Rating::Healthy,
+			warnings: 0,
+			criticals: 0,
+			started: Instant::now(),
+			max_duration: None,
+			max_samples: None,
+			display_interval: Duration::ZERO,
+			last_display: None,
+		})
+	}
+
+	pub fn with_display_interval(mut self, interval: Duration) -> Self {
+		self.display_interval = interval;
+		self
+	}
+
+	pub fn should_display(&mut self) -> bool {
+		let now = Instant::now();
+		match self.last_display {
+			Some(last) if now.duration_since(last) < self.display_interval => false,
+			_ => {
+				self.last_display = Some(now);
+				true
+			}
+		}
+	}
+
+	pub fn with_bounds(mut self, duration: Option<Duration>, count: Option<u64>) -> Self {
+		self.max_duration = duration;
+		self.max_samples = count;
+		self
+	}
+
+	pub fn is_complete(&self) -> bool {
+		self.max_duration.is_some_and(|d| self.elapsed() >= d)
+			|| self.max_samples.is_some_and(|n| self.samples >= n)
+	}
+
+	// Returns the ports that crossed their limit with this sample.
+	pub fn record(&mut self, stats: &PowerStats) -> Vec<usize> {
+		self.samples += 1;
+		self.voltage.add(stats.bus_voltage);
+
+		let rating = Rating::from_voltage(stats.bus_voltage);
+		if rating != self.rating {
+			match rating {
+				Rating::Warning => self.warnings += 1,
+				Rating::Critical => self.criticals += 1,
+				Rating::Healthy => {}
+			}
+			self.rating = rating;
+		}
+
+		let mut new_violations = Vec::new();
+		for (i, current) in port_currents(stats).into_iter().enumerate() {
+			self.currents[i].add(current);
+			let over = self.limits[i].is_some_and(|max| current > max);
+			if over && !self.over_limit[i] {
+				self.violations[i] += 1;
+				new_violations.push(i + 1);
+			}
+			self.over_limit[i] = over;
+		}
+		new_violations
+	}
+
+	pub fn limit(&self, port: usize) -> Option<u16> {
+		self.limits[port - 1]
+	}
+
+	pub fn status_line(&self, stats: &PowerStats) -> String {
+		let voltage = stats.bus_voltage as f64 / 1000.0;
+		let rating = Rating::from_voltage(stats.bus_voltage);
+		let ports: Vec<String> = port_currents(stats)
+			.iter()
+			.enumerate()
+			.map(|(i, current)| {
+				if self.over_limit[i] {
+					format!("{current}mA!")
+				} else {
+					format!("{current}mA")
+				}
+			})
+			.collect();
+		format!("Bus: {voltage:.2}V ({rating}) | Ports: {}", ports.join(" "))
+	}
+
+	pub fn elapsed(&self) -> Duration {
+		self.started.elapsed()
+	}
+
+	pub fn print_summary(&self) {
+		eprintln!(
+			"Session: {:.0?}, {} sample(s)",
+			self.elapsed(),
+			self.samples
+		);
+		if self.samples == 0 {
+			return;
+		}
+		eprintln!(
+			"  Bus voltage: min {:.2}V  max {:.2}V  avg {:.2}V",
+			self.voltage.min as f64 / 1000.0,
+			self.voltage.max as f64 / 1000.0,
+			self.voltage.avg() / 1000.0
+		);
+		for (i, stat) in self.currents.iter().enumerate() {
+			eprintln!(
+				"  Port {}: min {}mA  max {}mA  avg {:.0}mA",
+				i + 1,
+				stat.min,
+				stat.max,
+				stat.avg()
+			);
+		}
+		eprintln!(
+			"  Excursions: {} Warning, {} Critical",
+			self.warnings, self.criticals
+		);
+		for (i, limit) in self.limits.iter().enumerate() {
+			if let Some(max) = limit {
+				eprintln!(
+					"  Port {} limit {max}mA: {} violation(s)",
+					i + 1,
+					self.violations[i]
+				);
+			}
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Edge {
+	Top,
+	Bottom,
+}
+
+// Bus voltage range covered by the voltage gauge, in millivolts.
+const GAUGE_MIN_MV: u16 = 4000;
+const GAUGE_MAX_MV: u16 = 5500;
+
+// Draws four gauges across one edge of an RGB565 frame: bus voltage (coloured by
+// rating) followed by each port's current as a fraction of `full_scale_ma`.
+pub fn draw_power_bar(
+	frame: &mut [u8],
+	stats: &PowerStats,
+	edge: Edge,
+	height: u32,
+	full_scale_ma: u16,
+) {
+	const GAP: u32 = 2;
+	const TRACK: [u8; 3] = [40, 40, 40];
+	const CURRENT: [u8; 3] = [0, 176, 255];
+
+	let height = height.min(DISPLAY_HEIGHT);
+	let y = match edge {
+		Edge::Top => 0,
+		Edge::Bottom => DISPLAY_HEIGHT - height,
+	};
+	fill_rect(frame, 0, y, DISPLAY_WIDTH, height, [0, 0, 0]);
+
+	let voltage = stats.bus_voltage.clamp(GAUGE_MIN_MV, GAUGE_MAX_MV) - GAUGE_MIN_MV;
+	let voltage_color = match Rating::from_voltage(stats.bus_voltage) {
+		Rating::Healthy => [0, 200, 83],
+		Rating::Warning => [255, 193, 7],
+		Rating::Critical => [244, 67, 54],
+	};
+	let mut gauges = vec![(
+		voltage as f64 / (GAUGE_MAX_MV - GAUGE_MIN_MV) as f64,
+		voltage_color,
+	)];
+	for current in port_currents(stats) {
+		let fraction = (current as f64 / full_scale_ma.max(1) as f64).min(1.0);
+		gauges.push((fraction, CURRENT));
+	}
+
+	let slot = DISPLAY_WIDTH / gauges.len() as u32;
+	for (i, (fraction, color)) in gauges.into_iter().enumerate() {
+		let x = i as u32 * slot + GAP / 2;
+		let width = slot - GAP;
+		fill_rect(frame, x, y, width, height, TRACK);
+		fill_rect(
+			frame,
+			x,
+			y,
+			(width as f64 * fraction).round() as u32,
+			height,
+			color,
+		);
+	}
+}
+
+// Whether a new reading is different enough from the one last drawn to be worth
+// re-rendering: the voltage rating changed or a port moved by `min_change_ma`.
+pub fn changed_meaningfully(
+	previous: &PowerStats,
+	current: &PowerStats,
+	min_change_ma: u16,
+) -> bool {
+	Rating::from_voltage(previous.bus_voltage) != Rating::from_voltage(current.bus_voltage)
+		|| port_currents(previous)
+			.into_iter()
+			.zip(port_currents(current))
+			.any(|(a, b)| a.abs_diff(b) >= min_change_ma)
+}
@@ -0,0 +1,181 @@
+use indicatif::{ProgressBar, ProgressStyle};
+use std::io::IsTerminal;
+use std::sync::Mutex;
+
+use crate::i18n::{tr, Msg};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressMode {
+	Bar,
+	Lines,
+	Hidden,
+	// Like Hidden, but transfers skip the progress tracker entirely and drop transfer
+	// notices, keeping per-packet work to a minimum on slow hosts.
+	Minimal,
+}
+
+impl ProgressMode {
+	pub fn detect(disabled: bool) -> Self {
+		if disabled {
+			ProgressMode::Hidden
+		} else if std::io::stderr().is_terminal() {
+			ProgressMode::Bar
+		} else {
+			ProgressMode::Lines
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+	Erasing,
+	Writing,
+	Reading,
+}
+
+// Receives progress from flash transfers. Every method defaults to doing nothing,
+// so GUI or daemon consumers implement only what they display.
+pub trait ProgressObserver {
+	fn on_phase(&mut self, _phase: Phase) {}
+
+	fn on_progress(&mut self, _done: u64, _total: u64) {}
+
+	// Notices about the transfer itself. Device log lines go to `tracing`.
+	fn on_message(&mut self, _message: &str) {}
+
+	fn on_finish(&mut self, _ok: bool) {}
+}
+
+impl ProgressObserver for () {}
+
+pub struct Progress {
+	mode: ProgressMode,
+	bar: Option<ProgressBar>,
+	total: u64,
+	last_step: u64,
+	message: String,
+	phase: Option<Phase>,
+}
+
+const LINE_STEP_PERCENT: u64 = 10;
+
+// The bar currently on screen, so log output can be printed above it instead of
+// through it.
+static ACTIVE_BAR: Mutex<Option<ProgressBar>> = Mutex::new(None);
+
+// Runs `f` (which writes to the terminal) with any progress bar hidden.
+pub fn suspend<R>(f: impl FnOnce() -> R) -> R {
+	let bar = ACTIVE_BAR.lock().ok().and_then(|bar| bar.clone());
+	match bar {
+		Some(pb) => pb.suspend(f),
+		None => f(),
+	}
+}
+
+fn set_active(bar: Option<&ProgressBar>) {
+	if let Ok(mut active) = ACTIVE_BAR.lock() {
+		*active = bar.cloned();
+	}
+}
+
+impl Progress {
+	pub fn new(mode: ProgressMode, total: u64) -> Self {
+		let bar = if mode == ProgressMode::Bar {
+			let pb = ProgressBar::new(total);
+			pb.set_style(
+				ProgressStyle::default_bar()
+					.template("{spinner:.cyan} [{bar:40.cyan/dim}] {bytes}/{total_bytes} ({eta})")
+					.expect("progress template is valid")
+					.progress_chars("=> "),
+			);
+			pb.set_position(0);
+			set_active(Some(&pb));
+			Some(pb)
+		} else {
+			None
+		};
+		Self {
+			mode,
+			bar,
+			total,
+			last_step: 0,
+			message: String::new(),
+			phase: None,
+		}
+	}
+
+	pub fn set_position(&mut self, pos: u64) {
+		if let Some(pb) = &self.bar {
+			pb.set_position(pos);
+		} else if self.mode == ProgressMode::Lines && self.total > 0 {
+			let percent = pos.min(self.total) * 100 / self.total;
+			let step = percent / LINE_STEP_PERCENT;
+			if step > self.last_step {
+				self.last_step = step;
+				eprintln!("{}%", step * LINE_STEP_PERCENT);
+			}
+		}
+	}
+
+	pub fn set_message(&mut self, msg: &str) {
+		if let Some(pb) = &self.bar {
+			pb.set_message(msg.to_string());
+		} else if self.mode == ProgressMode::Lines && self.message != msg {
+			eprintln!("{msg}");
+		}
+		self.message = msg.to_string();
+	}
+
+	pub fn println(&self, msg: &str) {
+		match &self.bar {
+			Some(pb) => pb.println(msg),
+			None => eprintln!("{msg}"),
+		}
+	}
+
+	pub fn finish(&self, msg: &str) {
+		match &self.bar {
+			Some(pb) => {
+				pb.finish_with_message(msg.to_string());
+				set_active(None);
+			}
+			None if self.mode == ProgressMode::Lines => eprintln!("{msg}"),
+			None => {}
+		}
+	}
+}
+
+impl Drop for Progress {
+	fn drop(&mut self) {
+		if self.bar.is_some() {
+			set_active(None);
+		}
+	}
+}
+
+impl ProgressObserver for Progress {
+	fn on_phase(&mut self, phase: Phase) {
+		if phase == Phase::Erasing {
+			self.set_message(tr(Msg::Erasing));
+		}
+		self.phase = Some(phase);
+	}
+
+	fn on_progress(&mut self, done: u64, _total: u64) {
+		self.set_position(done);
+	}
+
+	fn on_message(&mut self, message: &str) {
+		self.println(message);
+	}
+
+	fn on_finish(&mut self, ok: bool) {
+		let msg = match (self.phase == Some(Phase::Reading), ok) {
+			(true, true) => Msg::ReadComplete,
+			(true, false) => Msg::ReadFailed,
+			(false, true) => Msg::UploadComplete,
+			(false, false) => Msg::UploadFailed,
+		};
+		self.finish(tr(msg));
+	}
+}
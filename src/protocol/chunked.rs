@@ -1,8 +1,9 @@
-use anyhow::Result;
-
 use crate::consts::{CHUNK_DATA_SIZE, PAYLOAD_SIZE};
+use crate::error::Result;
 use crate::types::Packet;
 
+/// Splits `data` plus its CRC32 into numbered packets, as the device expects for
+/// anything larger than one payload.
 pub fn encode_chunked(cmd_id: u8, sub_cmd: u8, data: &[u8]) -> Result<Vec<Packet>> {
 	let crc = crc32fast::hash(data);
 	let mut full_data = Vec::with_capacity(data.len() + 4);
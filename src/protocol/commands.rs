@@ -1,15 +1,20 @@
-use anyhow::{bail, Result};
-
 use crate::consts::*;
-use crate::types::{DeviceInfo, Packet, PowerStats};
+use crate::error::{HubError, Result};
+use crate::protocol::chunked::encode_chunked;
+use crate::types::{DeviceConfig, DeviceInfo, Packet, PowerStats};
 
+/// Asks the device to identify itself; it answers with a handshake packet.
 pub fn build_handshake() -> Result<Packet> {
 	Packet::new(CMD_HANDSHAKE, &[0; PAYLOAD_SIZE])
 }
 
+/// Hardware id, firmware version and flash size, each a u32.
 pub fn parse_handshake(packet: &Packet) -> Result<DeviceInfo> {
 	if packet.cmd_id() != CMD_HANDSHAKE {
-		bail!("expected handshake response, got cmd {}", packet.cmd_id());
+		return Err(HubError::ProtocolViolation(format!(
+			"expected handshake response, got cmd {}",
+			packet.cmd_id()
+		)));
 	}
 	let p = packet.payload();
 	Ok(DeviceInfo {
@@ -19,12 +24,15 @@ pub fn parse_handshake(packet: &Packet) -> Result<DeviceInfo> {
 	})
 }
 
+/// The reply is a run of chunked `CMD_CONFIG` packets with sub-command 2.
 pub fn build_config_read() -> Result<Packet> {
 	let mut payload = [0u8; PAYLOAD_SIZE];
 	payload[0] = 1;
 	Packet::new(CMD_CONFIG, &payload)
 }
 
+/// Starts an upload. The device erases `total_size` bytes, then requests the data
+/// piece by piece with sub-command 2 packets (offset u32, length u16).
 pub fn build_flash_start(total_size: u32) -> Result<Packet> {
 	let mut payload = [0u8; PAYLOAD_SIZE];
 	payload[0] = 1;
@@ -32,29 +40,98 @@ pub fn build_flash_start(total_size: u32) -> Result<Packet> {
 	Packet::new(CMD_FLASH, &payload)
 }
 
-pub fn build_flash_data_response(offset: u32, length: u16, data: &[u8]) -> Result<Packet> {
+/// Answers a device data request by copying straight from the flash image into a
+/// reused packet. Anything past the end of the image reads as erased flash; returns
+/// whether the request ran past the end.
+pub fn fill_flash_data_response(
+	pkt: &mut Packet,
+	offset: u32,
+	length: u16,
+	flash_data: &[u8],
+) -> bool {
+	// A request that starts past the end copies nothing but must not index past it.
+	let start = (offset as usize).min(flash_data.len());
+	let len = (length as usize).min(PAYLOAD_SIZE - 7);
+	let available = (flash_data.len() - start).min(len);
+
+	let buf = &mut pkt.buf;
+	buf[0] = CMD_FLASH;
+	buf[1] = 2;
+	buf[2..6].copy_from_slice(&offset.to_le_bytes());
+	buf[6..8].copy_from_slice(&length.to_le_bytes());
+	buf[8..8 + available].copy_from_slice(&flash_data[start..start + available]);
+	buf[8 + available..8 + len].fill(0xFF);
+	buf[8 + len..CRC_OFFSET].fill(0);
+	pkt.seal();
+	offset as usize + length as usize > flash_data.len()
+}
+
+/// Sub-code 1 carries a status byte. Only 2 (erasing) is confirmed on hardware; 3
+/// (upload size rejected) and 4 (data out of sequence) are the failures the emulator
+/// reports and the only statuses treated as fatal. Other statuses and sub-codes may
+/// be harmless, so callers log them rather than abort.
+pub fn parse_flash_error(packet: &Packet) -> Option<String> {
+	let p = packet.payload();
+	match (p[0], p[1]) {
+		(1, 3) => Some("device rejected the upload size".to_string()),
+		(1, 4) => Some("device received flash data out of sequence".to_string()),
+		_ => None,
+	}
+}
+
+/// Asks for the whole flash. The device streams it back as sub-command 3 packets
+/// (offset u32, length u16, data) and ends with a sub-command 4 packet.
+pub fn build_flash_readback() -> Result<Packet> {
 	let mut payload = [0u8; PAYLOAD_SIZE];
-	payload[0] = 2;
-	payload[1..5].copy_from_slice(&offset.to_le_bytes());
-	payload[5..7].copy_from_slice(&length.to_le_bytes());
-	let len = data.len().min(PAYLOAD_SIZE - 7);
-	payload[7..7 + len].copy_from_slice(&data[..len]);
+	payload[0] = 3;
 	Packet::new(CMD_FLASH, &payload)
 }
 
-pub fn build_flash_readback() -> Result<Packet> {
+/// Splits a sub-command 3 readback packet into its flash offset and data. A length
+/// longer than the payload can hold is a protocol violation, not a slice to trust.
+pub fn parse_readback_data(packet: &Packet) -> Result<(usize, &[u8])> {
+	let p = packet.payload();
+	let offset = u32::from_le_bytes([p[1], p[2], p[3], p[4]]) as usize;
+	let length = u16::from_le_bytes([p[5], p[6]]) as usize;
+	let data = p.get(7..7 + length).ok_or_else(|| {
+		HubError::ProtocolViolation(format!(
+			"readback packet claims {length} data bytes, at most {} fit",
+			p.len() - 7
+		))
+	})?;
+	Ok((offset, data))
+}
+
+/// Asks for `length` bytes from `offset`. Zero offset and length (as sent by
+/// [`build_flash_readback`]) mean the whole flash. The range fields are a host-side
+/// extension not confirmed on real firmware, which may ignore them and stream the
+/// whole flash; callers must keep only the window they asked for.
+pub fn build_flash_readback_range(offset: u32, length: u32) -> Result<Packet> {
 	let mut payload = [0u8; PAYLOAD_SIZE];
 	payload[0] = 3;
+	payload[1..5].copy_from_slice(&offset.to_le_bytes());
+	payload[5..9].copy_from_slice(&length.to_le_bytes());
 	Packet::new(CMD_FLASH, &payload)
 }
 
+/// Replaces the whole config block. The device doesn't acknowledge the write; read
+/// the config back to check it.
+pub fn build_config_write(config: &DeviceConfig) -> Result<Vec<Packet>> {
+	encode_chunked(CMD_CONFIG, 2, &config.to_bytes())
+}
+
+/// Restores default config. The device doesn't reply.
 pub fn build_factory_reset() -> Result<Packet> {
 	Packet::new(CMD_FACTORY_RESET, &[0; PAYLOAD_SIZE])
 }
 
+/// Bus voltage in mV, then each port's current in mA, all u16.
 pub fn parse_power_stats(packet: &Packet) -> Result<PowerStats> {
 	if packet.cmd_id() != CMD_POWER {
-		bail!("expected power stats, got cmd {}", packet.cmd_id());
+		return Err(HubError::ProtocolViolation(format!(
+			"expected power stats, got cmd {}",
+			packet.cmd_id()
+		)));
 	}
 	let p = packet.payload();
 	Ok(PowerStats {
@@ -65,9 +142,13 @@ pub fn parse_power_stats(packet: &Packet) -> Result<PowerStats> {
 	})
 }
 
+/// A length byte followed by that many bytes of text.
 pub fn parse_log(packet: &Packet) -> Result<String> {
 	if packet.cmd_id() != CMD_LOG {
-		bail!("expected log, got cmd {}", packet.cmd_id());
+		return Err(HubError::ProtocolViolation(format!(
+			"expected log, got cmd {}",
+			packet.cmd_id()
+		)));
 	}
 	let p = packet.payload();
 	let len = (p[0] as usize).min(p.len() - 1);
@@ -1,3 +1,66 @@
+//! Low-level packet builders and parsers for the hub's serial protocol.
+//!
+//! `Device` covers everything the stock tools need; this module is for working out
+//! what else the firmware understands. Build a packet here, send it with
+//! `Device::send_packet`, and read replies with `Device::recv_packet` (or
+//! `Device::events`, which leaves unrecognised packets as `DeviceEvent::Unknown`).
+//!
+//! # Framing
+//!
+//! Every packet is exactly [`PACKET_SIZE`] (256) bytes in both directions:
+//!
+//! | bytes     | contents                                         |
+//! |-----------|--------------------------------------------------|
+//! | 0         | command id (`CMD_*`)                             |
+//! | 1..252    | payload, zero-padded ([`PAYLOAD_SIZE`] bytes)    |
+//! | 252..256  | CRC32 of bytes 0..252, little-endian             |
+//!
+//! [`Packet::new`] fills in the CRC and [`Packet::from_bytes`] checks it. The first
+//! payload byte is a sub-command for most commands. Multi-byte fields are
+//! little-endian throughout.
+//!
+//! # Chunked transfers
+//!
+//! Data too large for one packet (the config block) is sent as [`encode_chunked`]
+//! packets: sub-command, total chunks (u16), chunk index (u16), chunk length (u16),
+//! then up to [`CHUNK_DATA_SIZE`] bytes. The data is followed by its CRC32 before
+//! splitting. Replies from the device use a shorter chunk header (index u8, total u8,
+//! length u16) after the sub-command byte; feed the payload after that byte to a
+//! [`ChunkedReceiver`], which reorders chunks and checks the CRC.
+//!
+//! # Known commands
+//!
+//! | id | command                                 | builders / parsers                     |
+//! |----|-----------------------------------------|----------------------------------------|
+//! | 1  | handshake                               | [`build_handshake`], [`parse_handshake`] |
+//! | 3  | config read (sub 1) / write (sub 2)     | [`build_config_read`], [`build_config_write`] |
+//! | 6  | factory reset                           | [`build_factory_reset`]                |
+//! | 8  | flash upload (sub 1, 2) / readback (3)  | [`build_flash_start`], [`fill_flash_data_response`], [`build_flash_readback_range`], [`parse_flash_error`] |
+//! | 9  | power report (sent by the device)       | [`parse_power_stats`]                  |
+//! | 10 | log message (sent by the device)        | [`parse_log`]                          |
+//!
+//! Anything else is unexplored. Command ids the firmware doesn't know are ignored
+//! rather than answered, so a timeout is the usual sign of a dead end.
+
 pub mod chunked;
 pub mod commands;
 pub mod packet;
+
+pub use crate::consts::{
+	CHUNK_DATA_SIZE, CMD_CONFIG, CMD_FACTORY_RESET, CMD_FLASH, CMD_HANDSHAKE, CMD_LOG, CMD_POWER,
+	CRC_OFFSET, PACKET_SIZE, PAYLOAD_SIZE,
+};
+pub use crate::types::{ChunkedReceiver, Packet};
+pub use chunked::encode_chunked;
+pub use commands::*;
+pub use packet::{read_packet, recv_packet};
+
+/// Every command id this crate speaks, with a short name, as in the table above.
+pub const KNOWN_COMMANDS: &[(u8, &str)] = &[
+	(CMD_HANDSHAKE, "handshake"),
+	(CMD_CONFIG, "config"),
+	(CMD_FACTORY_RESET, "factory_reset"),
+	(CMD_FLASH, "flash"),
+	(CMD_POWER, "power"),
+	(CMD_LOG, "log"),
+];
@@ -1,28 +1,44 @@
-use anyhow::{bail, Result};
 use std::time::Duration;
 
 use crate::consts::PACKET_SIZE;
-use crate::types::Packet;
+use crate::error::{HubError, Result};
+use crate::transport::Transport;
+use crate::types::{trace_packet, Packet};
 
-pub fn recv_packet(port: &mut dyn serialport::SerialPort, timeout: Duration) -> Result<Packet> {
+/// Waits up to `timeout` for one whole packet and checks its CRC.
+pub fn recv_packet<T: Transport + ?Sized>(port: &mut T, timeout: Duration) -> Result<Packet> {
 	port.set_timeout(timeout)?;
+	read_packet(port, timeout)
+}
+
+/// Like [`recv_packet`], but assumes the transport timeout is already set. Hot loops use
+/// this to avoid reconfiguring the port for every packet.
+pub fn read_packet<T: Transport + ?Sized>(port: &mut T, timeout: Duration) -> Result<Packet> {
 	let mut buf = [0u8; PACKET_SIZE];
 	let mut pos = 0;
 	let deadline = std::time::Instant::now() + timeout;
 	while pos < PACKET_SIZE {
 		if std::time::Instant::now() > deadline {
-			bail!("timeout waiting for packet ({pos}/{PACKET_SIZE} bytes received)");
+			return Err(HubError::Timeout(format!(
+				"timeout waiting for packet ({pos}/{PACKET_SIZE} bytes received)"
+			)));
 		}
 		match port.read(&mut buf[pos..]) {
-			Ok(0) => bail!("serial port EOF"),
+			Ok(0) => return Err(HubError::Disconnected),
 			Ok(n) => pos += n,
-			Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {
+			Err(e)
+				if matches!(
+					e.kind(),
+					std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock
+				) =>
+			{
 				if pos == 0 {
-					bail!("timeout waiting for packet");
+					return Err(HubError::Timeout("timeout waiting for packet".to_string()));
 				}
 			}
 			Err(e) => return Err(e.into()),
 		}
 	}
+	trace_packet("rx", &buf);
 	Packet::from_bytes(buf)
 }
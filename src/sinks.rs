@@ -0,0 +1,176 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+
+use crate::output::{LinkOutput, PowerOutput};
+use crate::types::{LinkStats, PowerStats};
+
+pub enum Event<'a> {
+	Sample {
+		stats: &'a PowerStats,
+		status: String,
+	},
+	Limit {
+		port: usize,
+		current_ma: u16,
+		limit_ma: u16,
+	},
+	Alert(String),
+	Link {
+		up: bool,
+		message: String,
+	},
+	// Link error counters, sent whenever one of them goes up.
+	LinkStats(LinkStats),
+}
+
+impl Event<'_> {
+	fn message(&self) -> String {
+		match self {
+			Event::Sample { status, .. } => status.clone(),
+			Event::Limit {
+				port,
+				current_ma,
+				limit_ma,
+			} => format!("[limit] Port {port} over budget: {current_ma}mA > {limit_ma}mA"),
+			Event::Alert(message) => format!("[alert] {message}"),
+			Event::Link { message, .. } => format!("[watchdog] {message}"),
+			Event::LinkStats(stats) => format!("[link] {stats}"),
+		}
+	}
+}
+
+pub trait Sink {
+	fn emit(&mut self, event: &Event) -> Result<()>;
+
+	fn finish(&mut self) -> Result<()> {
+		Ok(())
+	}
+}
+
+pub struct StatusLine;
+
+impl Sink for StatusLine {
+	fn emit(&mut self, event: &Event) -> Result<()> {
+		match event {
+			Event::Sample { status, .. } => eprint!("\r{status}   "),
+			_ => eprintln!("\r{}                ", event.message()),
+		}
+		Ok(())
+	}
+
+	fn finish(&mut self) -> Result<()> {
+		eprintln!();
+		Ok(())
+	}
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum JsonEvent {
+	Sample(PowerOutput),
+	Limit {
+		port: usize,
+		current_ma: u16,
+		limit_ma: u16,
+	},
+	Alert {
+		message: String,
+	},
+	Link {
+		up: bool,
+		message: String,
+	},
+	#[serde(rename = "link_stats")]
+	LinkStats(LinkOutput),
+}
+
+// One JSON object per line, for stdout or an appended log file.
+pub struct Ndjson<W: Write> {
+	out: W,
+}
+
+impl Ndjson<std::io::Stdout> {
+	pub fn stdout() -> Self {
+		Self {
+			out: std::io::stdout(),
+		}
+	}
+}
+
+impl Ndjson<std::fs::File> {
+	pub fn file(path: &Path) -> Result<Self> {
+		let out = OpenOptions::new()
+			.create(true)
+			.append(true)
+			.open(path)
+			.with_context(|| format!("failed to open {}", path.display()))?;
+		Ok(Self { out })
+	}
+}
+
+impl<W: Write> Sink for Ndjson<W> {
+	fn emit(&mut self, event: &Event) -> Result<()> {
+		let json = match event {
+			Event::Sample { stats, .. } => JsonEvent::Sample(PowerOutput::new(stats)),
+			&Event::Limit {
+				port,
+				current_ma,
+				limit_ma,
+			} => JsonEvent::Limit {
+				port,
+				current_ma,
+				limit_ma,
+			},
+			Event::Alert(message) => JsonEvent::Alert {
+				message: message.clone(),
+			},
+			Event::Link { up, message } => JsonEvent::Link {
+				up: *up,
+				message: message.clone(),
+			},
+			Event::LinkStats(stats) => JsonEvent::LinkStats(LinkOutput::new(stats)),
+		};
+		serde_json::to_writer(&mut self.out, &json)?;
+		writeln!(self.out)?;
+		self.out.flush()?;
+		Ok(())
+	}
+}
+
+// Writes RFC 3164 datagrams to /dev/log, which both syslog daemons and journald read.
+#[cfg(unix)]
+pub struct Syslog {
+	socket: std::os::unix::net::UnixDatagram,
+}
+
+#[cfg(unix)]
+impl Syslog {
+	const FACILITY_USER: u8 = 1;
+	const SEVERITY_WARNING: u8 = 4;
+	const SEVERITY_INFO: u8 = 6;
+
+	pub fn connect() -> Result<Self> {
+		let socket = std::os::unix::net::UnixDatagram::unbound()?;
+		socket
+			.connect("/dev/log")
+			.context("failed to connect to /dev/log")?;
+		Ok(Self { socket })
+	}
+}
+
+#[cfg(unix)]
+impl Sink for Syslog {
+	fn emit(&mut self, event: &Event) -> Result<()> {
+		let severity = match event {
+			Event::Sample { .. } | Event::Link { up: true, .. } => Self::SEVERITY_INFO,
+			_ => Self::SEVERITY_WARNING,
+		};
+		let pri = Self::FACILITY_USER * 8 + severity;
+		let line = format!("<{pri}>hm-hub[{}]: {}", std::process::id(), event.message());
+		self.socket.send(line.as_bytes())?;
+		Ok(())
+	}
+}
@@ -0,0 +1,127 @@
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::cli::{parse_size, parse_temperature, ImageArgs};
+use crate::types::{config_field_names, DeviceConfig};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct State {
+	#[serde(default)]
+	pub config: BTreeMap<String, toml::Value>,
+	pub images: Option<ImageState>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ImageState {
+	pub dir: PathBuf,
+	#[serde(default)]
+	pub no_crop: bool,
+	pub temperature: Option<toml::Value>,
+	#[serde(default)]
+	pub interpolate: u8,
+	pub similarity_threshold: Option<f64>,
+	pub size: Option<String>,
+}
+
+impl State {
+	pub fn load(path: &Path) -> Result<Self> {
+		let text = std::fs::read_to_string(path)
+			.with_context(|| format!("failed to read {}", path.display()))?;
+		let json = path
+			.extension()
+			.is_some_and(|e| e.eq_ignore_ascii_case("json"));
+		let mut state: State = if json {
+			serde_json::from_str(&text).map_err(anyhow::Error::from)
+		} else {
+			toml::from_str(&text).map_err(anyhow::Error::from)
+		}
+		.with_context(|| format!("invalid state file {}", path.display()))?;
+		// Image directories are relative to the state file, not the working directory.
+		if let Some(images) = &mut state.images {
+			if images.dir.is_relative() {
+				let base = path.parent().unwrap_or(Path::new("."));
+				images.dir = base.join(&images.dir);
+			}
+		}
+		Ok(state)
+	}
+
+	// Returns the desired config and the fields that differ from `current`.
+	pub fn config_changes(&self, current: &DeviceConfig) -> Result<(DeviceConfig, Vec<String>)> {
+		let mut desired = current.clone();
+		let mut changes = Vec::new();
+		for (field, value) in &self.config {
+			let value = value_string(value).with_context(|| format!("config.{field}"))?;
+			let mut single = current.clone();
+			single
+				.set_field(field, &value)
+				.with_context(|| format!("config.{field}"))?;
+			if single.to_bytes() != current.to_bytes() {
+				desired.set_field(field, &value)?;
+				changes.push(format!("{field} = {value}"));
+			}
+		}
+		Ok((desired, changes))
+	}
+}
+
+// The [config] table of a state file, as `config export` writes it.
+#[derive(Serialize)]
+pub struct ConfigFile {
+	pub config: BTreeMap<&'static str, u32>,
+}
+
+impl ConfigFile {
+	// A rotation code outside the four known ones is left out rather than failing.
+	pub fn new(config: &DeviceConfig) -> Self {
+		Self {
+			config: config_field_names()
+				.filter_map(|name| Some((name, config.get_field(name).ok()?)))
+				.collect(),
+		}
+	}
+
+	pub fn save(&self, path: &Path) -> Result<()> {
+		let text = if path
+			.extension()
+			.is_some_and(|e| e.eq_ignore_ascii_case("json"))
+		{
+			serde_json::to_string_pretty(self)?
+		} else {
+			toml::to_string(self)?
+		};
+		std::fs::write(path, text).with_context(|| format!("failed to write {}", path.display()))
+	}
+}
+
+impl ImageState {
+	pub fn image_args(&self) -> Result<ImageArgs> {
+		let temperature = match &self.temperature {
+			Some(value) => Some(parse_temperature(&value_string(value)?).map_err(|e| anyhow!(e))?),
+			None => None,
+		};
+		Ok(ImageArgs {
+			no_crop: self.no_crop,
+			temperature,
+			interpolate: self.interpolate,
+			similarity_threshold: self.similarity_threshold,
+			size: match &self.size {
+				Some(size) => Some(parse_size(size).map_err(|e| anyhow!(e))?),
+				None => None,
+			},
+		})
+	}
+}
+
+fn value_string(value: &toml::Value) -> Result<String> {
+	Ok(match value {
+		toml::Value::String(s) => s.clone(),
+		toml::Value::Integer(i) => i.to_string(),
+		toml::Value::Boolean(b) => u8::from(*b).to_string(),
+		other => bail!("unsupported value {other}"),
+	})
+}
@@ -0,0 +1,494 @@
+// Packet traces (`--record file.hmtrace`) are NDJSON, one line per 256-byte packet
+// in wire order, so they stay greppable and diffable. `Replayer` walks a trace
+// through the same parsers and reassembly the device code uses and describes what
+// happened, flagging anything a well-behaved device or host wouldn't do.
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::consts::*;
+use crate::protocol::commands::{parse_flash_error, parse_handshake, parse_log, parse_power_stats};
+use crate::transport::Transport;
+use crate::types::{ChunkedReceiver, DeviceConfig, Packet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+	Tx,
+	Rx,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TraceEntry {
+	pub ts_ms: u64,
+	pub dir: Direction,
+	pub packet: String,
+}
+
+impl TraceEntry {
+	pub fn bytes(&self) -> Option<[u8; PACKET_SIZE]> {
+		let hex = self.packet.as_bytes();
+		if hex.len() != PACKET_SIZE * 2 {
+			return None;
+		}
+		let mut buf = [0u8; PACKET_SIZE];
+		for (i, pair) in hex.chunks(2).enumerate() {
+			let digits = std::str::from_utf8(pair).ok()?;
+			buf[i] = u8::from_str_radix(digits, 16).ok()?;
+		}
+		Some(buf)
+	}
+}
+
+pub fn read_trace(path: &Path) -> Result<Vec<TraceEntry>> {
+	let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+	let mut entries = Vec::new();
+	for (n, line) in BufReader::new(file).lines().enumerate() {
+		let line = line?;
+		if line.trim().is_empty() {
+			continue;
+		}
+		let entry = serde_json::from_str(&line)
+			.with_context(|| format!("{}:{}: invalid trace entry", path.display(), n + 1))?;
+		entries.push(entry);
+	}
+	Ok(entries)
+}
+
+// Wraps a transport and appends every complete packet sent or received to a trace
+// file. Appending lets several connections in one run share a file.
+pub struct Recorder<T> {
+	inner: T,
+	out: BufWriter<File>,
+	tx: Vec<u8>,
+	rx: Vec<u8>,
+}
+
+impl<T: Transport> Recorder<T> {
+	pub fn new(inner: T, path: &Path) -> io::Result<Self> {
+		let file = OpenOptions::new().create(true).append(true).open(path)?;
+		Ok(Self {
+			inner,
+			out: BufWriter::new(file),
+			tx: Vec::new(),
+			rx: Vec::new(),
+		})
+	}
+
+	fn log(&mut self, dir: Direction) -> io::Result<()> {
+		let pending = match dir {
+			Direction::Tx => &mut self.tx,
+			Direction::Rx => &mut self.rx,
+		};
+		while pending.len() >= PACKET_SIZE {
+			let packet: String = pending
+				.drain(..PACKET_SIZE)
+				.map(|b| format!("{b:02x}"))
+				.collect();
+			let entry = TraceEntry {
+				ts_ms: chrono::Utc::now().timestamp_millis() as u64,
+				dir,
+				packet,
+			};
+			serde_json::to_writer(&mut self.out, &entry)?;
+			self.out.write_all(b"\n")?;
+		}
+		// Flushed per packet so a crash or hang still leaves the trace up to that point.
+		self.out.flush()
+	}
+}
+
+impl<T: Transport> Read for Recorder<T> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let n = self.inner.read(buf)?;
+		self.rx.extend_from_slice(&buf[..n]);
+		self.log(Direction::Rx)?;
+		Ok(n)
+	}
+}
+
+impl<T: Transport> Write for Recorder<T> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let n = self.inner.write(buf)?;
+		self.tx.extend_from_slice(&buf[..n]);
+		self.log(Direction::Tx)?;
+		Ok(n)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.inner.flush()
+	}
+}
+
+impl<T: Transport> Transport for Recorder<T> {
+	fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+		self.inner.set_timeout(timeout)
+	}
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum RunKind {
+	Upload,
+	Readback,
+}
+
+// Flash transfers are thousands of packets; they are summarised as one line per run.
+struct Run {
+	kind: RunKind,
+	started_ms: u64,
+	packets: usize,
+	first: usize,
+	end: usize,
+}
+
+pub struct Replayer {
+	start_ms: Option<u64>,
+	config: ChunkedReceiver,
+	upload_total: Option<usize>,
+	upload_next: usize,
+	last_request: Option<(u32, u16)>,
+	run: Option<Run>,
+	pub tx: usize,
+	pub rx: usize,
+	pub anomalies: usize,
+}
+
+impl Default for Replayer {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Replayer {
+	pub fn new() -> Self {
+		Self {
+			start_ms: None,
+			config: ChunkedReceiver::new(),
+			upload_total: None,
+			upload_next: 0,
+			last_request: None,
+			run: None,
+			tx: 0,
+			rx: 0,
+			anomalies: 0,
+		}
+	}
+
+	// Returns the lines describing this entry; anomalies start with "!".
+	pub fn step(&mut self, entry: &TraceEntry) -> Vec<String> {
+		let start = *self.start_ms.get_or_insert(entry.ts_ms);
+		let ts = entry.ts_ms.saturating_sub(start);
+		match entry.dir {
+			Direction::Tx => self.tx += 1,
+			Direction::Rx => self.rx += 1,
+		}
+		let mut lines = Vec::new();
+		let arrow = match entry.dir {
+			Direction::Tx => "->",
+			Direction::Rx => "<-",
+		};
+
+		let pkt = match entry.bytes().map(Packet::from_bytes) {
+			Some(Ok(pkt)) => pkt,
+			Some(Err(e)) => {
+				self.anomaly(&mut lines, ts, arrow, &e.to_string());
+				return lines;
+			}
+			None => {
+				self.anomaly(&mut lines, ts, arrow, "malformed packet hex");
+				return lines;
+			}
+		};
+
+		match (entry.dir, pkt.cmd_id()) {
+			(Direction::Tx, CMD_HANDSHAKE) => note(&mut lines, ts, arrow, "handshake"),
+			(Direction::Rx, CMD_HANDSHAKE) => match parse_handshake(&pkt) {
+				Ok(info) => note(
+					&mut lines,
+					ts,
+					arrow,
+					&format!(
+						"handshake: hw {:#010x}, firmware {}, flash {} bytes",
+						info.hw_id,
+						info.fw_version_string(),
+						info.flash_size
+					),
+				),
+				Err(e) => self.anomaly(&mut lines, ts, arrow, &e.to_string()),
+			},
+			(dir, CMD_CONFIG) => self.config_packet(&mut lines, ts, arrow, dir, &pkt),
+			(dir, CMD_FLASH) => self.flash_packet(&mut lines, ts, arrow, dir, &pkt),
+			(Direction::Rx, CMD_POWER) => match parse_power_stats(&pkt) {
+				Ok(s) => note(
+					&mut lines,
+					ts,
+					arrow,
+					&format!(
+						"power: {}mV, ports {}/{}/{} mA",
+						s.bus_voltage, s.current_port1, s.current_port2, s.current_port3
+					),
+				),
+				Err(e) => self.anomaly(&mut lines, ts, arrow, &e.to_string()),
+			},
+			(Direction::Rx, CMD_LOG) => match parse_log(&pkt) {
+				Ok(msg) => note(&mut lines, ts, arrow, &format!("device log: {msg}")),
+				Err(e) => self.anomaly(&mut lines, ts, arrow, &e.to_string()),
+			},
+			(Direction::Tx, CMD_FACTORY_RESET) => note(&mut lines, ts, arrow, "factory reset"),
+			(_, cmd) => self.anomaly(&mut lines, ts, arrow, &format!("unexpected command {cmd}")),
+		}
+		lines
+	}
+
+	// Closes any transfer still in progress at the end of the trace.
+	pub fn finish(&mut self) -> Vec<String> {
+		let mut lines = Vec::new();
+		self.flush_run(&mut lines);
+		if let Some(total) = self.upload_total {
+			lines.push(format!(
+				"! upload incomplete: {} of {total} bytes requested",
+				self.upload_next
+			));
+			self.anomalies += 1;
+		}
+		let missing = self.config.missing();
+		if !missing.is_empty() {
+			lines.push(format!(
+				"! config reply incomplete, missing chunk(s) {missing:?}"
+			));
+			self.anomalies += 1;
+		}
+		lines
+	}
+
+	fn anomaly(&mut self, lines: &mut Vec<String>, ts: u64, arrow: &str, msg: &str) {
+		self.anomalies += 1;
+		lines.push(format!("! {}  {arrow} {msg}", stamp(ts)));
+	}
+
+	fn config_packet(
+		&mut self,
+		lines: &mut Vec<String>,
+		ts: u64,
+		arrow: &str,
+		dir: Direction,
+		pkt: &Packet,
+	) {
+		let p = pkt.payload();
+		match (dir, p[0]) {
+			(Direction::Tx, 1) => note(lines, ts, arrow, "config read request"),
+			(Direction::Tx, 2) => {
+				let total = u16::from_le_bytes([p[1], p[2]]);
+				let index = u16::from_le_bytes([p[3], p[4]]);
+				note(
+					lines,
+					ts,
+					arrow,
+					&format!("config write chunk {}/{total}", index + 1),
+				);
+			}
+			(Direction::Rx, 1) => note(lines, ts, arrow, &format!("config ack, status {}", p[1])),
+			(Direction::Rx, 2) => match self.config.feed(&p[1..]) {
+				Ok(Some(data)) => match DeviceConfig::from_bytes(&data) {
+					Ok(config) => note(
+						lines,
+						ts,
+						arrow,
+						&format!(
+							"config reply complete: brightness {}, page {}, interval {}s",
+							config.screen_brightness,
+							config.memory_page,
+							config.image_switch_interval
+						),
+					),
+					Err(e) => self.anomaly(lines, ts, arrow, &e.to_string()),
+				},
+				Ok(None) => note(lines, ts, arrow, &format!("config reply chunk {}", p[1])),
+				Err(e) => self.anomaly(lines, ts, arrow, &e.to_string()),
+			},
+			(_, sub) => self.anomaly(
+				lines,
+				ts,
+				arrow,
+				&format!("unknown config sub-command {sub}"),
+			),
+		}
+	}
+
+	fn flash_packet(
+		&mut self,
+		lines: &mut Vec<String>,
+		ts: u64,
+		arrow: &str,
+		dir: Direction,
+		pkt: &Packet,
+	) {
+		let p = pkt.payload();
+		let offset = u32::from_le_bytes([p[1], p[2], p[3], p[4]]);
+		match (dir, p[0]) {
+			(Direction::Tx, 1) => {
+				self.flush_run(lines);
+				self.upload_total = Some(offset as usize);
+				self.upload_next = 0;
+				note(
+					lines,
+					ts,
+					arrow,
+					&format!("flash upload start, {offset} bytes"),
+				);
+			}
+			(Direction::Tx, 2) => {
+				let length = u16::from_le_bytes([p[5], p[6]]);
+				if self.last_request.take() != Some((offset, length)) {
+					self.anomaly(
+						lines,
+						ts,
+						arrow,
+						&format!("flash data for {offset}+{length} without a matching request"),
+					);
+				}
+				self.extend_run(RunKind::Upload, ts, offset as usize, length as usize);
+				if self
+					.upload_total
+					.is_some_and(|total| offset as usize + length as usize >= total)
+				{
+					self.upload_total = None;
+					self.flush_run(lines);
+				}
+			}
+			(Direction::Tx, 3) => {
+				self.flush_run(lines);
+				let length = u32::from_le_bytes([p[5], p[6], p[7], p[8]]);
+				let what = if length == 0 {
+					"whole flash".to_string()
+				} else {
+					format!("{offset}+{length}")
+				};
+				note(lines, ts, arrow, &format!("flash readback request, {what}"));
+			}
+			(Direction::Rx, _) if parse_flash_error(pkt).is_some() => {
+				let err = parse_flash_error(pkt).unwrap_or_default();
+				self.upload_total = None;
+				self.flush_run(lines);
+				self.anomaly(lines, ts, arrow, &err);
+			}
+			(Direction::Rx, 1) => {
+				let status = match p[1] {
+					0 => "accepted",
+					1 => "ready",
+					2 => "erasing",
+					other => {
+						let msg = format!("unknown flash status {other:#04x}");
+						self.anomaly(lines, ts, arrow, &msg);
+						return;
+					}
+				};
+				note(lines, ts, arrow, &format!("flash status: {status}"));
+			}
+			(Direction::Rx, 2) => {
+				let length = u16::from_le_bytes([p[5], p[6]]);
+				if self.upload_total.is_none() {
+					self.anomaly(lines, ts, arrow, "flash data request outside an upload");
+				} else if offset as usize != self.upload_next {
+					self.anomaly(
+						lines,
+						ts,
+						arrow,
+						&format!(
+							"device requested offset {offset}, expected {}",
+							self.upload_next
+						),
+					);
+				}
+				if length as usize > PAYLOAD_SIZE - 7 {
+					self.anomaly(
+						lines,
+						ts,
+						arrow,
+						&format!("device requested {length} bytes, more than a packet holds"),
+					);
+				}
+				self.upload_next = offset as usize + length as usize;
+				self.last_request = Some((offset, length));
+			}
+			(Direction::Rx, 3) => {
+				let length = u16::from_le_bytes([p[5], p[6]]) as usize;
+				if self
+					.run
+					.as_ref()
+					.is_some_and(|run| run.kind == RunKind::Readback && run.end != offset as usize)
+				{
+					let expected = self.run.as_ref().map_or(0, |run| run.end);
+					self.anomaly(
+						lines,
+						ts,
+						arrow,
+						&format!("readback jumped to offset {offset}, expected {expected}"),
+					);
+				}
+				self.extend_run(RunKind::Readback, ts, offset as usize, length);
+			}
+			(Direction::Rx, 4) => {
+				self.upload_total = None;
+				self.flush_run(lines);
+				note(lines, ts, arrow, "flash transfer done");
+			}
+			(_, sub) => self.anomaly(
+				lines,
+				ts,
+				arrow,
+				&format!("unknown flash sub-command {sub}"),
+			),
+		}
+	}
+
+	fn extend_run(&mut self, kind: RunKind, ts: u64, offset: usize, length: usize) {
+		match &mut self.run {
+			Some(run) if run.kind == kind => {
+				run.packets += 1;
+				run.end = run.end.max(offset + length);
+			}
+			_ => {
+				self.run = Some(Run {
+					kind,
+					started_ms: ts,
+					packets: 1,
+					first: offset,
+					end: offset + length,
+				});
+			}
+		}
+	}
+
+	fn flush_run(&mut self, lines: &mut Vec<String>) {
+		let Some(run) = self.run.take() else {
+			return;
+		};
+		let arrow = match run.kind {
+			RunKind::Upload => "->",
+			RunKind::Readback => "<-",
+		};
+		let label = match run.kind {
+			RunKind::Upload => "flash data sent",
+			RunKind::Readback => "flash data received",
+		};
+		lines.push(format!(
+			"  {}  {arrow} {label}: {} packet(s), bytes {}..{}",
+			stamp(run.started_ms),
+			run.packets,
+			run.first,
+			run.end
+		));
+	}
+}
+
+fn note(lines: &mut Vec<String>, ts: u64, arrow: &str, msg: &str) {
+	lines.push(format!("  {}  {arrow} {msg}", stamp(ts)));
+}
+
+fn stamp(ts_ms: u64) -> String {
+	format!("{:>9.3}s", ts_ms as f64 / 1000.0)
+}
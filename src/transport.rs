@@ -0,0 +1,80 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use crate::types::Packet;
+
+// Byte stream to the device. Reads must fail with TimedOut (or WouldBlock) once the
+// timeout set by `set_timeout` passes without data.
+pub trait Transport: Read + Write + Send {
+	fn set_timeout(&mut self, timeout: Duration) -> io::Result<()>;
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+	fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+		(**self).set_timeout(timeout)
+	}
+}
+
+impl Transport for Box<dyn serialport::SerialPort> {
+	fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+		serialport::SerialPort::set_timeout(&mut **self, timeout).map_err(io::Error::from)
+	}
+}
+
+// For a serial port shared over the network (e.g. ser2net in raw mode).
+impl Transport for TcpStream {
+	fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+		// A zero read timeout is rejected by the OS.
+		self.set_read_timeout(Some(timeout.max(Duration::from_millis(1))))
+	}
+}
+
+// Replays queued replies and records everything written, for exercising the protocol
+// code without hardware.
+#[derive(Default)]
+pub struct MockTransport {
+	replies: VecDeque<u8>,
+	written: Vec<u8>,
+}
+
+impl MockTransport {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn push_reply(&mut self, pkt: &Packet) {
+		self.replies.extend(pkt.buf);
+	}
+
+	pub fn written(&self) -> &[u8] {
+		&self.written
+	}
+}
+
+impl Read for MockTransport {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		if self.replies.is_empty() {
+			return Err(io::Error::new(io::ErrorKind::TimedOut, "no reply queued"));
+		}
+		self.replies.read(buf)
+	}
+}
+
+impl Write for MockTransport {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.written.extend_from_slice(buf);
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+impl Transport for MockTransport {
+	fn set_timeout(&mut self, _timeout: Duration) -> io::Result<()> {
+		Ok(())
+	}
+}
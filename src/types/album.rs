@@ -1,4 +0,0 @@
-pub struct Album {
-	pub frames: Vec<Vec<u8>>,
-	pub delay_ms: u16,
-}
@@ -1,56 +1,111 @@
-use anyhow::{bail, Result};
+use crate::error::{HubError, Result};
 
+// Chunks are placed by index, so reordered packets still assemble correctly and
+// duplicates are only accepted if they repeat the same data.
 pub struct ChunkedReceiver {
-	buffer: Vec<u8>,
-	total_chunks: usize,
+	chunks: Vec<Option<Vec<u8>>>,
 	received: usize,
-	initialized: bool,
+}
+
+impl Default for ChunkedReceiver {
+	fn default() -> Self {
+		Self::new()
+	}
 }
 
 impl ChunkedReceiver {
 	pub fn new() -> Self {
 		Self {
-			buffer: Vec::new(),
-			total_chunks: 0,
+			chunks: Vec::new(),
 			received: 0,
-			initialized: false,
 		}
 	}
 
 	pub fn feed(&mut self, payload: &[u8]) -> Result<Option<Vec<u8>>> {
-		let _chunk_idx = payload[0] as usize;
+		if payload.len() < 4 {
+			return Err(HubError::ProtocolViolation(format!(
+				"chunk of {} bytes is too short for its header",
+				payload.len()
+			)));
+		}
+		let chunk_idx = payload[0] as usize;
 		let total = payload[1] as usize;
 		let chunk_len = u16::from_le_bytes([payload[2], payload[3]]) as usize;
+		if 4 + chunk_len > payload.len() {
+			return Err(HubError::ProtocolViolation(format!(
+				"chunk {chunk_idx} claims {chunk_len} bytes, more than a packet holds"
+			)));
+		}
 		let chunk_data = &payload[4..4 + chunk_len];
 
-		if !self.initialized {
-			self.total_chunks = total;
-			self.buffer = Vec::new();
-			self.initialized = true;
+		if total == 0 {
+			return Err(HubError::ProtocolViolation(format!(
+				"chunk {chunk_idx} announces zero total chunks"
+			)));
+		}
+		if self.chunks.is_empty() {
+			self.chunks = vec![None; total];
+		} else if self.chunks.len() != total {
+			return Err(HubError::ProtocolViolation(format!(
+				"chunk {chunk_idx} announces {total} total chunks, earlier chunks announced {}",
+				self.chunks.len()
+			)));
+		}
+		if chunk_idx >= total {
+			return Err(HubError::ProtocolViolation(format!(
+				"chunk index {chunk_idx} out of range (total {total})"
+			)));
 		}
 
-		self.buffer.extend_from_slice(chunk_data);
-		self.received += 1;
-
-		if self.received >= self.total_chunks {
-			if self.buffer.len() < 4 {
-				bail!("chunked data too small");
+		match &self.chunks[chunk_idx] {
+			Some(existing) if existing == chunk_data => return Ok(None),
+			Some(_) => {
+				return Err(HubError::ProtocolViolation(format!(
+					"chunk {chunk_idx} received twice with different data"
+				)))
 			}
-			let data_len = self.buffer.len() - 4;
-			let expected_crc = crc32fast::hash(&self.buffer[..data_len]);
-			let actual_crc = u32::from_le_bytes([
-				self.buffer[data_len],
-				self.buffer[data_len + 1],
-				self.buffer[data_len + 2],
-				self.buffer[data_len + 3],
-			]);
-			if expected_crc != actual_crc {
-				bail!("chunked CRC mismatch: expected {expected_crc:#x}, got {actual_crc:#x}");
+			None => {
+				self.chunks[chunk_idx] = Some(chunk_data.to_vec());
+				self.received += 1;
 			}
-			self.buffer.truncate(data_len);
-			Ok(Some(std::mem::take(&mut self.buffer)))
-		} else {
-			Ok(None)
 		}
+
+		if self.received < total {
+			return Ok(None);
+		}
+
+		let mut buffer: Vec<u8> = self.chunks.drain(..).flatten().flatten().collect();
+		self.received = 0;
+		if buffer.len() < 4 {
+			return Err(HubError::ProtocolViolation(
+				"chunked data too small".to_string(),
+			));
+		}
+		let data_len = buffer.len() - 4;
+		let expected_crc = crc32fast::hash(&buffer[..data_len]);
+		let actual_crc = u32::from_le_bytes([
+			buffer[data_len],
+			buffer[data_len + 1],
+			buffer[data_len + 2],
+			buffer[data_len + 3],
+		]);
+		if expected_crc != actual_crc {
+			return Err(HubError::CrcMismatch {
+				what: "chunked data",
+				expected: expected_crc,
+				actual: actual_crc,
+			});
+		}
+		buffer.truncate(data_len);
+		Ok(Some(buffer))
+	}
+
+	pub fn missing(&self) -> Vec<usize> {
+		self.chunks
+			.iter()
+			.enumerate()
+			.filter(|(_, chunk)| chunk.is_none())
+			.map(|(i, _)| i)
+			.collect()
 	}
 }
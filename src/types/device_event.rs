@@ -0,0 +1,24 @@
+use crate::consts::{CMD_LOG, CMD_POWER};
+use crate::protocol::commands::{parse_log, parse_power_stats};
+use crate::types::{Packet, PowerStats};
+
+// Something the device sent on its own rather than in reply to a request.
+#[derive(Debug)]
+pub enum DeviceEvent {
+	Power(PowerStats),
+	Log(String),
+	// Any other packet, including power or log packets that don't parse, so new
+	// firmware messages can be inspected without a library change.
+	Unknown(Box<Packet>),
+}
+
+impl DeviceEvent {
+	pub fn from_packet(packet: Packet) -> Self {
+		let parsed = match packet.cmd_id() {
+			CMD_POWER => parse_power_stats(&packet).map(DeviceEvent::Power).ok(),
+			CMD_LOG => parse_log(&packet).map(DeviceEvent::Log).ok(),
+			_ => None,
+		};
+		parsed.unwrap_or_else(|| DeviceEvent::Unknown(Box::new(packet)))
+	}
+}
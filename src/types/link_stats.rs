@@ -0,0 +1,59 @@
+use std::fmt;
+
+use crate::error::{HubError, Result};
+use crate::types::Packet;
+
+// Counters for one connection, to tell a marginal cable or port from a healthy one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LinkStats {
+	pub packets_sent: u64,
+	pub packets_received: u64,
+	pub crc_errors: u64,
+	// Waits that ended without a whole packet.
+	pub timeouts: u64,
+	// Handshakes and config requests sent again, and whole operations retried.
+	pub retransmissions: u64,
+	// Times the input was drained or the port reopened to get back in step.
+	pub resyncs: u64,
+}
+
+impl LinkStats {
+	pub fn errors(&self) -> u64 {
+		self.crc_errors + self.timeouts
+	}
+
+	// Share of packet reads that failed; 0 before anything was read.
+	pub fn error_rate(&self) -> f64 {
+		let reads = self.packets_received + self.errors();
+		if reads == 0 {
+			0.0
+		} else {
+			self.errors() as f64 / reads as f64
+		}
+	}
+
+	pub(crate) fn record_read(&mut self, result: &Result<Packet>) {
+		match result {
+			Ok(_) => self.packets_received += 1,
+			Err(HubError::CrcMismatch { .. }) => self.crc_errors += 1,
+			Err(HubError::Timeout(_)) => self.timeouts += 1,
+			Err(_) => {}
+		}
+	}
+}
+
+impl fmt::Display for LinkStats {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"{} sent, {} received, {} CRC error(s), {} timeout(s), {} retransmission(s), {} resync(s)",
+			self.packets_sent,
+			self.packets_received,
+			self.crc_errors,
+			self.timeouts,
+			self.retransmissions,
+			self.resyncs
+		)
+	}
+}
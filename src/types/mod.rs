@@ -1,15 +1,13 @@
-mod album;
 mod chunked_receiver;
-mod config;
-mod device_info;
-mod frame_header;
+mod device_event;
+mod link_stats;
 mod packet;
 mod power_stats;
 
-pub use album::Album;
 pub use chunked_receiver::ChunkedReceiver;
-pub use config::DeviceConfig;
-pub use device_info::{max_frames, DeviceInfo};
-pub use frame_header::FrameHeader;
+pub use device_event::DeviceEvent;
+pub use hm_hub_core::types::*;
+pub use link_stats::LinkStats;
+pub(crate) use packet::trace_packet;
 pub use packet::Packet;
 pub use power_stats::PowerStats;
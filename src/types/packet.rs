@@ -1,7 +1,10 @@
-use anyhow::{bail, Result};
+use std::fmt;
+use std::io::Write;
 
 use crate::consts::{CRC_OFFSET, PACKET_SIZE, PAYLOAD_SIZE};
+use crate::error::{HubError, Result};
 
+#[derive(Debug)]
 pub struct Packet {
 	pub buf: [u8; PACKET_SIZE],
 }
@@ -9,14 +12,24 @@ pub struct Packet {
 impl Packet {
 	pub fn new(cmd_id: u8, payload: &[u8]) -> Result<Self> {
 		if payload.len() > PAYLOAD_SIZE {
-			bail!("payload too large: {} > {}", payload.len(), PAYLOAD_SIZE);
+			return Err(HubError::ProtocolViolation(format!(
+				"payload too large: {} > {}",
+				payload.len(),
+				PAYLOAD_SIZE
+			)));
 		}
 		let mut buf = [0u8; PACKET_SIZE];
 		buf[0] = cmd_id;
 		buf[1..1 + payload.len()].copy_from_slice(payload);
-		let crc = crc32fast::hash(&buf[..CRC_OFFSET]);
-		buf[CRC_OFFSET..].copy_from_slice(&crc.to_le_bytes());
-		Ok(Packet { buf })
+		let mut pkt = Packet { buf };
+		pkt.seal();
+		Ok(pkt)
+	}
+
+	// Recomputes the CRC after the buffer was edited in place.
+	pub fn seal(&mut self) {
+		let crc = crc32fast::hash(&self.buf[..CRC_OFFSET]);
+		self.buf[CRC_OFFSET..].copy_from_slice(&crc.to_le_bytes());
 	}
 
 	pub fn from_bytes(buf: [u8; PACKET_SIZE]) -> Result<Self> {
@@ -28,7 +41,11 @@ impl Packet {
 			buf[CRC_OFFSET + 3],
 		]);
 		if expected != actual {
-			bail!("CRC mismatch: expected {expected:#x}, got {actual:#x}");
+			return Err(HubError::CrcMismatch {
+				what: "packet",
+				expected,
+				actual,
+			});
 		}
 		Ok(Packet { buf })
 	}
@@ -41,9 +58,27 @@ impl Packet {
 		&self.buf[1..CRC_OFFSET]
 	}
 
-	pub fn send(&self, port: &mut dyn serialport::SerialPort) -> Result<()> {
+	pub fn send<W: Write + ?Sized>(&self, port: &mut W) -> Result<()> {
+		trace_packet("tx", &self.buf);
 		port.write_all(&self.buf)?;
 		port.flush()?;
 		Ok(())
 	}
 }
+
+// Raw dumps of every packet, at trace level under their own target so they can be
+// enabled separately. The hex is only formatted when someone is listening.
+pub(crate) fn trace_packet(direction: &str, buf: &[u8]) {
+	tracing::trace!(target: "hm_hub::packet", "{direction} {}", Hex(buf));
+}
+
+struct Hex<'a>(&'a [u8]);
+
+impl fmt::Display for Hex<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for b in self.0 {
+			write!(f, "{b:02x}")?;
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,132 @@
+use anyhow::Result;
+use chrono::{Local, NaiveTime, Timelike};
+use hm_hub::HubError;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// How often `idle` wakes up to check for Ctrl+C.
+const STOP_POLL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone)]
+pub enum Health {
+	Unknown,
+	Up(Duration),
+	Down,
+}
+
+impl Health {
+	pub fn describe(&self) -> String {
+		match self {
+			Health::Unknown => "Link: ?".to_string(),
+			Health::Up(rtt) => format!("Link: ok {}ms", rtt.as_millis()),
+			Health::Down => "Link: DOWN".to_string(),
+		}
+	}
+}
+
+// Pings the device every `interval` while a daemon is otherwise idle, so a dead link
+// shows up within seconds rather than at the next scheduled action.
+pub struct Watchdog {
+	interval: Duration,
+	last_check: Option<Instant>,
+	health: Health,
+	stop: Option<Arc<AtomicBool>>,
+}
+
+impl Watchdog {
+	pub fn new(interval: Duration) -> Self {
+		Self {
+			interval,
+			last_check: None,
+			health: Health::Unknown,
+			stop: None,
+		}
+	}
+
+	// `idle` gives up with `HubError::Cancelled` once the flag is set.
+	pub fn with_stop(mut self, stop: Arc<AtomicBool>) -> Self {
+		self.stop = Some(stop);
+		self
+	}
+
+	pub fn health(&self) -> &Health {
+		&self.health
+	}
+
+	pub fn is_due(&self) -> bool {
+		!self.interval.is_zero() && self.last_check.is_none_or(|t| t.elapsed() >= self.interval)
+	}
+
+	// Runs the ping if one is due and returns a message when the link changes state.
+	pub fn check(&mut self, ping: impl FnOnce() -> Result<Duration>) -> Option<String> {
+		if !self.is_due() {
+			return None;
+		}
+		self.last_check = Some(Instant::now());
+		let was_down = matches!(self.health, Health::Down);
+		match ping() {
+			Ok(rtt) => {
+				self.health = Health::Up(rtt);
+				was_down.then(|| format!("device responding again ({}ms)", rtt.as_millis()))
+			}
+			Err(e) => {
+				self.health = Health::Down;
+				(!was_down).then(|| format!("device not responding: {e:#}"))
+			}
+		}
+	}
+
+	// Sleeps for `duration`, waking up to ping whenever the interval elapses.
+	pub fn idle(
+		&mut self,
+		duration: Duration,
+		mut ping: impl FnMut() -> Result<Duration>,
+	) -> Result<()> {
+		let until = Instant::now() + duration;
+		loop {
+			if self.stop.as_ref().is_some_and(|s| s.load(Ordering::SeqCst)) {
+				return Err(HubError::Cancelled.into());
+			}
+			if let Some(message) = self.check(&mut ping) {
+				tracing::warn!("[watchdog] {message}");
+			}
+			let now = Instant::now();
+			if now >= until {
+				return Ok(());
+			}
+			let mut step = if self.interval.is_zero() {
+				until - now
+			} else {
+				self.interval.min(until - now)
+			};
+			if self.stop.is_some() {
+				step = step.min(STOP_POLL);
+			}
+			std::thread::sleep(step);
+		}
+	}
+	// Like `idle`, but wakes on the next local wall-clock multiple of `interval`, so
+	// 60s lands exactly on the minute. The wait is worked out from the clock on every
+	// call, so sleep overruns and time spent between calls never add up to drift.
+	pub fn idle_aligned(
+		&mut self,
+		interval: Duration,
+		ping: impl FnMut() -> Result<Duration>,
+	) -> Result<()> {
+		self.idle(until_boundary(Local::now().time(), interval), ping)
+	}
+}
+
+// Boundaries restart at midnight, for intervals that don't divide a day evenly.
+fn until_boundary(now: NaiveTime, interval: Duration) -> Duration {
+	const DAY: u128 = 86_400 * 1_000_000_000;
+	let step = interval.as_nanos();
+	if step == 0 {
+		return Duration::ZERO;
+	}
+	let elapsed =
+		u128::from(now.num_seconds_from_midnight()) * 1_000_000_000 + u128::from(now.nanosecond());
+	let next = ((elapsed / step + 1) * step).min(DAY);
+	Duration::from_nanos(next.saturating_sub(elapsed) as u64)
+}
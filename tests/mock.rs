@@ -0,0 +1,560 @@
+use std::io::{self, Read, Write};
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use hm_hub::consts::FLASH_HEADER_AREA;
+use hm_hub::consts::FRAME_PIXEL_SIZE;
+use hm_hub::flash::{
+	append_albums, build_flash_buffer, parse_albums, read_headers, read_metadata, read_sync_hash,
+	reorder_headers, replace_album, replace_extent, select_albums, set_album_delay,
+	stamp_sync_hash, used_length, write_metadata,
+};
+use hm_hub::mock::{MockDevice, MOCK_CONFIG, MOCK_HW_ID};
+use hm_hub::progress::ProgressMode;
+use hm_hub::protocol::{
+	fill_flash_data_response, parse_flash_error, parse_readback_data, Packet, CMD_FLASH,
+	PACKET_SIZE, PAYLOAD_SIZE,
+};
+use hm_hub::types::{
+	config_field_names, AlbumMeta, Button, ChunkedReceiver, FlashMetadata, Rotation,
+};
+use hm_hub::{Album, Device, DeviceEvent, HubError, Transport};
+
+const FLASH_SIZE: u32 = 1024 * 1024;
+
+fn open() -> Device<MockDevice> {
+	let mut dev = Device::builder("mock:")
+		.timeout(Duration::from_millis(200))
+		.open_with(MockDevice::new(FLASH_SIZE))
+		.unwrap();
+	dev.progress = ProgressMode::Hidden;
+	dev
+}
+
+fn album(seed: u8, frames: usize) -> Album {
+	Album {
+		frames: (0..frames)
+			.map(|f| {
+				(0..FRAME_PIXEL_SIZE)
+					.map(|i| (i as u8).wrapping_mul(seed).wrapping_add(f as u8))
+					.collect()
+			})
+			.collect(),
+		delay_ms: 100,
+	}
+}
+
+#[test]
+fn handshake_reports_mock_identity() {
+	let dev = open();
+	assert_eq!(dev.info.hw_id, MOCK_HW_ID);
+	assert_eq!(dev.info.flash_size, FLASH_SIZE);
+}
+
+#[test]
+fn upload_then_read_back() {
+	let mut dev = open();
+	let albums = vec![album(3, 2), album(7, 1)];
+	let flash = build_flash_buffer(&albums, FLASH_SIZE).unwrap();
+	dev.upload_flash(&flash).unwrap();
+
+	let data = dev.read_flash().unwrap();
+	assert_eq!(&data[..flash.len()], &flash[..]);
+	let parsed = parse_albums(&data).unwrap();
+	assert_eq!(parsed.len(), 2);
+	assert_eq!(parsed[0].frames, albums[0].frames);
+	assert_eq!(parsed[1].frames, albums[1].frames);
+
+	let range = dev.read_flash_range(5000, 1000).unwrap();
+	assert_eq!(range, &flash[5000..6000]);
+
+	let header = dev.read_flash_range(0, FLASH_HEADER_AREA).unwrap();
+	let headers = read_headers(&header).unwrap();
+	let frames: Vec<_> = headers.iter().map(|h| h.frame_count).collect();
+	assert_eq!(frames, [2, 1]);
+}
+
+#[test]
+fn second_upload_erases_the_first() {
+	let mut dev = open();
+	let big = build_flash_buffer(&[album(3, 3)], FLASH_SIZE).unwrap();
+	dev.upload_flash(&big).unwrap();
+	let small = build_flash_buffer(&[album(5, 1)], FLASH_SIZE).unwrap();
+	dev.upload_flash(&small).unwrap();
+
+	let parsed = parse_albums(&dev.read_flash().unwrap()).unwrap();
+	assert_eq!(parsed.len(), 1);
+	assert_eq!(parsed[0].frames.len(), 1);
+}
+
+#[test]
+fn header_metadata_survives_upload() {
+	let mut dev = open();
+	let albums = vec![album(3, 1), album(7, 1)];
+	let mut flash = build_flash_buffer(&albums, FLASH_SIZE).unwrap();
+	let meta = FlashMetadata {
+		sync_hash: None,
+		albums: vec![
+			AlbumMeta {
+				label: Some("clouds".to_string()),
+				source_hash: Some(0xDEAD_BEEF),
+				..Default::default()
+			},
+			AlbumMeta {
+				flags: 0x80,
+				..Default::default()
+			},
+		],
+	};
+	write_metadata(&mut flash, &meta).unwrap();
+	let hash = stamp_sync_hash(&mut flash).unwrap();
+	dev.upload_flash(&flash).unwrap();
+
+	let header = dev.read_flash_range(0, FLASH_HEADER_AREA).unwrap();
+	assert_eq!(read_sync_hash(&header), Some(hash));
+	let read = read_metadata(&header).unwrap().unwrap();
+	assert_eq!(read.albums, meta.albums);
+	assert_eq!(parse_albums(&dev.read_flash().unwrap()).unwrap().len(), 2);
+
+	let mut corrupt = header.clone();
+	corrupt[FLASH_HEADER_AREA - 20] ^= 1;
+	assert!(read_metadata(&corrupt).is_err());
+	assert_eq!(read_sync_hash(&corrupt), None);
+}
+
+#[test]
+fn select_and_append_repack_data_and_metadata() {
+	let mut dev = open();
+	let albums = vec![album(1, 2), album(2, 1), album(3, 3)];
+	let mut flash = build_flash_buffer(&albums, FLASH_SIZE).unwrap();
+	let labels = ["one", "two", "three"].map(|l| AlbumMeta {
+		label: Some(l.to_string()),
+		..Default::default()
+	});
+	let meta = FlashMetadata {
+		sync_hash: None,
+		albums: labels.to_vec(),
+	};
+	write_metadata(&mut flash, &meta).unwrap();
+	stamp_sync_hash(&mut flash).unwrap();
+	dev.upload_flash(&flash).unwrap();
+
+	let header = dev.read_flash_range(0, FLASH_HEADER_AREA).unwrap();
+	let data = dev
+		.read_flash_range(0, used_length(&header).unwrap())
+		.unwrap();
+	let repacked = select_albums(&data, &[0, 2], FLASH_SIZE).unwrap();
+	assert!(repacked.len() < flash.len());
+	dev.upload_flash(&repacked).unwrap();
+
+	let parsed = parse_albums(&dev.read_flash().unwrap()).unwrap();
+	assert_eq!(parsed.len(), 2);
+	assert_eq!(parsed[0].frames, albums[0].frames);
+	assert_eq!(parsed[1].frames, albums[2].frames);
+	let header = dev.read_flash_range(0, FLASH_HEADER_AREA).unwrap();
+	let read = read_metadata(&header).unwrap().unwrap();
+	assert_eq!(read.albums, [labels[0].clone(), labels[2].clone()]);
+	assert_eq!(read.sync_hash, None);
+
+	let appended = append_albums(&data, vec![album(4, 1)], FLASH_SIZE).unwrap();
+	let parsed = parse_albums(&appended).unwrap();
+	assert_eq!(parsed.len(), 4);
+	assert_eq!(parsed[3].frames, album(4, 1).frames);
+	let read = read_metadata(&appended).unwrap().unwrap();
+	assert_eq!(read.albums, labels);
+
+	assert!(select_albums(&data, &[1, 1], FLASH_SIZE).is_err());
+	assert!(select_albums(&data, &[3], FLASH_SIZE).is_err());
+}
+
+#[test]
+fn header_only_rewrites_keep_the_data_in_place() {
+	let mut dev = open();
+	let albums = vec![album(1, 1), album(2, 2), album(3, 1)];
+	let mut flash = build_flash_buffer(&albums, FLASH_SIZE).unwrap();
+	let meta = FlashMetadata {
+		sync_hash: None,
+		albums: ["a", "b", "c"]
+			.map(|l| AlbumMeta {
+				label: Some(l.to_string()),
+				..Default::default()
+			})
+			.to_vec(),
+	};
+	write_metadata(&mut flash, &meta).unwrap();
+	dev.upload_flash(&flash).unwrap();
+
+	let header = dev.read_flash_range(0, FLASH_HEADER_AREA).unwrap();
+	let reordered = reorder_headers(&header, &[2, 0, 1]).unwrap();
+	assert_eq!(reordered.len(), FLASH_HEADER_AREA);
+	dev.upload_flash(&reordered).unwrap();
+
+	let data = dev.read_flash().unwrap();
+	assert_eq!(
+		data[FLASH_HEADER_AREA..flash.len()],
+		flash[FLASH_HEADER_AREA..]
+	);
+	let parsed = parse_albums(&data).unwrap();
+	assert_eq!(parsed[0].frames, albums[2].frames);
+	assert_eq!(parsed[1].frames, albums[0].frames);
+	assert_eq!(parsed[2].frames, albums[1].frames);
+	let labels: Vec<_> = read_metadata(&data)
+		.unwrap()
+		.unwrap()
+		.albums
+		.into_iter()
+		.map(|a| a.label.unwrap())
+		.collect();
+	assert_eq!(labels, ["c", "a", "b"]);
+
+	let header = dev.read_flash_range(0, FLASH_HEADER_AREA).unwrap();
+	dev.upload_flash(&set_album_delay(&header, 2, 40).unwrap())
+		.unwrap();
+	let data = dev.read_flash().unwrap();
+	assert_eq!(parse_albums(&data).unwrap()[2].delay_ms, 40);
+	assert_eq!(parse_albums(&data).unwrap()[2].frames, albums[1].frames);
+	assert!(set_album_delay(&header, 3, 40).is_err());
+
+	assert!(reorder_headers(&header, &[0, 1]).is_err());
+	assert!(reorder_headers(&header, &[0, 0, 1]).is_err());
+}
+
+// Header-only rewrites assume the erase stops at the uploaded length; on firmware
+// that erases the whole chip they lose every frame.
+#[test]
+fn header_only_rewrites_lose_data_under_full_erase() {
+	let mut mock = MockDevice::new(FLASH_SIZE);
+	mock.full_erase = true;
+	let mut dev = Device::builder("mock:")
+		.timeout(Duration::from_millis(200))
+		.open_with(mock)
+		.unwrap();
+	dev.progress = ProgressMode::Hidden;
+	let albums = vec![album(1, 1), album(2, 1)];
+	dev.upload_flash(&build_flash_buffer(&albums, FLASH_SIZE).unwrap())
+		.unwrap();
+
+	let header = dev.read_flash_range(0, FLASH_HEADER_AREA).unwrap();
+	dev.upload_flash(&reorder_headers(&header, &[1, 0]).unwrap())
+		.unwrap();
+	let data = dev.read_flash().unwrap();
+	assert!(data[FLASH_HEADER_AREA..].iter().all(|&b| b == 0xFF));
+	assert_ne!(parse_albums(&data).unwrap()[0].frames, albums[1].frames);
+}
+
+#[test]
+fn replacing_an_album_repacks_unless_asked_to_write_in_place() {
+	let mut dev = open();
+	let albums = vec![album(1, 1), album(2, 3), album(3, 2)];
+	let flash = build_flash_buffer(&albums, FLASH_SIZE).unwrap();
+	dev.upload_flash(&flash).unwrap();
+	let header = dev.read_flash_range(0, FLASH_HEADER_AREA).unwrap();
+
+	// By default everything is packed again, even when the new album would fit.
+	let extent = replace_extent(&header, 1, 2, false).unwrap();
+	assert_eq!(extent, used_length(&header).unwrap());
+	let data = dev.read_flash_range(0, extent).unwrap();
+	let repacked = replace_album(&data, 1, album(4, 2), FLASH_SIZE, false).unwrap();
+	dev.upload_flash(&repacked).unwrap();
+	let parsed = parse_albums(&dev.read_flash().unwrap()).unwrap();
+	assert_eq!(parsed[1].frames, album(4, 2).frames);
+	assert_eq!(parsed[2].frames, albums[2].frames);
+
+	// In place, one frame fits where two were, so only the start of flash is rewritten.
+	let header = dev.read_flash_range(0, FLASH_HEADER_AREA).unwrap();
+	let extent = replace_extent(&header, 1, 1, true).unwrap();
+	assert!(extent < used_length(&header).unwrap());
+	let data = dev.read_flash_range(0, extent).unwrap();
+	let smaller = replace_album(&data, 1, album(5, 1), FLASH_SIZE, true).unwrap();
+	assert!(smaller.len() < repacked.len());
+	dev.upload_flash(&smaller).unwrap();
+	let parsed = parse_albums(&dev.read_flash().unwrap()).unwrap();
+	assert_eq!(parsed[0].frames, albums[0].frames);
+	assert_eq!(parsed[1].frames, album(5, 1).frames);
+	assert_eq!(parsed[2].frames, albums[2].frames);
+
+	// Four frames don't fit, so even in place everything is packed again.
+	let header = dev.read_flash_range(0, FLASH_HEADER_AREA).unwrap();
+	let extent = replace_extent(&header, 0, 4, true).unwrap();
+	assert_eq!(extent, used_length(&header).unwrap());
+	let data = dev.read_flash_range(0, extent).unwrap();
+	let bigger = replace_album(&data, 0, album(6, 4), FLASH_SIZE, true).unwrap();
+	dev.upload_flash(&bigger).unwrap();
+	let parsed = parse_albums(&dev.read_flash().unwrap()).unwrap();
+	assert_eq!(parsed[0].frames, album(6, 4).frames);
+	assert_eq!(parsed[1].frames, album(5, 1).frames);
+	assert_eq!(parsed[2].frames, albums[2].frames);
+
+	assert!(replace_extent(&header, 3, 1, false).is_err());
+}
+
+#[test]
+fn data_requests_past_the_end_read_as_erased() {
+	let mut pkt = Packet {
+		buf: [0; PACKET_SIZE],
+	};
+	let data = vec![0x11; 100];
+	assert!(fill_flash_data_response(&mut pkt, 5000, 16, &data));
+	assert!(pkt.payload()[7..23].iter().all(|&b| b == 0xFF));
+
+	assert!(fill_flash_data_response(&mut pkt, 90, 16, &data));
+	assert_eq!(pkt.payload()[7..17], [0x11; 10]);
+	assert!(pkt.payload()[17..23].iter().all(|&b| b == 0xFF));
+	assert!(!fill_flash_data_response(&mut pkt, 0, 16, &data));
+}
+
+#[test]
+fn only_known_flash_failures_abort() {
+	let status = |sub: u8, code: u8| {
+		let mut payload = [0u8; PAYLOAD_SIZE];
+		payload[..2].copy_from_slice(&[sub, code]);
+		parse_flash_error(&Packet::new(CMD_FLASH, &payload).unwrap())
+	};
+	assert!(status(1, 3).unwrap().contains("upload size"));
+	assert!(status(1, 4).unwrap().contains("out of sequence"));
+	assert_eq!(status(1, 2), None);
+	assert_eq!(status(1, 0x05), None);
+	assert_eq!(status(9, 0), None);
+}
+
+#[test]
+fn readback_lengths_beyond_the_payload_are_rejected() {
+	let mut payload = [0u8; PAYLOAD_SIZE];
+	payload[0] = 3;
+	payload[1..5].copy_from_slice(&4096u32.to_le_bytes());
+	payload[5..7].copy_from_slice(&1000u16.to_le_bytes());
+	let pkt = Packet::new(CMD_FLASH, &payload).unwrap();
+	let err = parse_readback_data(&pkt).unwrap_err();
+	assert!(matches!(err, HubError::ProtocolViolation(_)), "{err}");
+
+	payload[5..7].copy_from_slice(&((PAYLOAD_SIZE - 7) as u16).to_le_bytes());
+	let pkt = Packet::new(CMD_FLASH, &payload).unwrap();
+	let (offset, data) = parse_readback_data(&pkt).unwrap();
+	assert_eq!((offset, data.len()), (4096, PAYLOAD_SIZE - 7));
+}
+
+#[test]
+fn short_chunk_payloads_are_rejected() {
+	let mut receiver = ChunkedReceiver::new();
+	for len in 0..4 {
+		assert!(receiver.feed(&[1; 3][..len.min(3)]).is_err());
+	}
+}
+
+#[test]
+fn config_round_trip_and_factory_reset() {
+	let mut dev = open();
+	let mut config = dev.read_config().unwrap();
+	config.brightness(7).unwrap().rotation(Rotation::Deg90);
+	config.set_field("single_click", "2").unwrap();
+	dev.write_config(&config).unwrap();
+
+	let config = dev.read_config().unwrap();
+	assert_eq!(config.screen_brightness, 7);
+	assert_eq!(config.screen_rotation(), Some(Rotation::Deg90));
+	assert_eq!(config.button_action(Button::SingleClick), 2);
+	assert_eq!(config.get_field("brightness").unwrap(), 7);
+	assert_eq!(config.get_field("rotation").unwrap(), 90);
+	assert_eq!(config.get_field("single_click").unwrap(), 2);
+	assert!(config.get_field("nonsense").is_err());
+	let mut named = config.clone();
+	named.set_field("double_click", "3").unwrap();
+	assert_eq!(named.button_action(Button::DoubleClick), 3);
+	assert!(named.set_field("tilt", "next_image").is_err());
+	// What config export writes, config import must accept unchanged.
+	let mut copy = config.clone();
+	for name in config_field_names() {
+		let value = config.get_field(name).unwrap();
+		copy.set_field(name, &value.to_string()).unwrap();
+	}
+	assert_eq!(copy.to_bytes(), config.to_bytes());
+
+	dev.factory_reset().unwrap();
+	let config = dev.read_config().unwrap();
+	assert_eq!(config.screen_brightness, 20);
+	assert_eq!(config.screen_rotation(), Some(Rotation::Deg0));
+	assert_eq!(config.to_bytes(), MOCK_CONFIG);
+}
+
+// config reset: let the firmware restore its defaults, then put the images back.
+#[test]
+fn factory_reset_then_reupload_keeps_images() {
+	let mut dev = open();
+	let flash = build_flash_buffer(&[album(3, 2)], FLASH_SIZE).unwrap();
+	dev.upload_flash(&flash).unwrap();
+	let mut config = dev.read_config().unwrap();
+	config.brightness(5).unwrap();
+	dev.write_config(&config).unwrap();
+
+	let header = dev.read_flash_range(0, FLASH_HEADER_AREA).unwrap();
+	let images = dev
+		.read_flash_range(0, used_length(&header).unwrap())
+		.unwrap();
+	dev.factory_reset().unwrap();
+	dev.reconnect().unwrap();
+	assert_eq!(dev.read_config().unwrap().to_bytes(), MOCK_CONFIG);
+	dev.upload_flash(&images).unwrap();
+	let albums = parse_albums(&dev.read_flash().unwrap()).unwrap();
+	assert_eq!(albums.len(), 1);
+	assert_eq!(albums[0].frames.len(), 2);
+}
+
+#[test]
+fn oversized_upload_is_refused() {
+	let mut dev = open();
+	let err = dev
+		.upload_flash(&vec![0; FLASH_SIZE as usize + 1])
+		.unwrap_err();
+	assert!(matches!(err, hm_hub::HubError::FlashFailed(_)), "{err}");
+}
+
+#[test]
+fn power_samples_are_plausible() {
+	let mut dev = open();
+	let stats = dev.read_power().unwrap();
+	assert!((4750..=5250).contains(&stats.bus_voltage));
+	assert!(stats.current_port2 >= stats.current_port1);
+}
+
+#[test]
+fn event_stream_yields_power_reports() {
+	let mut dev = Device::builder("mock:")
+		.timeout(Duration::from_secs(2))
+		.open_with(MockDevice::new(FLASH_SIZE))
+		.unwrap();
+	let events: Vec<_> = dev.events().take(2).collect::<Result<_, _>>().unwrap();
+	assert_eq!(events.len(), 2);
+	assert!(events
+		.iter()
+		.all(|e| matches!(e, DeviceEvent::Power(stats) if stats.bus_voltage > 0)));
+}
+
+#[test]
+fn state_file_persists_between_opens() {
+	let path = std::env::temp_dir().join(format!("hm-hub-mock-{}.bin", std::process::id()));
+	let _ = std::fs::remove_file(&path);
+	let reopen = || {
+		let mut dev = Device::builder("mock:")
+			.timeout(Duration::from_millis(200))
+			.open_with(MockDevice::open(&path).unwrap())
+			.unwrap();
+		dev.progress = ProgressMode::Hidden;
+		dev
+	};
+
+	let flash = build_flash_buffer(&[album(9, 2)], FLASH_SIZE).unwrap();
+	{
+		let mut dev = reopen();
+		dev.upload_flash(&flash).unwrap();
+		let mut config = dev.read_config().unwrap();
+		config.set_field("brightness", "3").unwrap();
+		dev.write_config(&config).unwrap();
+	}
+
+	let mut dev = reopen();
+	assert_eq!(dev.read_config().unwrap().screen_brightness, 3);
+	let data = dev.read_flash().unwrap();
+	assert_eq!(&data[..flash.len()], &flash[..]);
+	std::fs::remove_file(&path).unwrap();
+}
+
+// Fails a single write partway through, like a brief USB dropout.
+struct Flaky {
+	inner: MockDevice,
+	writes: usize,
+	fail_at: usize,
+}
+
+impl Read for Flaky {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		self.inner.read(buf)
+	}
+}
+
+impl Write for Flaky {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.writes += 1;
+		if self.writes == self.fail_at {
+			return Err(io::Error::new(io::ErrorKind::BrokenPipe, "link dropped"));
+		}
+		self.inner.write(buf)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.inner.flush()
+	}
+}
+
+impl Transport for Flaky {
+	fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+		self.inner.set_timeout(timeout)
+	}
+}
+
+#[test]
+fn op_retries_recover_from_a_transient_failure() {
+	let flash = build_flash_buffer(&[album(4, 1)], FLASH_SIZE).unwrap();
+	let flaky = || Flaky {
+		inner: MockDevice::new(FLASH_SIZE),
+		writes: 0,
+		fail_at: 50,
+	};
+
+	let mut dev = Device::builder("mock:")
+		.timeout(Duration::from_millis(200))
+		.open_with(flaky())
+		.unwrap();
+	dev.progress = ProgressMode::Hidden;
+	assert!(dev.upload_flash(&flash).is_err());
+
+	let mut dev = Device::builder("mock:")
+		.timeout(Duration::from_millis(200))
+		.op_retries(2, Duration::from_millis(10))
+		.open_with(flaky())
+		.unwrap();
+	dev.progress = ProgressMode::Hidden;
+	dev.upload_flash(&flash).unwrap();
+	let data = dev.read_flash().unwrap();
+	assert_eq!(&data[..flash.len()], &flash[..]);
+
+	let stats = dev.link_stats();
+	assert_eq!(stats.retransmissions, 1);
+	assert_eq!(stats.resyncs, 1);
+	assert_eq!(stats.crc_errors, 0);
+	assert!(stats.packets_sent > 50);
+	assert!(stats.packets_received > 0);
+}
+
+#[test]
+fn cancellation_stops_operations_and_monitor() {
+	let cancel = Arc::new(AtomicBool::new(true));
+	let mut dev = Device::builder("mock:")
+		.timeout(Duration::from_secs(2))
+		.cancel(cancel.clone())
+		.open_with(MockDevice::new(FLASH_SIZE))
+		.unwrap();
+	dev.progress = ProgressMode::Hidden;
+	let flash = build_flash_buffer(&[album(2, 1)], FLASH_SIZE).unwrap();
+	assert!(matches!(dev.upload_flash(&flash), Err(HubError::Cancelled)));
+	assert!(matches!(dev.read_flash(), Err(HubError::Cancelled)));
+
+	cancel.store(false, Ordering::SeqCst);
+	let setter = {
+		let cancel = cancel.clone();
+		std::thread::spawn(move || {
+			std::thread::sleep(Duration::from_millis(300));
+			cancel.store(true, Ordering::SeqCst);
+		})
+	};
+	// Never breaks on its own; only the cancellation ends it.
+	dev.monitor(|_, _| Ok::<_, HubError>(ControlFlow::Continue(())))
+		.unwrap();
+	setter.join().unwrap();
+
+	cancel.store(false, Ordering::SeqCst);
+	dev.upload_flash(&flash).unwrap();
+	let data = dev.read_flash().unwrap();
+	assert_eq!(&data[..flash.len()], &flash[..]);
+}